@@ -1,26 +1,29 @@
 extern crate png;
 extern crate rayon;
-use rayon::prelude::*;
 
 pub mod raytrace;
 pub mod scenes;
 
-use raytrace::camera::Camera;
+use raytrace::camera::{Camera, PerspectiveCamera};
 use raytrace::Vec3;
 use std::time::{Duration, Instant};
 use std::path::Path;
-use std::fs::File;
-use std::io::BufWriter;
-use raytrace::renderer::{Renderer, Background, GradientBackground};
+use std::ops::Range;
+use raytrace::renderer::{Renderer, Background, GradientBackground, Integrator, write_png};
+use raytrace::animation::{AnimatedCamera, render_sequence};
 use raytrace::bvh::BVHNode;
 use std::sync::Arc;
 use raytrace::util::degrees_to_radians;
 use raytrace::hittable::Hittable;
+use raytrace::light::Light;
+
+/// The camera, objects and background that make up a scene.
+type Scene = (Box<dyn Camera>, Arc<dyn Hittable>, Option<Box<dyn Background>>);
 
 fn main() {
     let width = 800;
     let height = 800;
-    let default_camera = Camera::new(
+    let default_camera = PerspectiveCamera::new(
         Vec3::new(13.0, 2.0, 3.0),
         Vec3::new(0.0, 0.0, 0.0),
         Vec3::new(0.0, 1.0, 0.0),
@@ -31,67 +34,79 @@ fn main() {
     );
 
     const SCENE_NAME: &str = "rtiw_final";
-
-    let (camera, objects, background): (Camera, Arc<dyn Hittable>, Option<Box<dyn Background>>) = match SCENE_NAME {
-        "rtiw_final" => (default_camera, BVHNode::construct(scenes::rtiw_final::generate()), Some(Box::new(GradientBackground{}))),
-        "image_texture" => (default_camera, BVHNode::construct(scenes::image_texture::generate()), Some(Box::new(GradientBackground{}))),
-        "box" => (default_camera, BVHNode::construct(scenes::box_scene::generate()), Some(Box::new(GradientBackground{}))),
-        "marble" => (default_camera, BVHNode::construct(scenes::marble::generate()), Some(Box::new(GradientBackground{}))),
-        "emitting_scene" => (scenes::emitting_scene::camera(width, height), BVHNode::construct(scenes::emitting_scene::generate()), None),
-        "cornell_box" => (scenes::cornell_box::camera(width as f64 /  height as f64), BVHNode::construct(scenes::cornell_box::generate()), None),
-        "cornell_smoke" => (scenes::cornell_smoke::camera(width as f64 /  height as f64), BVHNode::construct(scenes::cornell_smoke::generate()), None),
+    // frames rendered for animated scenes
+    const FRAMES: Range<u32> = 0..48;
+    const FRAMES_PER_SECOND: f64 = 24.0;
+
+    let (camera, objects, background): Scene = match SCENE_NAME {
+        "rtiw_final" => (Box::new(default_camera), BVHNode::construct(scenes::rtiw_final::generate()), Some(Box::new(GradientBackground{}))),
+        "image_texture" => (Box::new(default_camera), BVHNode::construct(scenes::image_texture::generate()), Some(Box::new(GradientBackground{}))),
+        "box" => (Box::new(default_camera), BVHNode::construct(scenes::box_scene::generate()), Some(Box::new(GradientBackground{}))),
+        "marble" => (Box::new(default_camera), BVHNode::construct(scenes::marble::generate()), Some(Box::new(GradientBackground{}))),
+        "emitting_scene" => (Box::new(scenes::emitting_scene::camera(width, height)), BVHNode::construct(scenes::emitting_scene::generate()), None),
+        "cornell_box" => (Box::new(scenes::cornell_box::camera(width as f64 /  height as f64)), BVHNode::construct(scenes::cornell_box::generate()), None),
+        "cornell_smoke" => (Box::new(scenes::cornell_smoke::camera(width as f64 /  height as f64)), BVHNode::construct(scenes::cornell_smoke::generate()), None),
+        "motion_blur" => (Box::new(scenes::motion_blur::camera(width as f64 /  height as f64)), BVHNode::construct(scenes::motion_blur::generate()), Some(Box::new(GradientBackground{}))),
+        "turntable" => (Box::new(default_camera), BVHNode::construct(scenes::turntable::generate()), Some(Box::new(GradientBackground{}))),
+        "environment" => (Box::new(default_camera), BVHNode::construct(scenes::environment::generate()), Some(Box::new(scenes::environment::background()))),
+        "sky" => (Box::new(default_camera), BVHNode::construct(scenes::environment::generate()), Some(Box::new(scenes::sky::background()))),
+        "lights" => (Box::new(scenes::lights::camera(width as f64 /  height as f64)), BVHNode::construct(scenes::lights::generate()), None),
+        "foggy_lights" => (Box::new(scenes::lights::camera(width as f64 /  height as f64)), Arc::new(scenes::lights::fog(BVHNode::construct(scenes::lights::generate()))), None),
+        "microfacet" => (Box::new(scenes::microfacet::camera(width as f64 /  height as f64)), BVHNode::construct(scenes::microfacet::generate()), Some(Box::new(scenes::sky::background()))),
+        "principled" => (Box::new(scenes::microfacet::camera(width as f64 /  height as f64)), BVHNode::construct(scenes::principled::generate()), Some(Box::new(scenes::sky::background()))),
+        "layered" => (Box::new(scenes::microfacet::camera(width as f64 /  height as f64)), BVHNode::construct(scenes::layered::generate()), Some(Box::new(scenes::sky::background()))),
+        "dispersion" => (Box::new(scenes::dispersion::camera(width as f64 /  height as f64)), BVHNode::construct(scenes::dispersion::generate()), None),
+        "caustics" => (Box::new(scenes::caustics::camera(width as f64 /  height as f64)), BVHNode::construct(scenes::caustics::generate()), None),
+        "subsurface" => (Box::new(scenes::microfacet::camera(width as f64 /  height as f64)), BVHNode::construct(scenes::subsurface::generate()), Some(Box::new(scenes::sky::background()))),
+        "shapes" => (Box::new(scenes::microfacet::camera(width as f64 /  height as f64)), BVHNode::construct(scenes::shapes::generate()), Some(Box::new(scenes::sky::background()))),
+        "clouds" => (Box::new(scenes::clouds::camera(width as f64 /  height as f64)), BVHNode::construct(scenes::clouds::generate()), Some(Box::new(scenes::sky::background()))),
         _ => panic!("Invalid scene name")
-    };  
-
-    let renderer = Renderer::new(width, height, 100, camera, objects, background);
-
-    let mut data = vec![0u8; (width * height * 4) as usize];
+    };
+
+    let animation: Option<AnimatedCamera> = match SCENE_NAME {
+        "turntable" => Some(scenes::turntable::animation(width as f64 / height as f64, FRAMES.end as f64 / FRAMES_PER_SECOND)),
+        _ => None
+    };
+
+    let lights: Vec<Arc<dyn Light>> = match SCENE_NAME {
+        "lights" => scenes::lights::lights(),
+        "foggy_lights" => scenes::lights::foggy_lights(),
+        "cornell_box" => scenes::cornell_box::lights(),
+        "caustics" => scenes::caustics::lights(),
+        _ => Vec::new()
+    };
+
+    let mut renderer = Renderer::new(width, height, 100, camera, objects, background);
+    renderer.set_lights(lights);
+    // dispersion only shows up when tracing wavelengths
+    renderer.set_spectral(SCENE_NAME == "dispersion");
+    // the enclosed box is mostly lit indirectly, which is easier to find from the light
+    if SCENE_NAME == "cornell_box" {
+        renderer.set_integrator(Integrator::Bidirectional);
+    }
+    // caustics are found by tracing photons from the light
+    if SCENE_NAME == "caustics" {
+        renderer.set_integrator(Integrator::PhotonMapping(scenes::caustics::photon_settings()));
+    }
+    // the smoke scene has no lights to sample, so paths learn where the light is instead
+    renderer.set_path_guiding(SCENE_NAME == "cornell_smoke");
 
     let start = Instant::now();
 
-    // for y in 0..height {
-    //     for x in 0..width {
-    //         let mut color = renderer.color_at(x as f64, y as f64);
-    //         color.x = f64::sqrt(color.x);
-    //         color.y = f64::sqrt(color.y);
-    //         color.z = f64::sqrt(color.z);
-    //         let r = (255.99 * color.x) as u8;
-    //         let g = (255.99 * color.y) as u8;
-    //         let b = (255.99 * color.z) as u8;
-    //         let i = ((x + (height - y - 1) * width) * 4) as usize;
-    //         data[i] = r;
-    //         data[i + 1] = g;
-    //         data[i + 2] = b;
-    //         data[i + 3] = 255;
-    //     }
-    // }
-
-    data
-        .par_chunks_mut((4 * width) as usize)
-        .rev()
-        .enumerate()
-        .for_each(|(j, row)| {
-            for (i, rgba) in row.chunks_mut(4).enumerate() {
-                let color = renderer.color_at(i as f64, j as f64);
-                let mut iter = rgba.iter_mut();
-                *iter.next().unwrap() = (255.99 * color.x().sqrt()) as u8;
-                *iter.next().unwrap() = (255.99 * color.y().sqrt()) as u8;
-                *iter.next().unwrap() = (255.99 * color.z().sqrt()) as u8;
-                *iter.next().unwrap() = 255;
-            }
-        });
-
-    // write image to png
-    let path = Path::new("./image.png");
-    let file = File::create(path).unwrap();
-    let ref mut w = BufWriter::new(file);
+    if let Some(animation) = animation {
+        let timings = render_sequence(&mut renderer, &animation, FRAMES, FRAMES_PER_SECOND, 0.5, "./frame_").unwrap();
+        for (frame, timing) in FRAMES.zip(timings) {
+            eprintln!("rendered frame {} in {:.2}s", frame, timing.as_secs_f64());
+        }
+        let elapsed = start.elapsed();
+        eprintln!("rendered {} frames of {} x {} with {} samples in {}", FRAMES.len(), width, height, renderer.samples, human_readable_time(elapsed));
+        return;
+    }
 
-    let mut encoder = png::Encoder::new(w, width, height); // Width is 2 pixels and height is 1.
-    encoder.set_color(png::ColorType::RGBA);
-    encoder.set_depth(png::BitDepth::Eight);
-    let mut writer = encoder.write_header().unwrap();
+    let data = renderer.render();
 
-    writer.write_image_data(&data).unwrap(); // Save
+    // write image to png
+    write_png(Path::new("./image.png"), width, height, &data).unwrap();
 
     let elapsed = start.elapsed();
     eprintln!("rendered {} x {} with {} samples in {}", width, height, renderer.samples, human_readable_time(elapsed));
@@ -0,0 +1,367 @@
+//! Keyframe animation of cameras and objects, and rendering of numbered image sequences.
+
+use super::Vec3;
+use super::aabb::AABB;
+use super::ray::Ray;
+use super::quaternion::Quaternion;
+use super::camera::PerspectiveCamera;
+use super::hittable::{HitRecord, Hittable};
+use super::renderer::{Renderer, write_png};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A value that can be interpolated between two keyframes.
+pub trait Interpolate: Copy {
+    fn interpolate(a: Self, b: Self, amount: f64) -> Self;
+}
+
+impl Interpolate for f64 {
+    fn interpolate(a: f64, b: f64, amount: f64) -> f64 {
+        a + (b - a) * amount
+    }
+}
+
+impl Interpolate for Vec3 {
+    fn interpolate(a: Vec3, b: Vec3, amount: f64) -> Vec3 {
+        a + (b - a) * amount
+    }
+}
+
+impl Interpolate for Quaternion {
+    /// Rotations are slerped, which falls back to nlerp when the keys are close together.
+    fn interpolate(a: Quaternion, b: Quaternion, amount: f64) -> Quaternion {
+        a.slerp(b, amount)
+    }
+}
+
+/// How a keyframe moves towards the next keyframe.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Easing {
+    /// Hold the value until the next keyframe.
+    Step,
+    Linear,
+    /// Ease in and out of the keyframes using smoothstep.
+    Smooth
+}
+
+impl Easing {
+    fn apply(self, amount: f64) -> f64 {
+        match self {
+            Easing::Step => 0.0,
+            Easing::Linear => amount,
+            Easing::Smooth => amount * amount * (3.0 - 2.0 * amount)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Keyframe<T> {
+    pub time: f64,
+    pub value: T,
+    /// the easing used from this keyframe to the next one
+    pub easing: Easing
+}
+
+/// A list of keyframes sorted by time, sampling before the first or after the last keyframe holds their value.
+#[derive(Clone, Debug)]
+pub struct Track<T> {
+    keyframes: Vec<Keyframe<T>>
+}
+
+impl<T: Interpolate> Track<T> {
+    pub fn new() -> Self {
+        Track{keyframes: Vec::new()}
+    }
+
+    /// Creates a track that has the same value at all times.
+    pub fn constant(value: T) -> Self {
+        let mut track = Self::new();
+        track.add(0.0, value);
+        track
+    }
+
+    /// Adds a linearly interpolated keyframe.
+    pub fn add(&mut self, time: f64, value: T) -> &mut Self {
+        self.add_with_easing(time, value, Easing::Linear)
+    }
+
+    /// Adds a keyframe, replacing any keyframe already at that time.
+    pub fn add_with_easing(&mut self, time: f64, value: T, easing: Easing) -> &mut Self {
+        let keyframe = Keyframe{time, value, easing};
+        match self.keyframes.binary_search_by(|k| k.time.total_cmp(&time)) {
+            Ok(i) => self.keyframes[i] = keyframe,
+            Err(i) => self.keyframes.insert(i, keyframe)
+        }
+        self
+    }
+
+    /// Builder version of add.
+    pub fn key(mut self, time: f64, value: T) -> Self {
+        self.add(time, value);
+        self
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe<T>] {
+        &self.keyframes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keyframes.is_empty()
+    }
+
+    /// The value at time, or None if the track has no keyframes.
+    pub fn sample(&self, time: f64) -> Option<T> {
+        let first = self.keyframes.first()?;
+        if time <= first.time {
+            return Some(first.value);
+        }
+        let last = self.keyframes.last().unwrap();
+        if time >= last.time {
+            return Some(last.value);
+        }
+        // index of the first keyframe after time, which the checks above only leave missing for NaN times
+        let i = match self.keyframes.iter().position(|k| k.time > time) {
+            Some(i) if i > 0 => i,
+            _ => return Some(last.value)
+        };
+        let (a, b) = (&self.keyframes[i - 1], &self.keyframes[i]);
+        let amount = a.easing.apply((time - a.time) / (b.time - a.time));
+        Some(T::interpolate(a.value, b.value, amount))
+    }
+
+    /// The value at time, or default if the track has no keyframes.
+    pub fn sample_or(&self, time: f64, default: T) -> T {
+        self.sample(time).unwrap_or(default)
+    }
+}
+
+impl<T: Interpolate> Default for Track<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A camera whose position, target and field of view are keyframed. Tracks without keyframes leave the camera at
+/// the origin looking down the negative z axis with a 90 degree field of view focused 10 away.
+pub struct AnimatedCamera {
+    pub look_from: Track<Vec3>,
+    pub look_at: Track<Vec3>,
+    /// vertical field of view in radians
+    pub vfov: Track<f64>,
+    pub focus_distance: Track<f64>,
+    pub vup: Vec3,
+    pub aspect: f64,
+    pub aperture: f64
+}
+
+impl AnimatedCamera {
+    pub fn new(look_from: Track<Vec3>, look_at: Track<Vec3>, vfov: Track<f64>, aspect: f64) -> Self {
+        AnimatedCamera{
+            look_from,
+            look_at,
+            vfov,
+            focus_distance: Track::constant(10.0),
+            vup: Vec3::unit_y(),
+            aspect,
+            aperture: 0.0
+        }
+    }
+
+    /// Returns the camera for a frame starting at time, with the shutter open for shutter seconds.
+    pub fn camera_at(&self, time: f64, shutter: f64) -> PerspectiveCamera {
+        let mut camera = PerspectiveCamera::new(
+            self.look_from.sample_or(time, Vec3::zero()),
+            self.look_at.sample_or(time, Vec3::new(0.0, 0.0, -1.0)),
+            self.vup,
+            self.vfov.sample_or(time, std::f64::consts::FRAC_PI_2),
+            self.aspect,
+            self.aperture,
+            self.focus_distance.sample_or(time, 10.0)
+        );
+        camera.set_shutter(time, time + shutter);
+        camera
+    }
+}
+
+/// Applies keyframed scale, rotation and translation, in that order, to a hittable. Tracks without keyframes leave
+/// the object as it is.
+/// The transform is evaluated at the time of each ray so it will motion blur within a frame.
+pub struct AnimatedTransform {
+    object: Arc<dyn Hittable>,
+    translation: Track<Vec3>,
+    rotation: Track<Quaternion>,
+    scale: Track<Vec3>,
+    bounding_box: Option<AABB>
+}
+
+impl AnimatedTransform {
+    pub fn new(object: Arc<dyn Hittable>, translation: Track<Vec3>, rotation: Track<Quaternion>, scale: Track<Vec3>) -> Self {
+        let mut transform = AnimatedTransform{object, translation, rotation, scale, bounding_box: None};
+        transform.bounding_box = transform.object.bounding_box().map(|bbox| transform.sweep_bounding_box(bbox));
+        transform
+    }
+
+    /// Creates a transform that only has keyframed translation and rotation.
+    pub fn rigid(object: Arc<dyn Hittable>, translation: Track<Vec3>, rotation: Track<Quaternion>) -> Self {
+        Self::new(object, translation, rotation, Track::constant(Vec3::new(1.0, 1.0, 1.0)))
+    }
+
+    fn transform_at(&self, time: f64) -> (Vec3, Quaternion, Vec3) {
+        (
+            self.translation.sample_or(time, Vec3::zero()),
+            self.rotation.sample_or(time, Quaternion::identity()),
+            self.scale.sample_or(time, Vec3::new(1.0, 1.0, 1.0))
+        )
+    }
+
+    /// Calculates a bounding box that contains the object for the whole animation.
+    fn sweep_bounding_box(&self, bounding_box: AABB) -> AABB {
+        const STEPS: usize = 16;
+        let mut times: Vec<f64> = self.translation.keyframes().iter().map(|k| k.time)
+            .chain(self.rotation.keyframes().iter().map(|k| k.time))
+            .chain(self.scale.keyframes().iter().map(|k| k.time))
+            .collect();
+        times.sort_by(|a, b| a.total_cmp(b));
+        times.dedup();
+        if times.is_empty() {
+            times.push(0.0);
+        }
+
+        let corners = bounding_box.get_corners();
+        let mut min = Vec3::new(f64::MAX, f64::MAX, f64::MAX);
+        let mut max = Vec3::new(f64::MIN, f64::MIN, f64::MIN);
+        let mut radius = 0.0f64;
+        let mut max_step_angle = 0.0f64;
+        let mut previous_rotation: Option<Quaternion> = None;
+        let mut add_sample = |time: f64| {
+            let (translation, rotation, scale) = self.transform_at(time);
+            for corner in corners.iter() {
+                let scaled = *corner * scale;
+                radius = radius.max(scaled.length());
+                let p = translation + rotation * scaled;
+                min = Vec3::new(min.x().min(p.x()), min.y().min(p.y()), min.z().min(p.z()));
+                max = Vec3::new(max.x().max(p.x()), max.y().max(p.y()), max.z().max(p.z()));
+            }
+            if let Some(previous) = previous_rotation {
+                let dot = Quaternion::dot(previous, rotation).abs().min(1.0);
+                max_step_angle = max_step_angle.max(2.0 * dot.acos());
+            }
+            previous_rotation = Some(rotation);
+        };
+
+        add_sample(times[0]);
+        for pair in times.windows(2) {
+            for i in 1..=STEPS {
+                add_sample(pair[0] + (pair[1] - pair[0]) * i as f64 / STEPS as f64);
+            }
+        }
+
+        // corners travel along arcs between the sampled rotations, pad by the furthest an arc can bulge from its chord
+        let padding = radius * (1.0 - (max_step_angle / 2.0).cos());
+        AABB::new(min - padding, max + padding)
+    }
+}
+
+impl Hittable for AnimatedTransform {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (translation, rotation, scale) = self.transform_at(r.time);
+        self.object.hit(object_ray(r, translation, rotation, scale), t_min, t_max).map(|rec| HitRecord{
+            p: translation + rotation * (rec.p * scale),
+            normal: (rotation * (rec.normal / scale)).normalize(),
+            ..rec
+        })
+    }
+
+    fn transmittance(&self, r: Ray, t_min: f64, t_max: f64) -> Vec3 {
+        let (translation, rotation, scale) = self.transform_at(r.time);
+        self.object.transmittance(object_ray(r, translation, rotation, scale), t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.bounding_box
+    }
+}
+
+/// The ray in the space of an object with the given transform. The direction is transformed without normalizing so t
+/// is the same in object and world space.
+fn object_ray(r: Ray, translation: Vec3, rotation: Quaternion, scale: Vec3) -> Ray {
+    let inverse_rotation = rotation.conjugate();
+    Ray::new((inverse_rotation * (r.origin - translation)) / scale, (inverse_rotation * r.direction) / scale, r.time)
+}
+
+/// Renders frames of an animation, writing each one to `<path_prefix><frame number>.png`.
+/// Frame n starts at n / frames_per_second and the shutter stays open for the given fraction of the frame.
+/// Returns how long each frame took to render.
+pub fn render_sequence(renderer: &mut Renderer, camera: &AnimatedCamera, frames: Range<u32>, frames_per_second: f64, shutter: f64, path_prefix: &str) -> std::io::Result<Vec<Duration>> {
+    let mut timings = Vec::with_capacity(frames.len());
+    for frame in frames {
+        let start = Instant::now();
+        let time = frame as f64 / frames_per_second;
+        renderer.set_camera(Box::new(camera.camera_at(time, shutter / frames_per_second)));
+        let data = renderer.render();
+        let path = format!("{}{:04}.png", path_prefix, frame);
+        write_png(Path::new(&path), renderer.width, renderer.height, &data)?;
+        timings.push(start.elapsed());
+    }
+    Ok(timings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytrace::sphere::Sphere;
+    use crate::raytrace::material::NormalMaterial;
+
+    #[test]
+    fn track_sample() {
+        let mut track = Track::new();
+        track.add(1.0, 2.0).add(0.0, 0.0).add_with_easing(2.0, 4.0, Easing::Step).add(3.0, 10.0);
+        assert_eq!(track.sample(-1.0), Some(0.0));
+        assert_eq!(track.sample(0.5), Some(1.0));
+        assert_eq!(track.sample(1.5), Some(3.0));
+        assert_eq!(track.sample(2.5), Some(4.0));
+        assert_eq!(track.sample(4.0), Some(10.0));
+        assert_eq!(Track::<f64>::new().sample(1.0), None);
+
+        // NaN times don't panic, sampling at one holds the last value
+        assert_eq!(track.sample(f64::NAN), Some(10.0));
+        track.add(f64::NAN, 5.0);
+        assert_eq!(track.sample(1.5), Some(3.0));
+    }
+
+    #[test]
+    fn track_rotation() {
+        let track = Track::new()
+            .key(0.0, Quaternion::identity())
+            .key(1.0, Quaternion::from_rotation_axis(Vec3::unit_y(), std::f64::consts::FRAC_PI_2));
+        let v = track.sample(0.5).unwrap() * Vec3::unit_x();
+        let expected = Vec3::new(std::f64::consts::FRAC_PI_4.cos(), 0.0, -std::f64::consts::FRAC_PI_4.sin());
+        assert!((v - expected).length() < 1e-9);
+    }
+
+    #[test]
+    fn animated_transform() {
+        let sphere = Arc::new(Sphere::new(Vec3::zero(), 1.0, Arc::new(NormalMaterial::new())));
+        let transform = AnimatedTransform::new(
+            sphere,
+            Track::new().key(0.0, Vec3::zero()).key(1.0, Vec3::new(0.0, 0.0, 10.0)),
+            Track::constant(Quaternion::identity()),
+            Track::new().key(0.0, Vec3::new(1.0, 1.0, 1.0)).key(1.0, Vec3::new(2.0, 2.0, 2.0))
+        );
+        let rec = transform.hit(Ray::new(Vec3::new(-5.0, 0.0, 10.0), Vec3::unit_x(), 1.0), 0.0, f64::MAX).unwrap();
+        assert!((rec.p - Vec3::new(-2.0, 0.0, 10.0)).length() < 1e-9);
+        assert!(transform.hit(Ray::new(Vec3::new(-5.0, 0.0, 10.0), Vec3::unit_x(), 0.0), 0.0, f64::MAX).is_none());
+        let bbox = transform.bounding_box().unwrap();
+        assert!(bbox.min.z() <= -1.0 && bbox.max.z() >= 12.0);
+
+        // without keyframes it stays where it is
+        let sphere = Arc::new(Sphere::new(Vec3::zero(), 1.0, Arc::new(NormalMaterial::new())));
+        let still = AnimatedTransform::new(sphere, Track::new(), Track::new(), Track::new());
+        let rec = still.hit(Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::unit_x(), 0.5), 0.0, f64::MAX).unwrap();
+        assert!((rec.p - Vec3::new(-1.0, 0.0, 0.0)).length() < 1e-9);
+        let bbox = still.bounding_box().unwrap();
+        assert!((bbox.min - Vec3::new(-1.0, -1.0, -1.0)).length() < 1e-9 && (bbox.max - Vec3::new(1.0, 1.0, 1.0)).length() < 1e-9);
+    }
+}
@@ -0,0 +1,152 @@
+//! Shapes of camera apertures, which give out of focus highlights (bokeh) their shape.
+
+use super::Vec3;
+use super::texture::Texture;
+use super::sampling::Distribution2D;
+use super::util::{drand48, random_in_unit_disk, luminance};
+use std::sync::Arc;
+
+pub trait Aperture: Send + Sync {
+    /// Returns a random point on the aperture, the aperture must fit within the unit circle.
+    fn sample(&self) -> (f64, f64);
+}
+
+/// A round aperture, as with a wide open lens.
+pub struct CircularAperture {}
+
+impl CircularAperture {
+    pub fn new() -> Self {
+        CircularAperture{}
+    }
+}
+
+impl Default for CircularAperture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Aperture for CircularAperture {
+    fn sample(&self) -> (f64, f64) {
+        let p = random_in_unit_disk();
+        (p.x(), p.y())
+    }
+}
+
+/// A regular polygon inscribed in the unit circle, as formed by the blades of a stopped down lens.
+pub struct PolygonalAperture {
+    blades: u32,
+    /// rotation of the first corner from the x axis in radians
+    rotation: f64
+}
+
+impl PolygonalAperture {
+    pub fn new(blades: u32, rotation: f64) -> Self {
+        assert!(blades >= 3, "A polygonal aperture needs at least 3 blades");
+        PolygonalAperture{blades, rotation}
+    }
+
+    fn corner(&self, i: u32) -> (f64, f64) {
+        let angle = self.rotation + 2.0 * std::f64::consts::PI * i as f64 / self.blades as f64;
+        (angle.cos(), angle.sin())
+    }
+}
+
+impl Aperture for PolygonalAperture {
+    fn sample(&self) -> (f64, f64) {
+        // the polygon is made of equal triangles fanned from the center, pick one then a uniform point within it
+        let i = ((drand48() * self.blades as f64) as u32).min(self.blades - 1);
+        let (ax, ay) = self.corner(i);
+        let (bx, by) = self.corner(i + 1);
+        let (mut s, mut t) = (drand48(), drand48());
+        if s + t > 1.0 {
+            s = 1.0 - s;
+            t = 1.0 - t;
+        }
+        (s * ax + t * bx, s * ay + t * by)
+    }
+}
+
+/// An aperture whose shape is given by the luminance of a texture over the unit square, for custom bokeh like stars or hearts.
+/// The texture is treated as a transmission mask, so values are clamped to [0, 1]. It is tabulated once over a grid
+/// and sampled in proportion to its transmission, a mask that lets no light through at all acts as a pinhole.
+pub struct TexturedAperture {
+    distribution: Option<Distribution2D>
+}
+
+impl TexturedAperture {
+    /// The number of cells the mask is tabulated over along each side.
+    const RESOLUTION: usize = 256;
+
+    pub fn new(mask: Arc<dyn Texture>) -> Self {
+        let n = Self::RESOLUTION;
+        let mut weights = Vec::with_capacity(n * n);
+        for j in 0..n {
+            for i in 0..n {
+                let (u, v) = ((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64);
+                let (x, y) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+                let transmission = if x * x + y * y <= 1.0 { luminance(mask.value(u, v, Vec3::new(x, y, 0.0))).clamp(0.0, 1.0) } else { 0.0 };
+                weights.push(transmission);
+            }
+        }
+        let distribution = if weights.iter().any(|&w| w > 0.0) { Some(Distribution2D::new(&weights, n, n)) } else { None };
+        TexturedAperture{distribution}
+    }
+}
+
+impl Aperture for TexturedAperture {
+    fn sample(&self) -> (f64, f64) {
+        let distribution = match &self.distribution {
+            Some(distribution) => distribution,
+            None => return (0.0, 0.0)
+        };
+        let ((u, v), _) = distribution.sample(drand48(), drand48());
+        let (x, y) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+        // cells on the rim stick out of the circle a little, pull those points back onto it
+        let r = (x * x + y * y).sqrt();
+        if r > 1.0 { (x / r, y / r) } else { (x, y) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytrace::texture::ConstantTexture;
+
+    #[test]
+    fn polygonal_aperture() {
+        // a square with corners on the axis only contains points where |x| + |y| <= 1
+        let aperture = PolygonalAperture::new(4, 0.0);
+        for _ in 0..1000 {
+            let (x, y) = aperture.sample();
+            assert!(x.abs() + y.abs() <= 1.0 + 1e-9);
+        }
+    }
+
+    #[test]
+    fn textured_aperture() {
+        /// Lets light through a small square off the centre, about 1% of the disk.
+        struct Window;
+
+        impl Texture for Window {
+            fn value(&self, _u: f64, _v: f64, p: Vec3) -> Vec3 {
+                if (p.x() - 0.5).abs() < 0.08 && (p.y() + 0.25).abs() < 0.08 { Vec3::new(1.0, 1.0, 1.0) } else { Vec3::zero() }
+            }
+        }
+
+        // every sample lands in the window rather than falling back to the centre
+        let aperture = TexturedAperture::new(Arc::new(Window));
+        for _ in 0..10000 {
+            let (x, y) = aperture.sample();
+            assert!((x - 0.5).abs() < 0.09 && (y + 0.25).abs() < 0.09, "{} {}", x, y);
+        }
+
+        // samples stay within the unit circle, and a mask that lets nothing through is a pinhole
+        let open = TexturedAperture::new(Arc::new(ConstantTexture::scalar(1.0)));
+        for _ in 0..10000 {
+            let (x, y) = open.sample();
+            assert!(x * x + y * y <= 1.0 + 1e-9);
+        }
+        assert_eq!(TexturedAperture::new(Arc::new(ConstantTexture::scalar(0.0))).sample(), (0.0, 0.0));
+    }
+}
@@ -0,0 +1,527 @@
+//! Bidirectional path tracing, which traces a path from the camera and a path from a light and connects every pair
+//! of their vertices, weighting the ways of making each path with multiple importance sampling. Light that reaches
+//! the camera through small openings or off caustics is much easier to find starting from the lights.
+
+use super::Vec3;
+use super::ray::Ray;
+use super::hittable::{Hittable, HitRecord};
+use super::camera::Camera;
+use super::light::Light;
+use super::material::offset_origin;
+use super::renderer::Background;
+use super::sampling::power_heuristic;
+use super::util::drand48;
+use std::sync::Arc;
+
+/// The most bounces a path can make between the camera and a light.
+pub const MAX_DEPTH: usize = 10;
+
+enum VertexKind {
+    Camera,
+    Light,
+    Surface(HitRecord)
+}
+
+/// A vertex of a camera or light path.
+struct Vertex {
+    kind: VertexKind,
+    p: Vec3,
+    /// zero for vertices that aren't on a surface, like point lights and the camera
+    normal: Vec3,
+    /// unit direction towards the previous vertex of the path the vertex is on
+    wo: Vec3,
+    /// the light the vertex is on, for light endpoints and lights hit by camera paths
+    light: Option<usize>,
+    /// radiance emitted back along the path towards the camera
+    emitted: Vec3,
+    /// the contribution of the path up to the vertex divided by the pdf of sampling it
+    beta: Vec3,
+    /// the density of sampling the vertex from the previous one, with respect to area
+    pdf_fwd: f64,
+    /// the density of sampling the vertex from the next one if the path was traced the other way
+    pdf_rev: f64,
+    /// set when the vertex scattered specularly so can't be connected to
+    delta: bool
+}
+
+impl Vertex {
+    fn endpoint(kind: VertexKind, p: Vec3, normal: Vec3, beta: Vec3, pdf_fwd: f64) -> Self {
+        Vertex{kind, p, normal, wo: Vec3::zero(), light: None, emitted: Vec3::zero(), beta, pdf_fwd, pdf_rev: 0.0, delta: false}
+    }
+
+    fn is_on_surface(&self) -> bool {
+        !self.normal.is_zero_length()
+    }
+
+    /// Converts a density with respect to solid angle at the vertex to one with respect to area at next.
+    fn convert_density(&self, pdf: f64, next: &Vertex) -> f64 {
+        let w = next.p - self.p;
+        let distance_squared = w.length_squared();
+        if distance_squared == 0.0 {
+            return 0.0;
+        }
+        let mut pdf = pdf / distance_squared;
+        if next.is_on_surface() {
+            pdf *= Vec3::dot(next.normal, w / distance_squared.sqrt()).abs();
+        }
+        pdf
+    }
+
+    /// Where rays leaving the vertex in direction start, off the surface so they don't hit it again.
+    fn spawn_point(&self, direction: Vec3) -> Vec3 {
+        match &self.kind {
+            VertexKind::Surface(rec) => offset_origin(rec, direction),
+            _ if self.is_on_surface() => self.p + Vec3::dot(self.normal, direction).signum() * super::EPSILON * self.normal,
+            _ => self.p
+        }
+    }
+
+    /// The scattering at the vertex from wo towards direction, times the cosine at the vertex.
+    fn f(&self, direction: Vec3) -> Vec3 {
+        match &self.kind {
+            VertexKind::Surface(rec) => rec.material.eval(rec, self.wo, direction),
+            _ => Vec3::zero()
+        }
+    }
+}
+
+/// The densities of a vertex, with the connection's changes, used to weight a path.
+#[derive(Clone, Copy)]
+struct Densities {
+    fwd: f64,
+    rev: f64,
+    delta: bool
+}
+
+impl From<&Vertex> for Densities {
+    fn from(v: &Vertex) -> Self {
+        Densities{fwd: v.pdf_fwd, rev: v.pdf_rev, delta: v.delta}
+    }
+}
+
+/// The ray a camera path escapes the scene along, the path's weight and the pdf of scattering into the ray
+/// if it could have been sampled.
+type Escape = (Ray, Vec3, Option<f64>);
+
+/// Traces and connects camera and light paths through a scene.
+pub struct BidirectionalTracer<'a> {
+    objects: &'a dyn Hittable,
+    camera: &'a dyn Camera,
+    lights: &'a [Arc<dyn Light>],
+    background: Option<&'a dyn Background>
+}
+
+impl<'a> BidirectionalTracer<'a> {
+    pub fn new(objects: &'a dyn Hittable, camera: &'a dyn Camera, lights: &'a [Arc<dyn Light>], background: Option<&'a dyn Background>) -> Self {
+        BidirectionalTracer{objects, camera, lights, background}
+    }
+
+    /// Estimates the radiance through the point (u, v) of the image. Light traced from the lights straight to the
+    /// camera lands elsewhere on the image and is passed to splat with its image coordinates instead.
+    /// Lights that can't start paths, like directional lights, are only reached by sampling them directly.
+    pub fn trace(&self, u: f64, v: f64, splat: &mut dyn FnMut(f64, f64, Vec3)) -> Vec3 {
+        let ray = match self.camera.get_ray(u, v) {
+            Some(ray) => ray,
+            None => return Vec3::zero()
+        };
+        let (camera_path, escaped) = self.camera_path(ray);
+        let light_path = self.light_path(ray.time);
+
+        let mut color = self.background_light(&camera_path, escaped, ray.time);
+        for t in 1..=camera_path.len() {
+            for s in 0..=light_path.len() {
+                if (s == 1 && t == 1) || s + t < 2 || s + t - 2 > MAX_DEPTH {
+                    continue;
+                }
+                if t == 1 {
+                    if let Some((u, v, contribution)) = self.connect_to_camera(&light_path, &camera_path, s, ray.time) {
+                        splat(u, v, contribution);
+                    }
+                }
+                else {
+                    color += self.connect(&light_path, &camera_path, s, t, ray.time);
+                }
+            }
+        }
+        color
+    }
+
+    fn camera_path(&self, ray: Ray) -> (Vec<Vertex>, Option<Escape>) {
+        let (_, pdf_direction) = self.camera.ray_pdf(&ray);
+        let mut camera = Vertex::endpoint(VertexKind::Camera, ray.origin, Vec3::zero(), Vec3::new(1.0, 1.0, 1.0), 1.0);
+        // cameras that can't be sampled can only be reached by tracing from them
+        camera.delta = pdf_direction == 0.0;
+        let mut path = vec![camera];
+        let escaped = self.random_walk(ray, Vec3::new(1.0, 1.0, 1.0), pdf_direction, MAX_DEPTH + 2, &mut path, true);
+        (path, escaped)
+    }
+
+    fn light_path(&self, time: f64) -> Vec<Vertex> {
+        if self.lights.is_empty() {
+            return Vec::new();
+        }
+        let index = ((drand48() * self.lights.len() as f64) as usize).min(self.lights.len() - 1);
+        let light_choice = 1.0 / self.lights.len() as f64;
+        let emission = match self.lights[index].sample_emission() {
+            Some(emission) if emission.pdf_position > 0.0 && emission.pdf_direction > 0.0 => emission,
+            _ => return Vec::new()
+        };
+        let mut vertex = Vertex::endpoint(VertexKind::Light, emission.origin, emission.normal, emission.radiance, emission.pdf_position * light_choice);
+        vertex.light = Some(index);
+        let cosine = if vertex.is_on_surface() { Vec3::dot(emission.normal, emission.direction).abs() } else { 1.0 };
+        let beta = emission.radiance * cosine / (light_choice * emission.pdf_position * emission.pdf_direction);
+        let ray = Ray::new(vertex.spawn_point(emission.direction), emission.direction, time);
+        let mut path = vec![vertex];
+        self.random_walk(ray, beta, emission.pdf_direction, MAX_DEPTH + 1, &mut path, false);
+        path
+    }
+
+    /// Extends the path by scattering off whatever the ray hits until it has max_vertices vertices or the path ends.
+    /// pdf is the density of sampling the ray's direction with respect to solid angle. Returns where a camera path
+    /// escapes the scene.
+    fn random_walk(&self, mut ray: Ray, mut beta: Vec3, pdf: f64, max_vertices: usize, path: &mut Vec<Vertex>, from_camera: bool) -> Option<Escape> {
+        let mut pdf_fwd = pdf;
+        let mut scatter_pdf = None;
+        // where light that went below the surface at the last vertex comes back out, which is the next vertex
+        let mut exit: Option<HitRecord> = None;
+        while path.len() < max_vertices {
+            let (rec, wo) = match exit.take() {
+                Some(exit) => {
+                    let wo = exit.normal;
+                    (exit, wo)
+                }
+                None => match self.objects.hit(ray, 0.0, f64::MAX) {
+                    Some(rec) => (rec, -ray.direction.normalize()),
+                    None => return if from_camera { Some((ray, beta, scatter_pdf)) } else { None }
+                }
+            };
+            let (p, normal) = (rec.p, rec.normal);
+            if rec.material.is_subsurface() {
+                // the walk below the surface can't be evaluated, so like a mirror nothing is connected to where the
+                // light goes in, and the density of reaching where it comes out is left out of the weights
+                let walk = rec.material.sample_subsurface(&rec, wo, ray.time);
+                let mut vertex = Vertex{kind: VertexKind::Surface(rec), p, normal, wo, light: None, emitted: Vec3::zero(), beta, pdf_fwd: 0.0, pdf_rev: 0.0, delta: true};
+                vertex.pdf_fwd = path.last().unwrap().convert_density(pdf_fwd, &vertex);
+                path.last_mut().unwrap().pdf_rev = 0.0;
+                path.push(vertex);
+                let (rec, weight) = match walk {
+                    Some(walk) => walk,
+                    None => break
+                };
+                beta *= weight;
+                pdf_fwd = 0.0;
+                scatter_pdf = None;
+                exit = Some(rec);
+                continue;
+            }
+            let emitted = if from_camera { rec.material.emitted(&ray, &rec) } else { Vec3::zero() };
+            let light = if emitted.is_zero_length() { None } else { self.light_at(&ray, &rec) };
+            let sample = rec.material.sample(&rec, wo, [drand48(), drand48(), drand48()]);
+            let reverse_pdf = sample.as_ref().map_or(0.0, |sample| rec.material.pdf(&rec, sample.wi, wo));
+            let mut vertex = Vertex{kind: VertexKind::Surface(rec), p, normal, wo, light, emitted, beta, pdf_fwd: 0.0, pdf_rev: 0.0, delta: false};
+            vertex.pdf_fwd = path.last().unwrap().convert_density(pdf_fwd, &vertex);
+            if path.len() + 1 >= max_vertices {
+                path.push(vertex);
+                break;
+            }
+            let sample = match sample {
+                Some(sample) => sample,
+                None => {
+                    path.push(vertex);
+                    break;
+                }
+            };
+            beta *= sample.weight;
+            let pdf_rev = if sample.lobe.is_specular() {
+                vertex.delta = true;
+                pdf_fwd = 0.0;
+                scatter_pdf = None;
+                0.0
+            }
+            else {
+                pdf_fwd = sample.pdf;
+                scatter_pdf = Some(sample.pdf);
+                reverse_pdf
+            };
+            let previous = path.last_mut().unwrap();
+            previous.pdf_rev = vertex.convert_density(pdf_rev, previous);
+            ray = Ray::new(vertex.spawn_point(sample.wi), sample.wi, ray.time);
+            path.push(vertex);
+            if beta.is_zero_length() {
+                break;
+            }
+        }
+        None
+    }
+
+    /// The index of the light a camera ray hit, if it's one of the lights.
+    fn light_at(&self, ray: &Ray, rec: &HitRecord) -> Option<usize> {
+        let distance = rec.t * ray.direction.length();
+        self.lights.iter().position(|light| {
+            !light.is_delta() && light.eval(ray.origin, ray.direction)
+                .is_some_and(|sample| (sample.distance - distance).abs() <= super::EPSILON.max(1e-6 * distance))
+        })
+    }
+
+    /// Light from the background, found by the camera path escaping the scene and by sampling it at each vertex.
+    fn background_light(&self, camera_path: &[Vertex], escaped: Option<Escape>, time: f64) -> Vec3 {
+        let background = match self.background {
+            Some(background) => background,
+            None => return Vec3::zero()
+        };
+        let mut color = Vec3::zero();
+        if let Some((ray, beta, scatter_pdf)) = escaped {
+            let weight = scatter_pdf.map_or(1.0, |pdf| power_heuristic(pdf, background.pdf(ray.direction)));
+            color += weight * beta * background.get(ray);
+        }
+        for vertex in camera_path.iter().skip(1).take(MAX_DEPTH) {
+            let rec = match &vertex.kind {
+                VertexKind::Surface(rec) => rec,
+                _ => continue
+            };
+            let (direction, radiance, pdf) = match background.sample() {
+                Some(sample) if sample.2 > 0.0 => sample,
+                _ => continue
+            };
+            let f = vertex.f(direction);
+            if f.is_zero_length() {
+                continue;
+            }
+            let transmittance = self.objects.transmittance(Ray::new(vertex.spawn_point(direction), direction, time), 0.0, f64::MAX);
+            if transmittance.is_zero_length() {
+                continue;
+            }
+            let weight = power_heuristic(pdf, rec.material.pdf(rec, vertex.wo, direction));
+            color += weight * vertex.beta * f * transmittance * radiance / pdf;
+        }
+        color
+    }
+
+    /// The fraction of each channel of light that makes it along the straight line between two vertices.
+    fn transmittance(&self, a: &Vertex, b: &Vertex, time: f64) -> Vec3 {
+        let direction = (b.p - a.p).normalize();
+        let origin = a.spawn_point(direction);
+        let distance = (b.spawn_point(-direction) - origin).length();
+        self.objects.transmittance(Ray::new(origin, direction, time), 0.0, distance - super::EPSILON)
+    }
+
+    /// Connects the first s vertices of the light path to the first t vertices of the camera path, for t of at least 2.
+    fn connect(&self, light_path: &[Vertex], camera_path: &[Vertex], s: usize, t: usize, time: f64) -> Vec3 {
+        let pt = &camera_path[t - 1];
+        if s == 0 {
+            // the camera path hit a light by itself
+            if pt.emitted.is_zero_length() {
+                return Vec3::zero();
+            }
+            let weight = if pt.light.is_some() { self.mis_weight(light_path, camera_path, None, s, t) } else { 1.0 };
+            return weight * pt.beta * pt.emitted;
+        }
+        if s == 1 {
+            return self.connect_to_light(light_path, camera_path, t, time);
+        }
+        let qs = &light_path[s - 1];
+        let w = pt.p - qs.p;
+        let distance_squared = w.length_squared();
+        if distance_squared == 0.0 {
+            return Vec3::zero();
+        }
+        let w = w / distance_squared.sqrt();
+        let contribution = qs.beta * qs.f(w) * pt.f(-w) * pt.beta / distance_squared;
+        if contribution.is_zero_length() {
+            return Vec3::zero();
+        }
+        let contribution = contribution * self.transmittance(qs, pt, time);
+        if contribution.is_zero_length() {
+            return Vec3::zero();
+        }
+        self.mis_weight(light_path, camera_path, None, s, t) * contribution
+    }
+
+    /// Samples a point on a light and connects it to the camera path, the light path is only used for weighting.
+    fn connect_to_light(&self, light_path: &[Vertex], camera_path: &[Vertex], t: usize, time: f64) -> Vec3 {
+        let pt = &camera_path[t - 1];
+        if self.lights.is_empty() {
+            return Vec3::zero();
+        }
+        let index = ((drand48() * self.lights.len() as f64) as usize).min(self.lights.len() - 1);
+        let light_choice = 1.0 / self.lights.len() as f64;
+        let light = &self.lights[index];
+        let sample = match light.sample(pt.p) {
+            Some(sample) if sample.pdf > 0.0 => sample,
+            _ => return Vec3::zero()
+        };
+        let f = pt.f(sample.direction);
+        if f.is_zero_length() {
+            return Vec3::zero();
+        }
+        let shadow_ray = Ray::new(pt.spawn_point(sample.direction), sample.direction, time);
+        let transmittance = self.objects.transmittance(shadow_ray, 0.0, sample.distance - 2.0 * super::EPSILON);
+        if transmittance.is_zero_length() {
+            return Vec3::zero();
+        }
+        let contribution = pt.beta * f * transmittance * sample.radiance / (sample.pdf * light_choice);
+        if sample.distance.is_infinite() {
+            // lights at infinity can't start light paths, so sampling them is the only way to reach them
+            return contribution;
+        }
+        let point = pt.p + sample.distance * sample.direction;
+        let normal = light.eval_emission(point, -sample.direction).map_or(Vec3::zero(), |emission| emission.normal);
+        let mut sampled = Vertex::endpoint(VertexKind::Light, point, normal, sample.radiance / (sample.pdf * light_choice), 0.0);
+        sampled.light = Some(index);
+        sampled.pdf_fwd = self.pdf_light_origin(&sampled, pt);
+        self.mis_weight(light_path, camera_path, Some(&sampled), 1, t) * contribution
+    }
+
+    /// Connects the light path to a point on the lens, returning where on the image it lands and its contribution.
+    fn connect_to_camera(&self, light_path: &[Vertex], camera_path: &[Vertex], s: usize, time: f64) -> Option<(f64, f64, Vec3)> {
+        let qs = &light_path[s - 1];
+        let importance = self.camera.sample_importance(qs.p)?;
+        if importance.pdf <= 0.0 || importance.importance <= 0.0 {
+            return None;
+        }
+        let point = qs.p + importance.distance * importance.direction;
+        let sampled = Vertex::endpoint(VertexKind::Camera, point, Vec3::zero(), Vec3::new(1.0, 1.0, 1.0) * (importance.importance / importance.pdf), 0.0);
+        let contribution = qs.beta * qs.f(importance.direction) * sampled.beta;
+        if contribution.is_zero_length() {
+            return None;
+        }
+        let contribution = contribution * self.transmittance(qs, &sampled, time);
+        if contribution.is_zero_length() {
+            return None;
+        }
+        let weight = self.mis_weight(light_path, camera_path, Some(&sampled), s, 1);
+        Some((importance.u, importance.v, weight * contribution))
+    }
+
+    /// The density of v sampling next, with respect to area at next, with prev the vertex before v.
+    fn pdf(&self, v: &Vertex, prev: Option<&Vertex>, next: &Vertex) -> f64 {
+        let wn = (next.p - v.p).normalize();
+        let pdf = match &v.kind {
+            VertexKind::Light => return self.pdf_light(v, next),
+            VertexKind::Camera => self.camera.ray_pdf(&Ray::new(v.p, wn, 0.0)).1,
+            VertexKind::Surface(rec) => match prev {
+                Some(prev) => rec.material.pdf(rec, (prev.p - v.p).normalize(), wn),
+                None => 0.0
+            }
+        };
+        v.convert_density(pdf, next)
+    }
+
+    /// The density of the light at v emitting towards next, with respect to area at next.
+    fn pdf_light(&self, v: &Vertex, next: &Vertex) -> f64 {
+        let light = match v.light {
+            Some(index) => &self.lights[index],
+            None => return 0.0
+        };
+        let w = next.p - v.p;
+        let distance_squared = w.length_squared();
+        if distance_squared == 0.0 {
+            return 0.0;
+        }
+        let w = w / distance_squared.sqrt();
+        let mut pdf = light.eval_emission(v.p, w).map_or(0.0, |emission| emission.pdf_direction) / distance_squared;
+        if next.is_on_surface() {
+            pdf *= Vec3::dot(next.normal, w).abs();
+        }
+        pdf
+    }
+
+    /// The density of a light path starting at v, emitting towards next, with respect to area.
+    fn pdf_light_origin(&self, v: &Vertex, next: &Vertex) -> f64 {
+        let light = match v.light {
+            Some(index) => &self.lights[index],
+            None => return 0.0
+        };
+        let w = (next.p - v.p).normalize();
+        light.eval_emission(v.p, w).map_or(0.0, |emission| emission.pdf_position) / self.lights.len() as f64
+    }
+
+    /// Weights the path made by the strategy with s light and t camera vertices against the other strategies
+    /// that could have made it, with the power heuristic. sampled replaces the endpoint when s or t is 1.
+    fn mis_weight(&self, light_path: &[Vertex], camera_path: &[Vertex], sampled: Option<&Vertex>, s: usize, t: usize) -> f64 {
+        if s + t == 2 {
+            return 1.0;
+        }
+        let qs = if s == 1 { sampled } else if s > 0 { Some(&light_path[s - 1]) } else { None };
+        let pt = if t == 1 { sampled.unwrap() } else { &camera_path[t - 1] };
+        let qs_minus = if s > 1 { Some(&light_path[s - 2]) } else { None };
+        let pt_minus = if t > 1 { Some(&camera_path[t - 2]) } else { None };
+
+        let mut light: Vec<Densities> = light_path[..s].iter().map(Densities::from).collect();
+        let mut camera: Vec<Densities> = camera_path[..t].iter().map(Densities::from).collect();
+        if let Some(qs) = qs {
+            light[s - 1] = qs.into();
+        }
+        camera[t - 1] = pt.into();
+        // the connected vertices can't have scattered specularly for the path to have been made
+        camera[t - 1].delta = false;
+        camera[t - 1].rev = match qs {
+            Some(qs) => self.pdf(qs, qs_minus, pt),
+            None => self.pdf_light_origin(pt, pt_minus.unwrap())
+        };
+        if let Some(pt_minus) = pt_minus {
+            camera[t - 2].rev = match qs {
+                Some(qs) => self.pdf(pt, Some(qs), pt_minus),
+                None => self.pdf_light(pt, pt_minus)
+            };
+        }
+        if let Some(qs) = qs {
+            light[s - 1].delta = false;
+            light[s - 1].rev = self.pdf(pt, pt_minus, qs);
+            if let Some(qs_minus) = qs_minus {
+                light[s - 2].rev = self.pdf(qs, Some(pt), qs_minus);
+            }
+        }
+
+        let remap = |pdf: f64| if pdf != 0.0 { pdf } else { 1.0 };
+        let mut sum = 0.0;
+        let mut ratio = 1.0;
+        for i in (1..t).rev() {
+            ratio *= remap(camera[i].rev) / remap(camera[i].fwd);
+            if !camera[i].delta && !camera[i - 1].delta {
+                sum += ratio * ratio;
+            }
+        }
+        ratio = 1.0;
+        for i in (0..s).rev() {
+            ratio *= remap(light[i].rev) / remap(light[i].fwd);
+            let delta_light = if i > 0 {
+                light[i - 1].delta
+            }
+            else {
+                let origin = if s == 1 { qs.unwrap() } else { &light_path[0] };
+                origin.light.is_some_and(|index| self.lights[index].is_delta())
+            };
+            if !light[i].delta && !delta_light {
+                sum += ratio * ratio;
+            }
+        }
+        1.0 / (1.0 + sum)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytrace::renderer::Integrator;
+    use crate::raytrace::renderer::tests::{comparison_renderer, seeded_mean};
+    use crate::raytrace::sphere::Sphere;
+    use crate::raytrace::light::PointLight;
+    use crate::raytrace::material::Metal;
+    use crate::raytrace::texture::ConstantTexture;
+
+    #[test]
+    fn matches_path_tracing() {
+        let renderer = || comparison_renderer(
+            128,
+            vec![Box::new(Sphere::new(Vec3::new(1.8, 0.6, 0.5), 0.6, Arc::new(Metal::new(Arc::new(ConstantTexture::scalar(0.9)), 0.3))))],
+            vec![Arc::new(PointLight::new(Vec3::new(-2.0, 2.0, 2.0), Vec3::new(2.0, 1.0, 1.0)))]
+        );
+        let path_tracer = renderer();
+        let mut bidirectional = renderer();
+        bidirectional.set_integrator(Integrator::Bidirectional);
+
+        let (expected, actual) = (seeded_mean(&path_tracer, 1), seeded_mean(&bidirectional, 2));
+        assert!((expected - actual).length() < 0.05 * expected.length(), "{:?} {:?}", expected, actual);
+    }
+}
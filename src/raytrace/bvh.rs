@@ -3,6 +3,7 @@
 use super::aabb::AABB;
 use super::hittable::{Hittable, HitRecord};
 use super::ray::Ray;
+use super::Vec3;
 use rand::Rng;
 use std::sync::Arc;
 
@@ -77,6 +78,14 @@ impl Hittable for BVHNode {
         }
     }
 
+    fn transmittance(&self, r: Ray, t_min: f64, t_max: f64) -> Vec3 {
+        if !self.bbox.hit(r, t_min, t_max) {
+            return Vec3::new(1.0, 1.0, 1.0);
+        }
+        let left = self.left.transmittance(r, t_min, t_max);
+        if left.is_zero_length() { left } else { left * self.right.transmittance(r, t_min, t_max) }
+    }
+
     fn bounding_box(&self) -> Option<AABB> {
         Some(self.bbox)
     }
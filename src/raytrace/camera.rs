@@ -1,43 +1,591 @@
 use super::Vec3;
 use super::ray::Ray;
-use super::util::random_in_unit_disk;
+use super::util::{random_in_unit_disk, drand48};
+use super::aperture::{Aperture, CircularAperture};
+use super::hittable::Hittable;
+use std::sync::Arc;
 
+/// Generates the primary rays for the image.
+pub trait Camera: Send + Sync {
+    /// Returns the ray through the point (u, v) of the image, where (0, 0) is the bottom left and (1, 1) the top right.
+    /// None is returned for points the camera can't see, like the corners of a circular fisheye.
+    fn get_ray(&self, u: f64, v: f64) -> Option<Ray>;
+
+    /// Scale applied to the radiance arriving at the camera.
+    fn exposure(&self) -> f64 {
+        1.0
+    }
+
+    /// Samples a point on the lens seen from p, for connecting paths traced from the lights to the image.
+    /// Cameras that return None can only be reached by tracing rays from the camera.
+    fn sample_importance(&self, _p: Vec3) -> Option<ImportanceSample> {
+        None
+    }
+
+    /// The densities of get_ray generating a ray, with respect to area on the lens and solid angle.
+    fn ray_pdf(&self, _ray: &Ray) -> (f64, f64) {
+        (0.0, 0.0)
+    }
+}
+
+/// A point on the lens of a camera seen from a point in the scene.
+#[derive(Clone, Copy, Debug)]
+pub struct ImportanceSample {
+    /// the image coordinates the point is seen at, as passed to get_ray
+    pub u: f64,
+    pub v: f64,
+    /// unit direction from the point in the scene towards the lens
+    pub direction: Vec3,
+    pub distance: f64,
+    /// the sensitivity of the camera to light arriving along direction, normalized over the whole image
+    pub importance: f64,
+    /// the density of sampling the point on the lens with respect to solid angle at the point in the scene
+    pub pdf: f64
+}
+
+/// The interval the shutter is open for, rays are generated at random times within it.
+#[derive(Clone, Copy, Debug, Default)]
+struct Shutter {
+    open: f64,
+    close: f64
+}
+
+impl Shutter {
+    fn sample(self) -> f64 {
+        self.open + drand48() * (self.close - self.open)
+    }
+}
+
+/// Returns the orthonormal basis (u, v, w) for a camera at look_from looking at look_at, with w pointing backwards.
+fn camera_basis(look_from: Vec3, look_at: Vec3, vup: Vec3) -> (Vec3, Vec3, Vec3) {
+    let w = (look_from - look_at).normalize();
+    let u = Vec3::cross(vup, w).normalize();
+    let v = Vec3::cross(w, u);
+    (u, v, w)
+}
+
+/// A perspective camera with a thin lens for depth of field.
 #[derive(Clone, Copy)]
-pub struct Camera {
+pub struct PerspectiveCamera {
     pub origin: Vec3,
     pub lower_left_corner: Vec3,
     pub horizontal: Vec3,
     pub vertical: Vec3,
     u: Vec3,
     v: Vec3,
-    _w: Vec3,
-    lens_radius: f64
+    w: Vec3,
+    half_width: f64,
+    half_height: f64,
+    focus_distance: f64,
+    lens_radius: f64,
+    shutter: Shutter
 }
 
-impl Camera {
+impl PerspectiveCamera {
     // vfov is vertical field of view in radians
     pub fn new(look_from: Vec3, look_at: Vec3, vup: Vec3, vfov: f64, aspect: f64, apeture: f64, focus_distance: f64) -> Self {
         let half_height = f64::tan(vfov / 2.0);
         let half_width = aspect * half_height;
-        let w = (look_from - look_at).normalize();
-        let u = Vec3::cross(vup, w);
-        let v = Vec3::cross(w, u);
-        let lower_left_corner = look_from - half_width * focus_distance * u - half_height * focus_distance * v - focus_distance * w;
-        Camera{
+        let (u, v, w) = camera_basis(look_from, look_at, vup);
+        let mut camera = PerspectiveCamera{
             origin: look_from,
-            lower_left_corner,
-            horizontal: 2.0 * half_width * focus_distance * u,
-            vertical: 2.0 * half_height * focus_distance * v,
+            lower_left_corner: Vec3::zero(),
+            horizontal: Vec3::zero(),
+            vertical: Vec3::zero(),
             u,
             v,
-            _w: w,
-            lens_radius: apeture / 2.0
+            w,
+            half_width,
+            half_height,
+            focus_distance,
+            lens_radius: apeture / 2.0,
+            shutter: Shutter::default()
+        };
+        camera.set_focus_distance(focus_distance);
+        camera
+    }
+
+    /// The area of the lens, or 1 for a pinhole.
+    fn lens_area(&self) -> f64 {
+        if self.lens_radius > 0.0 { std::f64::consts::PI * self.lens_radius * self.lens_radius } else { 1.0 }
+    }
+
+    /// The image coordinates a ray from the lens lands on and the cosine of its angle to the view direction,
+    /// None if it misses the image.
+    fn image_point(&self, ray: &Ray) -> Option<(f64, f64, f64)> {
+        let direction = ray.direction.normalize();
+        let cos_theta = -Vec3::dot(direction, self.w);
+        if cos_theta <= 0.0 {
+            return None;
+        }
+        // every ray from the lens through a point of the image plane passes through it in focus
+        let offset = ray.origin + (self.focus_distance / cos_theta) * direction - self.lower_left_corner;
+        let u = Vec3::dot(offset, self.horizontal) / self.horizontal.length_squared();
+        let v = Vec3::dot(offset, self.vertical) / self.vertical.length_squared();
+        if !(0.0..=1.0).contains(&u) || !(0.0..=1.0).contains(&v) {
+            return None;
         }
+        Some((u, v, cos_theta))
+    }
+
+    /// Sets the interval the shutter is open for, rays are generated at random times within it.
+    pub fn set_shutter(&mut self, open: f64, close: f64) {
+        self.shutter = Shutter{open, close};
+    }
+
+    pub fn shutter(&self) -> (f64, f64) {
+        (self.shutter.open, self.shutter.close)
+    }
+
+    pub fn focus_distance(&self) -> f64 {
+        self.focus_distance
     }
 
-    pub fn get_ray(&self, u: f64, v: f64) -> Ray {
+    /// Moves the plane of focus, the image plane is placed on it so the field of view is unchanged.
+    pub fn set_focus_distance(&mut self, focus_distance: f64) {
+        self.focus_distance = focus_distance;
+        self.lower_left_corner = self.origin - self.half_width * focus_distance * self.u - self.half_height * focus_distance * self.v - focus_distance * self.w;
+        self.horizontal = 2.0 * self.half_width * focus_distance * self.u;
+        self.vertical = 2.0 * self.half_height * focus_distance * self.v;
+    }
+
+    /// Focuses on the first object hit by a ray through the center of the image.
+    /// Returns the new focus distance, or None if nothing was hit and the focus is unchanged.
+    pub fn auto_focus(&mut self, world: &dyn Hittable) -> Option<f64> {
+        let distance = focus_distance_to(world, self.origin, -self.w, self.shutter.open)?;
+        self.set_focus_distance(distance);
+        Some(distance)
+    }
+}
+
+impl Camera for PerspectiveCamera {
+    fn get_ray(&self, u: f64, v: f64) -> Option<Ray> {
         let rd = self.lens_radius * random_in_unit_disk();
-        let offset = self.u * rd + self.v * rd;
-        Ray::new(self.origin + offset, self.lower_left_corner + u * self.horizontal + v * self.vertical - self.origin - offset)
+        let offset = self.u * rd.x() + self.v * rd.y();
+        Some(Ray::new(self.origin + offset, self.lower_left_corner + u * self.horizontal + v * self.vertical - self.origin - offset, self.shutter.sample()))
+    }
+
+    fn sample_importance(&self, p: Vec3) -> Option<ImportanceSample> {
+        let rd = self.lens_radius * random_in_unit_disk();
+        let lens_point = self.origin + self.u * rd.x() + self.v * rd.y();
+        let to_lens = lens_point - p;
+        let distance = to_lens.length();
+        let direction = to_lens / distance;
+        let (u, v, cos_theta) = self.image_point(&Ray::new(lens_point, -direction, 0.0))?;
+        // the image plane at distance 1 has area 4 half_width half_height, and importance falls off with the
+        // cosine cubed from the solid angle a pixel covers and once more from the angle to the lens
+        let image_area = 4.0 * self.half_width * self.half_height;
+        let importance = 1.0 / (image_area * self.lens_area() * cos_theta.powi(4));
+        let pdf = distance * distance / (cos_theta * self.lens_area());
+        Some(ImportanceSample{u, v, direction, distance, importance, pdf})
+    }
+
+    fn ray_pdf(&self, ray: &Ray) -> (f64, f64) {
+        match self.image_point(ray) {
+            Some((_, _, cos_theta)) => {
+                let image_area = 4.0 * self.half_width * self.half_height;
+                (1.0 / self.lens_area(), 1.0 / (image_area * cos_theta.powi(3)))
+            }
+            None => (0.0, 0.0)
+        }
+    }
+}
+
+/// Returns the distance to the first object hit along a ray, direction must be normalized.
+fn focus_distance_to(world: &dyn Hittable, origin: Vec3, direction: Vec3, time: f64) -> Option<f64> {
+    world.hit(Ray::new(origin, direction, time), super::EPSILON, f64::MAX).map(|rec| rec.t)
+}
+
+/// A camera where all rays are parallel, so objects don't get smaller with distance.
+#[derive(Clone, Copy)]
+pub struct OrthographicCamera {
+    lower_left_corner: Vec3,
+    horizontal: Vec3,
+    vertical: Vec3,
+    direction: Vec3,
+    shutter: Shutter
+}
+
+impl OrthographicCamera {
+    /// view_height is the height of the area that is visible in scene units.
+    pub fn new(look_from: Vec3, look_at: Vec3, vup: Vec3, view_height: f64, aspect: f64) -> Self {
+        let (u, v, w) = camera_basis(look_from, look_at, vup);
+        let horizontal = view_height * aspect * u;
+        let vertical = view_height * v;
+        OrthographicCamera{
+            lower_left_corner: look_from - horizontal / 2.0 - vertical / 2.0,
+            horizontal,
+            vertical,
+            direction: -w,
+            shutter: Shutter::default()
+        }
+    }
+
+    pub fn set_shutter(&mut self, open: f64, close: f64) {
+        self.shutter = Shutter{open, close};
+    }
+}
+
+impl Camera for OrthographicCamera {
+    fn get_ray(&self, u: f64, v: f64) -> Option<Ray> {
+        Some(Ray::new(self.lower_left_corner + u * self.horizontal + v * self.vertical, self.direction, self.shutter.sample()))
+    }
+}
+
+/// An equidistant fisheye, the angle from the view direction is proportional to the distance from the center of the image.
+/// The image circle touches the top and bottom of the image, anything outside of it is not rendered.
+#[derive(Clone, Copy)]
+pub struct FisheyeCamera {
+    origin: Vec3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    fov: f64,
+    aspect: f64,
+    shutter: Shutter
+}
+
+impl FisheyeCamera {
+    /// fov is the angle across the image circle in radians, it can be more than 180 degrees.
+    pub fn new(look_from: Vec3, look_at: Vec3, vup: Vec3, fov: f64, aspect: f64) -> Self {
+        let (u, v, w) = camera_basis(look_from, look_at, vup);
+        FisheyeCamera{origin: look_from, u, v, w, fov, aspect, shutter: Shutter::default()}
+    }
+
+    pub fn set_shutter(&mut self, open: f64, close: f64) {
+        self.shutter = Shutter{open, close};
+    }
+}
+
+impl Camera for FisheyeCamera {
+    fn get_ray(&self, u: f64, v: f64) -> Option<Ray> {
+        // position relative to the center of the image, scaled so the image circle has a radius of 1
+        let x = (2.0 * u - 1.0) * self.aspect;
+        let y = 2.0 * v - 1.0;
+        let r = (x * x + y * y).sqrt();
+        if r > 1.0 {
+            return None;
+        }
+        let theta = r * self.fov / 2.0;
+        let phi = y.atan2(x);
+        let direction = theta.sin() * (phi.cos() * self.u + phi.sin() * self.v) - theta.cos() * self.w;
+        Some(Ray::new(self.origin, direction, self.shutter.sample()))
+    }
+}
+
+/// Maps the image to longitude and latitude around the camera, for 360 degree panoramas and VR.
+#[derive(Clone, Copy)]
+pub struct EquirectangularCamera {
+    origin: Vec3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    horizontal_fov: f64,
+    vertical_fov: f64,
+    shutter: Shutter
+}
+
+impl EquirectangularCamera {
+    /// Creates a full 360 by 180 degree panorama centered on look_at, which should be rendered with a 2:1 aspect.
+    pub fn new(look_from: Vec3, look_at: Vec3, vup: Vec3) -> Self {
+        Self::panoramic(look_from, look_at, vup, 2.0 * std::f64::consts::PI, std::f64::consts::PI)
+    }
+
+    /// Creates a panorama covering part of the sphere, the field of views are in radians.
+    pub fn panoramic(look_from: Vec3, look_at: Vec3, vup: Vec3, horizontal_fov: f64, vertical_fov: f64) -> Self {
+        let (u, v, w) = camera_basis(look_from, look_at, vup);
+        EquirectangularCamera{origin: look_from, u, v, w, horizontal_fov, vertical_fov, shutter: Shutter::default()}
+    }
+
+    pub fn set_shutter(&mut self, open: f64, close: f64) {
+        self.shutter = Shutter{open, close};
+    }
+}
+
+impl Camera for EquirectangularCamera {
+    fn get_ray(&self, u: f64, v: f64) -> Option<Ray> {
+        let longitude = (u - 0.5) * self.horizontal_fov;
+        let latitude = (v - 0.5) * self.vertical_fov;
+        let direction = latitude.cos() * (longitude.sin() * self.u - longitude.cos() * self.w) + latitude.sin() * self.v;
+        Some(Ray::new(self.origin, direction, self.shutter.sample()))
+    }
+}
+
+/// A camera described by the settings of a real camera, with the focal length and sensor size in millimeters.
+/// The scene is treated as being in meters unless set_scene_scale is used.
+#[derive(Clone)]
+pub struct PhysicalCamera {
+    origin: Vec3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    aspect: f64,
+    /// focal length in mm
+    focal_length: f64,
+    f_number: f64,
+    /// width of the sensor in mm, the height is worked out from the aspect
+    sensor_width: f64,
+    focus_distance: f64,
+    /// scene units per meter
+    scene_scale: f64,
+    aperture: Arc<dyn Aperture>,
+    exposure: f64,
+    shutter: Shutter
+}
+
+impl PhysicalCamera {
+    /// Creates a camera with a 50mm f/8 lens on a full frame (36mm wide) sensor, focused on look_at.
+    pub fn new(look_from: Vec3, look_at: Vec3, vup: Vec3, aspect: f64) -> Self {
+        let (u, v, w) = camera_basis(look_from, look_at, vup);
+        PhysicalCamera{
+            origin: look_from,
+            u,
+            v,
+            w,
+            aspect,
+            focal_length: 50.0,
+            f_number: 8.0,
+            sensor_width: 36.0,
+            focus_distance: (look_at - look_from).length(),
+            scene_scale: 1.0,
+            aperture: Arc::new(CircularAperture::new()),
+            exposure: 1.0,
+            shutter: Shutter::default()
+        }
+    }
+
+    /// Sets the focal length in mm and the f-number, the aperture diameter is focal_length / f_number.
+    pub fn set_lens(&mut self, focal_length: f64, f_number: f64) {
+        self.focal_length = focal_length;
+        self.f_number = f_number;
+    }
+
+    /// Sets the width of the sensor in mm, eg 36 for full frame or 23.6 for APS-C.
+    pub fn set_sensor_width(&mut self, sensor_width: f64) {
+        self.sensor_width = sensor_width;
+    }
+
+    /// Sets the distance to the plane of focus in scene units.
+    pub fn set_focus_distance(&mut self, focus_distance: f64) {
+        self.focus_distance = focus_distance;
+    }
+
+    /// Focuses on the first object hit by a ray through the center of the image.
+    /// Returns the new focus distance, or None if nothing was hit and the focus is unchanged.
+    pub fn auto_focus(&mut self, world: &dyn Hittable) -> Option<f64> {
+        let distance = focus_distance_to(world, self.origin, -self.w, self.shutter.open)?;
+        self.focus_distance = distance;
+        Some(distance)
+    }
+
+    /// Sets how many scene units there are to a meter, so the lens size is correct for scenes not modeled in meters.
+    pub fn set_scene_scale(&mut self, units_per_meter: f64) {
+        self.scene_scale = units_per_meter;
+    }
+
+    pub fn set_aperture_shape(&mut self, aperture: Arc<dyn Aperture>) {
+        self.aperture = aperture;
+    }
+
+    /// Sets the exposure from the ISO and exposure time in seconds, using the current f-number.
+    /// Radiance in the scene is treated as being in cd/m^2 and is scaled to give a middle grey for a correctly exposed image.
+    pub fn set_exposure(&mut self, iso: f64, exposure_time: f64) {
+        // saturation based exposure value at ISO 100, see "Moving Frostbite to Physically Based Rendering"
+        let ev100 = (self.f_number * self.f_number / exposure_time * 100.0 / iso).log2();
+        self.exposure = 1.0 / (1.2 * 2f64.powf(ev100));
+    }
+
+    /// Sets the interval the shutter is open for, rays are generated at random times within it.
+    /// This only controls motion blur, set_exposure sets the brightness.
+    pub fn set_shutter(&mut self, open: f64, close: f64) {
+        self.shutter = Shutter{open, close};
+    }
+
+    /// The vertical field of view in radians.
+    pub fn vfov(&self) -> f64 {
+        let sensor_height = self.sensor_width / self.aspect;
+        2.0 * (sensor_height / (2.0 * self.focal_length)).atan()
     }
-}
\ No newline at end of file
+
+    /// The radius of the aperture in scene units.
+    pub fn lens_radius(&self) -> f64 {
+        self.focal_length / self.f_number / 2.0 / 1000.0 * self.scene_scale
+    }
+}
+
+impl Camera for PhysicalCamera {
+    fn get_ray(&self, u: f64, v: f64) -> Option<Ray> {
+        let half_height = (self.vfov() / 2.0).tan() * self.focus_distance;
+        let half_width = self.aspect * half_height;
+        let target = self.origin
+            + (2.0 * u - 1.0) * half_width * self.u
+            + (2.0 * v - 1.0) * half_height * self.v
+            - self.focus_distance * self.w;
+        let (x, y) = self.aperture.sample();
+        let lens_radius = self.lens_radius();
+        let origin = self.origin + lens_radius * x * self.u + lens_radius * y * self.v;
+        Some(Ray::new(origin, target - origin, self.shutter.sample()))
+    }
+
+    fn exposure(&self) -> f64 {
+        self.exposure
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_vec_eq(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-9, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn orthographic() {
+        let camera = OrthographicCamera::new(Vec3::new(0.0, 0.0, 5.0), Vec3::zero(), Vec3::unit_y(), 2.0, 2.0);
+        let center = camera.get_ray(0.5, 0.5).unwrap();
+        let corner = camera.get_ray(1.0, 1.0).unwrap();
+        assert_vec_eq(center.origin, Vec3::new(0.0, 0.0, 5.0));
+        assert_vec_eq(corner.origin, Vec3::new(2.0, 1.0, 5.0));
+        assert_vec_eq(corner.direction, center.direction);
+    }
+
+    #[test]
+    fn fisheye() {
+        let camera = FisheyeCamera::new(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0), Vec3::unit_y(), std::f64::consts::PI, 1.0);
+        assert_vec_eq(camera.get_ray(0.5, 0.5).unwrap().direction, Vec3::new(0.0, 0.0, -1.0));
+        // the edge of a 180 degree fisheye looks sideways
+        assert_vec_eq(camera.get_ray(1.0, 0.5).unwrap().direction, Vec3::unit_x());
+        assert_vec_eq(camera.get_ray(0.5, 1.0).unwrap().direction, Vec3::unit_y());
+        assert!(camera.get_ray(1.0, 1.0).is_none());
+    }
+
+    #[test]
+    fn equirectangular() {
+        let camera = EquirectangularCamera::new(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0), Vec3::unit_y());
+        assert_vec_eq(camera.get_ray(0.5, 0.5).unwrap().direction, Vec3::new(0.0, 0.0, -1.0));
+        assert_vec_eq(camera.get_ray(0.75, 0.5).unwrap().direction, Vec3::unit_x());
+        assert_vec_eq(camera.get_ray(0.0, 0.5).unwrap().direction, Vec3::new(0.0, 0.0, 1.0));
+        assert_vec_eq(camera.get_ray(0.3, 1.0).unwrap().direction, Vec3::unit_y());
+    }
+
+    #[test]
+    fn physical_camera() {
+        let mut camera = PhysicalCamera::new(Vec3::zero(), Vec3::new(0.0, 0.0, -2.0), Vec3::unit_y(), 1.5);
+        camera.set_lens(50.0, 2.0);
+        assert!((camera.lens_radius() - 0.0125).abs() < 1e-12);
+        // 36 x 24mm sensor with a 50mm lens
+        assert!((camera.vfov() - 2.0 * (12.0f64 / 50.0).atan()).abs() < 1e-12);
+        // every ray through the center of the image passes through the focus point
+        for _ in 0..100 {
+            let ray = camera.get_ray(0.5, 0.5).unwrap();
+            let t = (-2.0 - ray.origin.z()) / ray.direction.z();
+            assert_vec_eq(ray.point_at_parameter(t), Vec3::new(0.0, 0.0, -2.0));
+        }
+        camera.set_lens(50.0, 16.0);
+        camera.set_exposure(100.0, 1.0 / 100.0);
+        assert!((camera.exposure() - 1.0 / (1.2 * 25600.0)).abs() < 1e-12);
+    }
+
+    fn thin_lens_camera() -> PerspectiveCamera {
+        PerspectiveCamera::new(Vec3::new(0.0, 0.0, 5.0), Vec3::zero(), Vec3::unit_y(), std::f64::consts::FRAC_PI_2, 1.0, 2.0, 4.0)
+    }
+
+    #[test]
+    fn thin_lens_origins() {
+        let camera = thin_lens_camera();
+        let (mut sum_x, mut sum_y, mut sum_xy, mut sum_xx, mut sum_yy) = (0.0, 0.0, 0.0, 0.0, 0.0);
+        let n = 10000;
+        for _ in 0..n {
+            let ray = camera.get_ray(0.3, 0.7).unwrap();
+            let offset = ray.origin - camera.origin;
+            // the lens is the disk of radius 1 facing down the z axis
+            assert!(offset.z().abs() < 1e-12);
+            assert!(offset.length() <= 1.0 + 1e-12);
+            sum_x += offset.x();
+            sum_y += offset.y();
+            sum_xy += offset.x() * offset.y();
+            sum_xx += offset.x() * offset.x();
+            sum_yy += offset.y() * offset.y();
+        }
+        let n = n as f64;
+        // a uniform disk of radius 1 has mean 0, a variance of 1/4 along each axis and no correlation between them
+        assert!((sum_x / n).abs() < 0.03);
+        assert!((sum_y / n).abs() < 0.03);
+        assert!((sum_xx / n - 0.25).abs() < 0.02);
+        assert!((sum_yy / n - 0.25).abs() < 0.02);
+        assert!((sum_xy / n).abs() < 0.02);
+    }
+
+    #[test]
+    fn thin_lens_focus_plane() {
+        let camera = thin_lens_camera();
+        for &(u, v) in [(0.5, 0.5), (0.0, 0.0), (0.8, 0.3)].iter() {
+            let expected = camera.lower_left_corner + u * camera.horizontal + v * camera.vertical;
+            // the focus plane is at z = 1, 4 units in front of the camera
+            assert!((expected.z() - 1.0).abs() < 1e-12);
+            for _ in 0..100 {
+                let ray = camera.get_ray(u, v).unwrap();
+                let t = (1.0 - ray.origin.z()) / ray.direction.z();
+                assert_vec_eq(ray.point_at_parameter(t), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn pinhole_camera() {
+        let camera = PerspectiveCamera::new(Vec3::new(0.0, 0.0, 5.0), Vec3::zero(), Vec3::unit_y(), std::f64::consts::FRAC_PI_2, 1.0, 0.0, 4.0);
+        for _ in 0..10 {
+            assert_vec_eq(camera.get_ray(0.2, 0.9).unwrap().origin, camera.origin);
+        }
+        // a 90 degree field of view reaches 45 degrees at the top of the image
+        assert_vec_eq(camera.get_ray(0.5, 1.0).unwrap().direction.normalize(), Vec3::new(0.0, 1.0, -1.0).normalize());
+    }
+
+    #[test]
+    fn auto_focus() {
+        use crate::raytrace::sphere::Sphere;
+        use crate::raytrace::material::NormalMaterial;
+        let sphere = Sphere::new(Vec3::new(0.0, 0.0, -1.0), 1.0, Arc::new(NormalMaterial::new()));
+        let mut camera = thin_lens_camera();
+        assert_eq!(camera.auto_focus(&sphere), Some(5.0));
+        assert_eq!(camera.focus_distance(), 5.0);
+        let expected = camera.lower_left_corner + 0.5 * camera.horizontal + 0.5 * camera.vertical;
+        assert_vec_eq(expected, Vec3::zero());
+        let mut camera = PerspectiveCamera::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, 6.0), Vec3::unit_y(), 1.0, 1.0, 2.0, 4.0);
+        assert_eq!(camera.auto_focus(&sphere), None);
+        assert_eq!(camera.focus_distance(), 4.0);
+    }
+
+    #[test]
+    fn importance() {
+        for &aperture in [0.0, 0.5].iter() {
+            let camera = PerspectiveCamera::new(Vec3::new(0.0, 0.0, 5.0), Vec3::zero(), Vec3::unit_y(), 1.0, 1.5, aperture, 4.0);
+            let p = Vec3::new(0.4, -0.3, 1.0);
+            let sample = camera.sample_importance(p).unwrap();
+            // the point is seen where the camera's ray lands, and a ray from the lens point back to it has that image point
+            let lens_point = p + sample.distance * sample.direction;
+            let (u, v, _) = camera.image_point(&Ray::new(lens_point, -sample.direction, 0.0)).unwrap();
+            assert!((u - sample.u).abs() < 1e-9 && (v - sample.v).abs() < 1e-9);
+            if aperture == 0.0 {
+                let ray = camera.get_ray(sample.u, sample.v).unwrap();
+                assert_vec_eq(ray.direction.normalize(), -sample.direction);
+            }
+            // the direction pdf integrates to 1 over the image
+            let n = 200;
+            let mut total = 0.0;
+            for i in 0..n {
+                for j in 0..n {
+                    let ray = camera.get_ray((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64).unwrap();
+                    let (_, pdf) = camera.ray_pdf(&ray);
+                    // each sample covers an area of the image plane at distance 1 with a solid angle of cos^3 over it
+                    let cos_theta = -Vec3::dot(ray.direction.normalize(), camera.w);
+                    total += pdf * 4.0 * camera.half_width * camera.half_height * cos_theta.powi(3) / (n * n) as f64;
+                }
+            }
+            assert!((total - 1.0).abs() < 1e-6, "{}", total);
+        }
+        let camera = thin_lens_camera();
+        assert!(camera.sample_importance(Vec3::new(0.0, 0.0, 10.0)).is_none());
+    }
+}
@@ -0,0 +1,103 @@
+use std::sync::Arc;
+use super::Vec3;
+use super::ray::Ray;
+use super::hittable::{Hittable, HitRecord};
+use super::material::Material;
+use super::aabb::AABB;
+use super::disk::{azimuth, hit_disk};
+use std::f64::consts::PI;
+
+/// A cone standing on the xz plane with the center of its base at base and its tip height above it up the y axis.
+/// The base is capped unless set otherwise.
+pub struct Cone {
+    base: Vec3,
+    radius: f64,
+    height: f64,
+    capped: bool,
+    material: Arc<dyn Material>
+}
+
+impl Cone {
+    pub fn new(base: Vec3, radius: f64, height: f64, material: Arc<dyn Material>) -> Self {
+        Cone{base, radius, height, capped: true, material}
+    }
+
+    pub fn set_capped(&mut self, capped: bool) {
+        self.capped = capped;
+    }
+}
+
+impl Hittable for Cone {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let mut closest: Option<HitRecord> = None;
+        // x² + z² = k (height - y)² with the base at the origin
+        let (o, d) = (r.origin - self.base, r.direction);
+        let k = (self.radius / self.height).powi(2);
+        let below_tip = self.height - o.y();
+        let a = d.x() * d.x() + d.z() * d.z() - k * d.y() * d.y();
+        let b = o.x() * d.x() + o.z() * d.z() + k * below_tip * d.y();
+        let c = o.x() * o.x() + o.z() * o.z() - k * below_tip * below_tip;
+        let mut roots = if a.abs() < 1e-12 {
+            // parallel to the side, so it only crosses the cone once
+            if b == 0.0 { vec![] } else { vec![-c / (2.0 * b)] }
+        }
+        else {
+            let discriminant = b * b - a * c;
+            if discriminant < 0.0 { vec![] } else { vec![(-b - discriminant.sqrt()) / a, (-b + discriminant.sqrt()) / a] }
+        };
+        roots.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        for t in roots {
+            let y = o.y() + t * d.y();
+            if t > t_min && t < t_max && y >= 0.0 && y <= self.height {
+                let p = r.point_at_parameter(t);
+                let (x, z) = (p.x() - self.base.x(), p.z() - self.base.z());
+                let normal = Vec3::new(x, k * (self.height - y), z);
+                // the normal at the tip is taken to point straight up
+                let normal = if normal.is_zero_length() { Vec3::unit_y() } else { normal.normalize() };
+                let u = azimuth(x, z) / (2.0 * PI);
+                closest = Some(HitRecord::new(t, p, normal, Arc::clone(&self.material), u, y / self.height));
+                break;
+            }
+        }
+        if self.capped {
+            let t_max = closest.as_ref().map_or(t_max, |rec| rec.t);
+            if let Some(rec) = hit_disk(self.base, 0.0, self.radius, -1.0, &self.material, r, t_min, t_max) {
+                closest = Some(rec);
+            }
+        }
+        closest
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        let extent = Vec3::new(self.radius, 0.0, self.radius);
+        Some(AABB::new(self.base - extent, self.base + extent + Vec3::new(0.0, self.height, 0.0)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytrace::material::NormalMaterial;
+
+    #[test]
+    fn cone_hit() {
+        let mut cone = Cone::new(Vec3::zero(), 1.0, 1.0, Arc::new(NormalMaterial::new()));
+        let rec = cone.hit(Ray::new(Vec3::new(-5.0, 0.5, 0.0), Vec3::unit_x(), 0.0), 0.0, f64::MAX).unwrap();
+        assert!((rec.t - 4.5).abs() < 1e-12 && (rec.v - 0.5).abs() < 1e-12);
+        assert!((rec.normal - Vec3::new(-1.0, 1.0, 0.0).normalize()).length() < 1e-12);
+
+        let up = Ray::new(Vec3::new(0.2, -1.0, 0.0), Vec3::unit_y(), 0.0);
+        let rec = cone.hit(up, 0.0, f64::MAX).unwrap();
+        assert!((rec.t - 1.0).abs() < 1e-12);
+        assert_eq!(rec.normal, Vec3::new(0.0, -1.0, 0.0));
+
+        // without the base the ray goes in and hits the inside of the side
+        cone.set_capped(false);
+        let rec = cone.hit(up, 0.0, f64::MAX).unwrap();
+        assert!((rec.t - 1.8).abs() < 1e-12);
+        assert!((rec.normal - Vec3::new(1.0, 1.0, 0.0).normalize()).length() < 1e-12);
+
+        // nothing above the tip
+        assert!(cone.hit(Ray::new(Vec3::new(-5.0, 1.5, 0.0), Vec3::unit_x(), 0.0), 0.0, f64::MAX).is_none());
+    }
+}
@@ -3,73 +3,388 @@ use super::aabb::AABB;
 use super::hittable::{HitRecord, Hittable};
 use super::Vec3;
 use std::sync::Arc;
-use super::material::Material;
+use super::material::{Material, BsdfSample, Lobe};
+use super::phase::PhaseFunction;
+use super::texture::{Texture, ConstantTexture};
 use super::ray::Ray;
 
+/// The most times a ray is followed in and out of a boundary, in case it never gets out.
+const MAX_CROSSINGS: usize = 64;
+
+/// A volume of particles filling a boundary, which light is tracked through a piece at a time so media can be
+/// combined.
+pub trait Medium: Send + Sync {
+    fn boundary(&self) -> &dyn Hittable;
+
+    /// Samples where a ray inside the medium between start and end first runs into a particle.
+    fn sample_collision(&self, r: Ray, start: f64, end: f64) -> Option<HitRecord>;
+
+    /// Estimates the fraction of each channel of light that makes it through the medium between start and end.
+    fn estimate_transmittance(&self, r: Ray, start: f64, end: f64) -> Vec3;
+}
+
 pub struct ConstantMedium {
     boundary: Arc<dyn Hittable>,
     density: f64,
+    /// the extinction of each channel, which is the density unless the medium was made from coefficients
+    sigma_t: Vec3,
     phase_function: Arc<dyn Material>
 }
 
 impl ConstantMedium {
+    /// Creates a medium that scatters light at the given density with phase_function, which is the material of
+    /// the particles and absorbs any light it doesn't scatter.
     pub fn new(boundary: Arc<dyn Hittable>, density: f64, phase_function: Arc<dyn Material>) -> ConstantMedium {
-        ConstantMedium{boundary, density, phase_function}
+        ConstantMedium{boundary, density, sigma_t: Vec3::new(density, density, density), phase_function}
+    }
+
+    /// Creates a medium from how much of each color channel it scatters and absorbs per unit distance, so it can
+    /// tint the light passing through it as well as the light it scatters. The albedo texture scales the
+    /// scattering from place to place, with the light it takes away absorbed.
+    ///
+    /// Light is tracked at the largest extinction of any channel, and channels with less extinction pass
+    /// straight through some of the particles.
+    pub fn with_coefficients(boundary: Arc<dyn Hittable>, sigma_s: Vec3, sigma_a: Vec3, phase_function: Arc<dyn PhaseFunction>, albedo: Option<Arc<dyn Texture>>) -> ConstantMedium {
+        let sigma_t = sigma_s + sigma_a;
+        let majorant = sigma_t.x().max(sigma_t.y()).max(sigma_t.z());
+        let albedo = albedo.unwrap_or_else(|| Arc::new(ConstantTexture::scalar(1.0)));
+        let particles = MediumParticles{phase_function, albedo, sigma_s, sigma_n: Vec3::new(majorant, majorant, majorant) - sigma_t, majorant};
+        ConstantMedium{boundary, density: majorant, sigma_t, phase_function: Arc::new(particles)}
     }
 }
 
-impl Hittable for ConstantMedium {
-    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        match self.boundary.hit(r, t_min, t_max) {
-            Some(mut rec1) => {
-                match self.boundary.hit(r, rec1.t + super::EPSILON, t_max) {
-                    Some(mut rec2) => {
-                        // eprintln!("rec1.t: {:?} rec2.t: {:?}", rec1.t, rec2.t);
-                        if rec1.t < t_min {
-                            rec1.t = t_min;
-                        }
-                        if rec2.t > t_max {
-                            rec2.t = t_max;
-                        }
-                        if rec1.t >= rec2.t {
-                            return None;
-                        }
-                        if rec1.t < 0.0 {
-                            rec1.t = 0.0;
-                        }
-
-                        let ray_length = r.direction.length();
-                        let distance_inside_boundary = (rec2.t - rec1.t) * ray_length;
-                        let hit_distance = -(1.0 / self.density) * drand48().ln();
-
-                        // eprintln!("hit_distance: {} distance_inside_boundary: {}", hit_distance, distance_inside_boundary);
-                        if hit_distance < distance_inside_boundary {
-                            // eprintln!("inside boundary");
-                            let t = rec1.t + hit_distance / ray_length;
-                            let p = r.point_at_parameter(t);
-                            let normal = Vec3::new(1.0, 0.0, 0.0);  // arbitrary
-                            Some(HitRecord::new(
-                                t,
-                                p,
-                                normal,
-                                self.phase_function.clone(),
-                                0.0,
-                                0.0
-                            ))
-                        }
-                        else {
-                            // eprintln!("hit but not inside boundary");
-                            None
-                        }
+/// What a ray tracked through a medium with per channel coefficients runs into: a particle that scatters some
+/// channels, or for channels with less than the largest extinction, nothing.
+struct MediumParticles {
+    phase_function: Arc<dyn PhaseFunction>,
+    albedo: Arc<dyn Texture>,
+    sigma_s: Vec3,
+    /// the extinction the channels lack, which lets them carry straight on
+    sigma_n: Vec3,
+    majorant: f64
+}
+
+impl MediumParticles {
+    fn scattering(&self, hit_record: &HitRecord) -> Vec3 {
+        self.sigma_s * self.albedo.value(hit_record.u, hit_record.v, hit_record.p) / self.majorant
+    }
+
+    fn scatter_probability(&self, scattering: Vec3) -> f64 {
+        let (scattered, passed) = (scattering.x() + scattering.y() + scattering.z(), (self.sigma_n.x() + self.sigma_n.y() + self.sigma_n.z()) / self.majorant);
+        if scattered + passed > 0.0 { scattered / (scattered + passed) } else { 0.0 }
+    }
+}
+
+impl Material for MediumParticles {
+    fn sample(&self, hit_record: &HitRecord, wo: Vec3, u: [f64; 3]) -> Option<BsdfSample> {
+        let scattering = self.scattering(hit_record);
+        let probability = self.scatter_probability(scattering);
+        if u[2] < probability {
+            let wi = self.phase_function.sample(wo, [u[0], u[1]]);
+            let pdf = probability * self.phase_function.p(wo, wi);
+            Some(BsdfSample{wi, weight: scattering / probability, pdf, lobe: Lobe::DIFFUSE | Lobe::REFLECTION | Lobe::TRANSMISSION})
+        }
+        else if probability < 1.0 {
+            Some(BsdfSample{wi: -wo.normalize(), weight: self.sigma_n / (self.majorant * (1.0 - probability)), pdf: 1.0, lobe: Lobe::SPECULAR | Lobe::TRANSMISSION})
+        }
+        else {
+            None
+        }
+    }
+
+    fn eval(&self, hit_record: &HitRecord, wo: Vec3, wi: Vec3) -> Vec3 {
+        self.scattering(hit_record) * self.phase_function.p(wo, wi)
+    }
+
+    fn pdf(&self, hit_record: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        self.scatter_probability(self.scattering(hit_record)) * self.phase_function.p(wo, wi)
+    }
+}
+
+/// The ranges of t between t_min and t_max where the ray is inside the boundary, in order. The boundary's normals
+/// must point outwards, which is how a ray starting inside it is told apart from one starting outside.
+pub(crate) fn boundary_intervals(boundary: &dyn Hittable, r: Ray, t_min: f64, t_max: f64) -> Vec<(f64, f64)> {
+    let mut intervals = Vec::new();
+    // the start of the current interval while inside, and the t searched from
+    let mut inside_from: Option<f64> = None;
+    let mut t = t_min.max(0.0);
+    for _ in 0..MAX_CROSSINGS {
+        match boundary.hit(r, t, t_max) {
+            Some(rec) => {
+                let leaving = Vec3::dot(rec.normal, r.direction) > 0.0;
+                match (inside_from, leaving) {
+                    (Some(start), true) => {
+                        intervals.push((start, rec.t));
+                        inside_from = None;
                     }
-                    None => None
+                    // leaving without having entered, so the ray was inside since the last crossing
+                    (None, true) => intervals.push((t, rec.t)),
+                    (None, false) => inside_from = Some(rec.t),
+                    // entering an overlapping part of the boundary while already inside
+                    (Some(_), false) => ()
                 }
-            },
-            None => None
+                t = rec.t + super::EPSILON;
+            }
+            None => break
         }
     }
+    if let Some(start) = inside_from {
+        intervals.push((start, t_max));
+    }
+    intervals.retain(|&(start, end)| start < end);
+    intervals
+}
+
+/// Samples the first collision in a medium of constant density between start and end, if there is one.
+fn constant_collision(density: f64, phase_function: &Arc<dyn Material>, r: Ray, start: f64, end: f64) -> Option<HitRecord> {
+    let ray_length = r.direction.length();
+    let hit_distance = -(1.0 / density) * drand48().ln();
+    if hit_distance < (end - start) * ray_length {
+        let t = start + hit_distance / ray_length;
+        Some(medium_hit(r, t, phase_function))
+    }
+    else {
+        None
+    }
+}
+
+/// The fraction of each channel of light that makes it distance through a medium with the extinction sigma_t.
+pub(crate) fn beer_lambert(sigma_t: Vec3, distance: f64) -> Vec3 {
+    // checked so a channel nothing stops gets through an infinite distance
+    let channel = |sigma: f64| if sigma > 0.0 { (-sigma * distance).exp() } else { 1.0 };
+    Vec3::new(channel(sigma_t.x()), channel(sigma_t.y()), channel(sigma_t.z()))
+}
+
+/// The fraction of each channel of light that makes it through a medium along the ray between t_min and t_max.
+pub(crate) fn medium_transmittance(medium: &dyn Medium, r: Ray, t_min: f64, t_max: f64) -> Vec3 {
+    let mut transmittance = Vec3::new(1.0, 1.0, 1.0);
+    for (start, end) in boundary_intervals(medium.boundary(), r, t_min, t_max) {
+        transmittance *= medium.estimate_transmittance(r, start, end);
+    }
+    transmittance
+}
+
+/// The hit with a particle at t. Particles have no surface, so the normal is zero.
+pub(crate) fn medium_hit(r: Ray, t: f64, phase_function: &Arc<dyn Material>) -> HitRecord {
+    HitRecord::new(t, r.point_at_parameter(t), Vec3::zero(), phase_function.clone(), 0.0, 0.0)
+}
+
+impl Medium for ConstantMedium {
+    fn boundary(&self) -> &dyn Hittable {
+        self.boundary.as_ref()
+    }
+
+    fn sample_collision(&self, r: Ray, start: f64, end: f64) -> Option<HitRecord> {
+        constant_collision(self.density, &self.phase_function, r, start, end)
+    }
+
+    fn estimate_transmittance(&self, r: Ray, start: f64, end: f64) -> Vec3 {
+        beer_lambert(self.sigma_t, (end - start) * r.direction.length())
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        // distances are memoryless, so sampling afresh in each interval is the same as carrying on across them
+        boundary_intervals(self.boundary.as_ref(), r, t_min, t_max).into_iter()
+            .find_map(|(start, end)| self.sample_collision(r, start, end))
+    }
+
+    fn transmittance(&self, r: Ray, t_min: f64, t_max: f64) -> Vec3 {
+        medium_transmittance(self, r, t_min, t_max)
+    }
 
     fn bounding_box(&self) -> Option<AABB> {
         self.boundary.bounding_box()
     }
-}
\ No newline at end of file
+}
+
+/// Media that overlap, where only the one with the highest priority fills the space they share, like an air
+/// bubble in water or smoke in a foggy room. Media are combined by adding them to one of these instead of to
+/// the scene.
+pub struct NestedMedia {
+    media: Vec<(u32, Arc<dyn Medium>)>
+}
+
+impl NestedMedia {
+    pub fn new() -> Self {
+        NestedMedia{media: Vec::new()}
+    }
+
+    pub fn add(&mut self, medium: Arc<dyn Medium>, priority: u32) {
+        self.media.push((priority, medium));
+    }
+}
+
+impl Default for NestedMedia {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NestedMedia {
+    /// The pieces of the ray between t_min and t_max inside any of the media, in order, each with the medium of the
+    /// highest priority there.
+    fn pieces(&self, r: Ray, t_min: f64, t_max: f64) -> Vec<(f64, f64, &dyn Medium)> {
+        let intervals: Vec<Vec<(f64, f64)>> = self.media.iter().map(|(_, medium)| boundary_intervals(medium.boundary(), r, t_min, t_max)).collect();
+        let mut crossings: Vec<f64> = intervals.iter().flatten().flat_map(|&(start, end)| vec![start, end]).collect();
+        crossings.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        crossings.dedup();
+        crossings.windows(2).filter_map(|piece| {
+            let middle = 0.5 * (piece[0] + piece[1]);
+            self.media.iter().zip(intervals.iter())
+                .filter(|(_, intervals)| intervals.iter().any(|&(start, end)| start <= middle && middle < end))
+                .max_by_key(|((priority, _), _)| *priority)
+                .map(|((_, medium), _)| (piece[0], piece[1], medium.as_ref()))
+        }).collect()
+    }
+}
+
+impl Hittable for NestedMedia {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.pieces(r, t_min, t_max).into_iter().find_map(|(start, end, medium)| medium.sample_collision(r, start, end))
+    }
+
+    fn transmittance(&self, r: Ray, t_min: f64, t_max: f64) -> Vec3 {
+        self.pieces(r, t_min, t_max).into_iter()
+            .fold(Vec3::new(1.0, 1.0, 1.0), |transmittance, (start, end, medium)| transmittance * medium.estimate_transmittance(r, start, end))
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.media.iter()
+            .map(|(_, medium)| medium.boundary().bounding_box())
+            .try_fold(None, |bounds: Option<AABB>, b| b.map(|b| Some(bounds.map_or(b, |bounds| AABB::merge(&bounds, &b)))))
+            .flatten()
+    }
+}
+
+/// A medium of constant density filling all the space around the objects of a scene, for fog. It wraps the
+/// scene's objects, and has their bounding box. Light from the background and from directional lights can't make
+/// it through infinite fog, so scenes in it need lights within it.
+pub struct GlobalMedium {
+    objects: Arc<dyn Hittable>,
+    density: f64,
+    phase_function: Arc<dyn Material>
+}
+
+impl GlobalMedium {
+    pub fn new(objects: Arc<dyn Hittable>, density: f64, phase_function: Arc<dyn Material>) -> Self {
+        GlobalMedium{objects, density, phase_function}
+    }
+}
+
+impl Hittable for GlobalMedium {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let rec = self.objects.hit(r, t_min, t_max);
+        let end = rec.as_ref().map_or(t_max, |rec| rec.t);
+        constant_collision(self.density, &self.phase_function, r, t_min.max(0.0), end).or(rec)
+    }
+
+    fn transmittance(&self, r: Ray, t_min: f64, t_max: f64) -> Vec3 {
+        let transmittance = self.objects.transmittance(r, t_min, t_max);
+        if transmittance.is_zero_length() {
+            return transmittance;
+        }
+        let density = Vec3::new(self.density, self.density, self.density);
+        transmittance * beer_lambert(density, (t_max - t_min.max(0.0)) * r.direction.length())
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.objects.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytrace::sphere::Sphere;
+    use crate::raytrace::hittable_list::HittableList;
+    use crate::raytrace::material::{Isotropic, offset_origin};
+    use crate::raytrace::phase::IsotropicPhase;
+
+    fn escaped(medium: &dyn Hittable, r: Ray) -> f64 {
+        let n = 20000;
+        (0..n).filter(|_| medium.hit(r, 0.0, f64::MAX).is_none()).count() as f64 / n as f64
+    }
+
+    fn sphere(center: Vec3, radius: f64) -> Arc<dyn Hittable> {
+        Arc::new(Sphere::new(center, radius, Arc::new(Isotropic::new(Arc::new(ConstantTexture::scalar(1.0))))))
+    }
+
+    #[test]
+    fn intervals() {
+        let r = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        // two separate pieces
+        let material = Arc::new(Isotropic::new(Arc::new(ConstantTexture::scalar(1.0))));
+        let boundary = HittableList::from_vec(vec![
+            Box::new(Sphere::new(Vec3::new(0.0, 0.0, -1.0), 1.0, material.clone())),
+            Box::new(Sphere::new(Vec3::new(0.0, 0.0, 2.0), 0.5, material))
+        ]);
+        let intervals = boundary_intervals(&boundary, r, 0.0, f64::MAX);
+        assert_eq!(intervals.len(), 2);
+        assert!((intervals[0].0 - 3.0).abs() < 1e-9 && (intervals[0].1 - 5.0).abs() < 1e-9);
+        assert!((intervals[1].0 - 6.5).abs() < 1e-9 && (intervals[1].1 - 7.5).abs() < 1e-9);
+        // starting inside
+        let intervals = boundary_intervals(&boundary, r, 4.0, 7.0);
+        assert_eq!(intervals.len(), 2);
+        assert!((intervals[0].0 - 4.0).abs() < 1e-9 && (intervals[1].1 - 7.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn transmittance() {
+        let phase_function: Arc<dyn Material> = Arc::new(Isotropic::new(Arc::new(ConstantTexture::scalar(1.0))));
+        let medium = ConstantMedium::new(sphere(Vec3::zero(), 1.0), 0.7, phase_function.clone());
+        // from inside, across the radius
+        let inside = escaped(&medium, Ray::new(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), 0.0));
+        assert!((inside - (-0.7f64).exp()).abs() < 0.02, "{}", inside);
+        assert!(medium.hit(Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::unit_z(), 0.0), 0.0, f64::MAX).is_none_or(|rec| rec.normal.is_zero_length()));
+
+        // a bubble of nothing inside the medium takes out the middle
+        let mut nested = NestedMedia::new();
+        nested.add(Arc::new(medium), 0);
+        nested.add(Arc::new(ConstantMedium::new(sphere(Vec3::zero(), 0.5), 0.0, phase_function.clone())), 1);
+        let r = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::unit_z(), 0.0);
+        let through = escaped(&nested, r);
+        assert!((through - (-0.7f64).exp()).abs() < 0.02, "{}", through);
+        // which shadow rays find without sampling
+        assert!((nested.transmittance(r, 0.0, f64::MAX) - Vec3::new(1.0, 1.0, 1.0) * (-0.7f64).exp()).length() < 1e-9);
+
+        // fog thins out the light reaching a sphere
+        let fog = GlobalMedium::new(sphere(Vec3::new(0.0, 0.0, 3.0), 1.0), 0.2, phase_function);
+        let n = 20000;
+        let reached = (0..n).filter(|_| fog.hit(Ray::new(Vec3::zero(), Vec3::unit_z(), 0.0), 0.0, f64::MAX).is_some_and(|rec| !rec.normal.is_zero_length())).count() as f64 / n as f64;
+        assert!((reached - (-0.4f64).exp()).abs() < 0.02, "{}", reached);
+        let r = Ray::new(Vec3::zero(), Vec3::unit_z(), 0.0);
+        assert!((fog.transmittance(r, 0.0, 2.0).x() - (-0.4f64).exp()).abs() < 1e-9);
+        assert!(fog.transmittance(r, 0.0, 3.0).is_zero_length());
+    }
+
+    #[test]
+    fn per_channel_extinction() {
+        // an absorbing medium tints the light by its extinction in each channel
+        let sigma_a = Vec3::new(0.2, 0.6, 1.2);
+        let medium = ConstantMedium::with_coefficients(sphere(Vec3::zero(), 1.0), Vec3::zero(), sigma_a, Arc::new(IsotropicPhase{}), None);
+        let n = 20000;
+        let mut total = Vec3::zero();
+        for _ in 0..n {
+            let mut r = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::unit_z(), 0.0);
+            let mut weight = Vec3::new(1.0, 1.0, 1.0);
+            while let Some(rec) = medium.hit(r, 0.0, f64::MAX) {
+                match rec.material.sample(&rec, -r.direction, [drand48(), drand48(), drand48()]) {
+                    Some(sample) => {
+                        weight *= sample.weight;
+                        r = Ray::new(offset_origin(&rec, sample.wi), sample.wi, 0.0);
+                    }
+                    None => {
+                        weight = Vec3::zero();
+                        break;
+                    }
+                }
+            }
+            total += weight;
+        }
+        let expected = Vec3::new((-0.4f64).exp(), (-1.2f64).exp(), (-2.4f64).exp());
+        assert!((total / n as f64 - expected).length() < 0.02, "{:?} {:?}", total / n as f64, expected);
+        assert!((medium.transmittance(Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::unit_z(), 0.0), 0.0, f64::MAX) - expected).length() < 1e-9);
+    }
+}
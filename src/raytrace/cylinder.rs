@@ -0,0 +1,92 @@
+use std::sync::Arc;
+use super::Vec3;
+use super::ray::Ray;
+use super::hittable::{Hittable, HitRecord};
+use super::material::Material;
+use super::aabb::AABB;
+use super::disk::{azimuth, hit_disk};
+use std::f64::consts::PI;
+
+/// A cylinder standing on the xz plane with the center of its base at base, going up the y axis. It is capped at
+/// both ends unless set otherwise, which leaves an open tube.
+pub struct Cylinder {
+    base: Vec3,
+    radius: f64,
+    height: f64,
+    capped: bool,
+    material: Arc<dyn Material>
+}
+
+impl Cylinder {
+    pub fn new(base: Vec3, radius: f64, height: f64, material: Arc<dyn Material>) -> Self {
+        Cylinder{base, radius, height, capped: true, material}
+    }
+
+    pub fn set_capped(&mut self, capped: bool) {
+        self.capped = capped;
+    }
+}
+
+impl Hittable for Cylinder {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let mut closest: Option<HitRecord> = None;
+        let (o, d) = (r.origin - self.base, r.direction);
+        let a = d.x() * d.x() + d.z() * d.z();
+        let b = o.x() * d.x() + o.z() * d.z();
+        let c = o.x() * o.x() + o.z() * o.z() - self.radius * self.radius;
+        let discriminant = b * b - a * c;
+        if a > 0.0 && discriminant > 0.0 {
+            for &t in &[(-b - discriminant.sqrt()) / a, (-b + discriminant.sqrt()) / a] {
+                let y = o.y() + t * d.y();
+                if t > t_min && t < t_max && y >= 0.0 && y <= self.height {
+                    let p = r.point_at_parameter(t);
+                    let normal = Vec3::new(p.x() - self.base.x(), 0.0, p.z() - self.base.z()) / self.radius;
+                    let u = azimuth(normal.x(), normal.z()) / (2.0 * PI);
+                    closest = Some(HitRecord::new(t, p, normal, Arc::clone(&self.material), u, y / self.height));
+                    break;
+                }
+            }
+        }
+        if self.capped {
+            let top = self.base + Vec3::new(0.0, self.height, 0.0);
+            for &(center, facing) in &[(self.base, -1.0), (top, 1.0)] {
+                let t_max = closest.as_ref().map_or(t_max, |rec| rec.t);
+                if let Some(rec) = hit_disk(center, 0.0, self.radius, facing, &self.material, r, t_min, t_max) {
+                    closest = Some(rec);
+                }
+            }
+        }
+        closest
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        let extent = Vec3::new(self.radius, 0.0, self.radius);
+        Some(AABB::new(self.base - extent, self.base + extent + Vec3::new(0.0, self.height, 0.0)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytrace::material::NormalMaterial;
+
+    #[test]
+    fn cylinder_hit() {
+        let mut cylinder = Cylinder::new(Vec3::zero(), 1.0, 2.0, Arc::new(NormalMaterial::new()));
+        let rec = cylinder.hit(Ray::new(Vec3::new(-5.0, 1.0, 0.0), Vec3::unit_x(), 0.0), 0.0, f64::MAX).unwrap();
+        assert!((rec.t - 4.0).abs() < 1e-12 && (rec.v - 0.5).abs() < 1e-12 && (rec.u - 0.5).abs() < 1e-12);
+        assert!((rec.normal - Vec3::new(-1.0, 0.0, 0.0)).length() < 1e-12);
+
+        let down = Ray::new(Vec3::new(0.5, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let rec = cylinder.hit(down, 0.0, f64::MAX).unwrap();
+        assert!((rec.t - 3.0).abs() < 1e-12);
+        assert_eq!(rec.normal, Vec3::unit_y());
+
+        // an open tube is seen straight through, and its normals point out even from inside
+        cylinder.set_capped(false);
+        assert!(cylinder.hit(down, 0.0, f64::MAX).is_none());
+        let rec = cylinder.hit(Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::unit_x(), 0.0), 0.0, f64::MAX).unwrap();
+        assert!((rec.t - 1.0).abs() < 1e-12);
+        assert!((rec.normal - Vec3::unit_x()).length() < 1e-12);
+    }
+}
@@ -0,0 +1,111 @@
+use std::sync::Arc;
+use super::Vec3;
+use super::ray::Ray;
+use super::hittable::{Hittable, HitRecord};
+use super::material::Material;
+use super::aabb::AABB;
+use std::f64::consts::PI;
+
+/// A flat disk lying in the xz plane around center and facing up the y axis.
+pub struct Disk {
+    center: Vec3,
+    radius: f64,
+    material: Arc<dyn Material>
+}
+
+impl Disk {
+    pub fn new(center: Vec3, radius: f64, material: Arc<dyn Material>) -> Self {
+        Disk{center, radius, material}
+    }
+}
+
+impl Hittable for Disk {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        hit_disk(self.center, 0.0, self.radius, 1.0, &self.material, r, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        disk_bounding_box(self.center, self.radius)
+    }
+}
+
+/// A flat ring lying in the xz plane around center and facing up the y axis, between the inner and outer radius.
+pub struct Annulus {
+    center: Vec3,
+    inner_radius: f64,
+    outer_radius: f64,
+    material: Arc<dyn Material>
+}
+
+impl Annulus {
+    pub fn new(center: Vec3, inner_radius: f64, outer_radius: f64, material: Arc<dyn Material>) -> Self {
+        assert!(inner_radius >= 0.0 && inner_radius < outer_radius, "The inner radius must be less than the outer one");
+        Annulus{center, inner_radius, outer_radius, material}
+    }
+}
+
+impl Hittable for Annulus {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        hit_disk(self.center, self.inner_radius, self.outer_radius, 1.0, &self.material, r, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        disk_bounding_box(self.center, self.outer_radius)
+    }
+}
+
+/// The angle around the y axis of a point at x, z, between 0 and 2π.
+pub(crate) fn azimuth(x: f64, z: f64) -> f64 {
+    let phi = z.atan2(x);
+    if phi < 0.0 { phi + 2.0 * PI } else { phi }
+}
+
+/// Hits the ring between inner_radius and outer_radius around center in the xz plane, with the normal pointing
+/// up the y axis when facing is 1 and down it when -1. u goes around the ring and v out from the inner radius.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn hit_disk(center: Vec3, inner_radius: f64, outer_radius: f64, facing: f64, material: &Arc<dyn Material>, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    if r.direction.y() == 0.0 {
+        return None;
+    }
+    let t = (center.y() - r.origin.y()) / r.direction.y();
+    if t <= t_min || t >= t_max {
+        return None;
+    }
+    let p = r.point_at_parameter(t);
+    let (x, z) = (p.x() - center.x(), p.z() - center.z());
+    let distance = (x * x + z * z).sqrt();
+    if distance > outer_radius || distance < inner_radius {
+        return None;
+    }
+    let u = azimuth(x, z) / (2.0 * PI);
+    let v = (distance - inner_radius) / (outer_radius - inner_radius);
+    Some(HitRecord::new(t, p, Vec3::new(0.0, facing, 0.0), Arc::clone(material), u, v))
+}
+
+fn disk_bounding_box(center: Vec3, radius: f64) -> Option<AABB> {
+    // padded so the box isn't flat
+    let extent = Vec3::new(radius, super::EPSILON, radius);
+    Some(AABB::new(center - extent, center + extent))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytrace::material::NormalMaterial;
+
+    #[test]
+    fn annulus_hit() {
+        let annulus = Annulus::new(Vec3::new(0.0, 1.0, 0.0), 0.5, 1.0, Arc::new(NormalMaterial::new()));
+        let down = Vec3::new(0.0, -1.0, 0.0);
+        assert!(annulus.hit(Ray::new(Vec3::new(0.2, 3.0, 0.0), down, 0.0), 0.0, f64::MAX).is_none());
+        assert!(annulus.hit(Ray::new(Vec3::new(1.2, 3.0, 0.0), down, 0.0), 0.0, f64::MAX).is_none());
+
+        let rec = annulus.hit(Ray::new(Vec3::new(0.0, 3.0, -0.75), down, 0.0), 0.0, f64::MAX).unwrap();
+        assert!((rec.t - 2.0).abs() < 1e-12);
+        assert_eq!(rec.normal, Vec3::unit_y());
+        assert!((rec.u - 0.75).abs() < 1e-12 && (rec.v - 0.5).abs() < 1e-12);
+
+        // seen edge on it isn't there
+        assert!(Disk::new(Vec3::zero(), 1.0, Arc::new(NormalMaterial::new())).hit(Ray::new(Vec3::new(-2.0, 0.0, 0.0), Vec3::unit_x(), 0.0), 0.0, f64::MAX).is_none());
+    }
+}
@@ -0,0 +1,139 @@
+//! Image based lighting from an equirectangular environment map.
+
+use super::Vec3;
+use super::ray::Ray;
+use super::hdr_image::HdrImage;
+use super::quaternion::Quaternion;
+use super::renderer::Background;
+use super::sampling::Distribution2D;
+use super::util::{drand48, luminance};
+use std::f64::consts::PI;
+use std::io;
+use std::path::Path;
+
+/// A background lit by an equirectangular (latitude/longitude) image surrounding the scene.
+/// The center of the image is in the -z direction and the top of the image is +y, before rotation.
+pub struct EnvironmentMap {
+    image: HdrImage,
+    rotation: Quaternion,
+    intensity: f64,
+    distribution: Distribution2D
+}
+
+impl EnvironmentMap {
+    pub fn new(image: HdrImage, rotation: Quaternion, intensity: f64) -> Self {
+        // weight each pixel by its brightness and the solid angle it covers, which shrinks towards the poles
+        let mut weights = Vec::with_capacity(image.width * image.height);
+        for y in 0..image.height {
+            let sin_theta = (PI * (y as f64 + 0.5) / image.height as f64).sin();
+            for x in 0..image.width {
+                weights.push(luminance(image.get(x, y)) * sin_theta);
+            }
+        }
+        let distribution = Distribution2D::new(&weights, image.width, image.height);
+        EnvironmentMap{image, rotation, intensity, distribution}
+    }
+
+    /// Loads the environment map from an image file, see HdrImage::load for the supported formats.
+    pub fn load(path: &Path, rotation: Quaternion, intensity: f64) -> io::Result<Self> {
+        Ok(Self::new(HdrImage::load(path)?, rotation, intensity))
+    }
+
+    /// Converts a direction in world space to (u, v) image coordinates and the sine of the polar angle.
+    fn direction_to_uv(&self, direction: Vec3) -> (f64, f64, f64) {
+        let d = (self.rotation.conjugate() * direction).normalize();
+        let u = 0.5 + d.x().atan2(-d.z()) / (2.0 * PI);
+        let theta = d.y().clamp(-1.0, 1.0).acos();
+        (u, theta / PI, theta.sin())
+    }
+
+    fn uv_to_direction(&self, u: f64, v: f64) -> Vec3 {
+        let phi = (u - 0.5) * 2.0 * PI;
+        let theta = v * PI;
+        let d = Vec3::new(theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos());
+        self.rotation * d
+    }
+}
+
+impl Background for EnvironmentMap {
+    fn get(&self, ray: Ray) -> Vec3 {
+        let (u, v, _) = self.direction_to_uv(ray.direction);
+        self.intensity * self.image.sample_bilinear(u, v)
+    }
+
+    fn sample(&self) -> Option<(Vec3, Vec3, f64)> {
+        let ((u, v), pdf_uv) = self.distribution.sample(drand48(), drand48());
+        if pdf_uv == 0.0 {
+            return None;
+        }
+        let sin_theta = (v * PI).sin();
+        if sin_theta == 0.0 {
+            return None;
+        }
+        let direction = self.uv_to_direction(u, v);
+        // the image covers 2pi by pi radians, and each pixel's solid angle is scaled by sin(theta)
+        let pdf = pdf_uv / (2.0 * PI * PI * sin_theta);
+        let radiance = self.get(Ray::new(Vec3::zero(), direction, 0.0));
+        Some((direction, radiance, pdf))
+    }
+
+    fn pdf(&self, direction: Vec3) -> f64 {
+        let (u, v, sin_theta) = self.direction_to_uv(direction);
+        if sin_theta == 0.0 {
+            return 0.0;
+        }
+        self.distribution.pdf(u, v) / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_map() -> EnvironmentMap {
+        // a dark map with one bright pixel
+        let mut data = vec![Vec3::new(0.1, 0.1, 0.1); 8 * 4];
+        data[5 + 8] = Vec3::new(100.0, 100.0, 100.0);
+        EnvironmentMap::new(HdrImage::new(8, 4, data), Quaternion::from_rotation_axis(Vec3::unit_y(), 0.3), 2.0)
+    }
+
+    #[test]
+    fn uv_round_trip() {
+        let map = test_map();
+        let direction = Vec3::new(0.3, -0.5, 0.8).normalize();
+        let (u, v, _) = map.direction_to_uv(direction);
+        assert!((map.uv_to_direction(u, v) - direction).length() < 1e-9);
+    }
+
+    #[test]
+    fn sample_pdf() {
+        let map = test_map();
+        let mut bright = 0;
+        for _ in 0..1000 {
+            let (direction, radiance, pdf) = map.sample().unwrap();
+            assert!((map.pdf(direction) - pdf).abs() < 1e-6 * pdf);
+            assert!((radiance - map.get(Ray::new(Vec3::zero(), direction, 0.0))).length() < 1e-9);
+            if luminance(radiance) > 20.0 {
+                bright += 1;
+            }
+        }
+        // almost all of the energy is in the bright pixel so most samples should be near it
+        assert!(bright > 500);
+    }
+
+    #[test]
+    fn pdf_integrates_to_one() {
+        let map = test_map();
+        let n = 200;
+        let mut total = 0.0;
+        for i in 0..n {
+            let theta = PI * (i as f64 + 0.5) / n as f64;
+            for j in 0..2 * n {
+                let phi = PI * (j as f64 + 0.5) / n as f64;
+                let direction = Vec3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin());
+                total += map.pdf(direction) * theta.sin() * (PI / n as f64) * (PI / n as f64);
+            }
+        }
+        assert!((total - 1.0).abs() < 0.01);
+    }
+}
@@ -0,0 +1,391 @@
+//! Path guiding, after Müller et al, "Practical Path Guiding for Efficient Light-Transport Simulation". The scene is
+//! split into regions by a binary tree, and each region learns how much light arrives from each direction in a
+//! quadtree over the sphere. Paths then pick directions from the learned distribution as well as from the material,
+//! so they head towards where the light comes from instead of wandering.
+
+use super::Vec3;
+use super::aabb::AABB;
+use super::hittable::HitRecord;
+use super::material::{BsdfSample, Lobe};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// The probability of picking a direction from the learned distribution rather than the material.
+const GUIDE_PROBABILITY: f64 = 0.5;
+/// A region is split once it records more than this times the square root of the samples per pixel of a pass.
+const SPATIAL_THRESHOLD: f64 = 12000.0;
+/// A direction quadrant is subdivided if it receives more than this fraction of the light in its region.
+const ENERGY_THRESHOLD: f64 = 0.01;
+const MAX_SPATIAL_DEPTH: usize = 24;
+const MAX_DIRECTIONAL_DEPTH: usize = 20;
+
+/// An f64 that can be added to from many threads.
+struct AtomicF64(AtomicU64);
+
+impl AtomicF64 {
+    fn new(value: f64) -> Self {
+        AtomicF64(AtomicU64::new(value.to_bits()))
+    }
+
+    fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    fn add(&self, value: f64) {
+        let mut current = self.0.load(Ordering::Relaxed);
+        loop {
+            let new = (f64::from_bits(current) + value).to_bits();
+            match self.0.compare_exchange_weak(current, new, Ordering::Relaxed, Ordering::Relaxed) {
+                Ok(_) => return,
+                Err(actual) => current = actual
+            }
+        }
+    }
+}
+
+/// Maps a unit direction to the unit square, preserving area so densities only differ by the sphere's area.
+fn to_square(direction: Vec3) -> (f64, f64) {
+    let x = 0.5 * (direction.z().clamp(-1.0, 1.0) + 1.0);
+    let phi = direction.y().atan2(direction.x());
+    let y = phi / (2.0 * std::f64::consts::PI);
+    (x.min(1.0 - 1e-12), if y < 0.0 { y + 1.0 } else { y }.min(1.0 - 1e-12))
+}
+
+fn from_square(x: f64, y: f64) -> Vec3 {
+    let cos_theta = 2.0 * x - 1.0;
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * std::f64::consts::PI * y;
+    Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+/// The quadrant of a node a point lies in, numbered with x in the low bit, and the point within the quadrant.
+fn quadrant(x: f64, y: f64) -> (usize, f64, f64) {
+    let (ix, iy) = ((x >= 0.5) as usize, (y >= 0.5) as usize);
+    (ix + 2 * iy, 2.0 * x - ix as f64, 2.0 * y - iy as f64)
+}
+
+#[derive(Clone, Copy)]
+struct QuadNode {
+    /// the light arriving through each quadrant
+    sums: [f64; 4],
+    /// the node subdividing each quadrant, 0 for quadrants that aren't subdivided
+    children: [usize; 4]
+}
+
+/// The light arriving at a region from each direction, as a quadtree over the directions mapped to the unit square.
+#[derive(Clone)]
+struct DirectionalTree {
+    nodes: Vec<QuadNode>
+}
+
+impl DirectionalTree {
+    fn new() -> Self {
+        DirectionalTree{nodes: vec![QuadNode{sums: [0.0; 4], children: [0; 4]}]}
+    }
+
+    fn total(&self) -> f64 {
+        self.nodes[0].sums.iter().sum()
+    }
+
+    /// Picks a direction in proportion to the light arriving from it.
+    fn sample(&self, mut u1: f64, u2: f64) -> Vec3 {
+        let (mut node, mut origin, mut size) = (0, (0.0, 0.0), 1.0);
+        loop {
+            let sums = self.nodes[node].sums;
+            let total: f64 = sums.iter().sum();
+            let mut picked = 3;
+            for (i, &sum) in sums.iter().enumerate() {
+                let p = if total > 0.0 { sum / total } else { 0.25 };
+                if u1 < p || i == 3 {
+                    picked = i;
+                    u1 = if p > 0.0 { (u1 / p).min(1.0 - 1e-12) } else { 0.0 };
+                    break;
+                }
+                u1 -= p;
+            }
+            size *= 0.5;
+            origin = (origin.0 + size * (picked & 1) as f64, origin.1 + size * (picked >> 1) as f64);
+            match self.nodes[node].children[picked] {
+                0 => return from_square(origin.0 + size * u1, origin.1 + size * u2),
+                child => node = child
+            }
+        }
+    }
+
+    /// The solid angle pdf of sample picking direction.
+    fn pdf(&self, direction: Vec3) -> f64 {
+        let (mut x, mut y) = to_square(direction);
+        let (mut node, mut pdf) = (0, 1.0);
+        loop {
+            let sums = self.nodes[node].sums;
+            let total: f64 = sums.iter().sum();
+            let (i, qx, qy) = quadrant(x, y);
+            pdf *= if total > 0.0 { 4.0 * sums[i] / total } else { 1.0 };
+            match self.nodes[node].children[i] {
+                0 => return pdf / (4.0 * std::f64::consts::PI),
+                child => {
+                    node = child;
+                    x = qx;
+                    y = qy;
+                }
+            }
+        }
+    }
+
+    /// Builds a tree from the light recorded in each node of this one, subdividing quadrants that received a lot
+    /// of it and merging those that didn't.
+    fn refine(&self, recorded: &[[f64; 4]]) -> DirectionalTree {
+        let total: f64 = recorded[0].iter().sum();
+        let mut nodes = Vec::new();
+        self.refine_node(recorded, Some(0), recorded[0], total, 1, &mut nodes);
+        DirectionalTree{nodes}
+    }
+
+    fn refine_node(&self, recorded: &[[f64; 4]], old: Option<usize>, sums: [f64; 4], total: f64, depth: usize, nodes: &mut Vec<QuadNode>) -> usize {
+        let index = nodes.len();
+        nodes.push(QuadNode{sums, children: [0; 4]});
+        for (i, &sum) in sums.iter().enumerate() {
+            if sum > ENERGY_THRESHOLD * total && depth < MAX_DIRECTIONAL_DEPTH {
+                let old_child = old.map(|old| self.nodes[old].children[i]).filter(|&child| child != 0);
+                // a newly subdivided quadrant spreads its light evenly over its children
+                let child_sums = old_child.map_or([0.25 * sum; 4], |child| recorded[child]);
+                let child = self.refine_node(recorded, old_child, child_sums, total, depth + 1, nodes);
+                nodes[index].children[i] = child;
+            }
+        }
+        index
+    }
+}
+
+/// A region of the scene, with the distribution paths in it sample from and the light recorded for the next one.
+struct Region {
+    tree: DirectionalTree,
+    recorded: Vec<[AtomicF64; 4]>,
+    samples: AtomicUsize
+}
+
+impl Region {
+    fn new(tree: DirectionalTree) -> Self {
+        let recorded = tree.nodes.iter().map(|_| [AtomicF64::new(0.0), AtomicF64::new(0.0), AtomicF64::new(0.0), AtomicF64::new(0.0)]).collect();
+        Region{tree, recorded, samples: AtomicUsize::new(0)}
+    }
+
+    fn record(&self, direction: Vec3, value: f64) {
+        let (mut x, mut y) = to_square(direction);
+        let mut node = 0;
+        loop {
+            let (i, qx, qy) = quadrant(x, y);
+            self.recorded[node][i].add(value);
+            match self.tree.nodes[node].children[i] {
+                0 => return,
+                child => {
+                    node = child;
+                    x = qx;
+                    y = qy;
+                }
+            }
+        }
+    }
+}
+
+enum SpatialNode {
+    Inner{axis: usize, children: [usize; 2]},
+    Leaf(usize)
+}
+
+/// The learned distributions of light over the scene. Paths sample and record through it during a pass of the
+/// image, and refine between passes.
+pub struct PathGuide {
+    bounds: AABB,
+    nodes: Vec<SpatialNode>,
+    regions: Vec<Region>,
+    passes: u32
+}
+
+impl PathGuide {
+    /// Creates a guide that hasn't learned anything, which leaves the sampling to the materials.
+    pub fn new(bounds: AABB) -> Self {
+        PathGuide{bounds, nodes: vec![SpatialNode::Leaf(0)], regions: vec![Region::new(DirectionalTree::new())], passes: 0}
+    }
+
+    fn region(&self, p: Vec3) -> &Region {
+        let (mut min, mut max) = (self.bounds.min.elements(), self.bounds.max.elements());
+        let p = p.elements();
+        let mut node = 0;
+        loop {
+            match self.nodes[node] {
+                SpatialNode::Leaf(region) => return &self.regions[region],
+                SpatialNode::Inner{axis, children} => {
+                    let middle = 0.5 * (min[axis] + max[axis]);
+                    if p[axis] < middle {
+                        max[axis] = middle;
+                        node = children[0];
+                    }
+                    else {
+                        min[axis] = middle;
+                        node = children[1];
+                    }
+                }
+            }
+        }
+    }
+
+    /// The learned distribution at the hit, if paths there should use it.
+    fn tree_at(&self, rec: &HitRecord) -> Option<&DirectionalTree> {
+        let tree = &self.region(rec.p).tree;
+        if tree.total() > 0.0 && !rec.material.is_specular() { Some(tree) } else { None }
+    }
+
+    /// Samples a direction at the hit from the material or the learned distribution, weighted by their mixture.
+    pub fn sample(&self, rec: &HitRecord, wo: Vec3, u: [f64; 3]) -> Option<BsdfSample> {
+        let material = &rec.material;
+        let tree = match self.tree_at(rec) {
+            Some(tree) => tree,
+            None => return material.sample(rec, wo, u)
+        };
+        let (wi, lobe) = if u[0] < GUIDE_PROBABILITY {
+            let wi = tree.sample(u[0] / GUIDE_PROBABILITY, u[1]);
+            let side = if Vec3::dot(wi, rec.normal) * Vec3::dot(wo, rec.normal) > 0.0 { Lobe::REFLECTION } else { Lobe::TRANSMISSION };
+            (wi, Lobe::DIFFUSE | side)
+        }
+        else {
+            let u0 = (u[0] - GUIDE_PROBABILITY) / (1.0 - GUIDE_PROBABILITY);
+            let sample = material.sample(rec, wo, [u0, u[1], u[2]])?;
+            if sample.lobe.is_specular() {
+                return Some(BsdfSample{weight: sample.weight / (1.0 - GUIDE_PROBABILITY), ..sample});
+            }
+            (sample.wi, sample.lobe)
+        };
+        let pdf = GUIDE_PROBABILITY * tree.pdf(wi) + (1.0 - GUIDE_PROBABILITY) * material.pdf(rec, wo, wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample{wi, weight: material.eval(rec, wo, wi) / pdf, pdf, lobe})
+    }
+
+    /// The solid angle pdf of sample picking wi, leaving out specular lobes.
+    pub fn pdf(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        let material_pdf = rec.material.pdf(rec, wo, wi);
+        match self.tree_at(rec) {
+            Some(tree) => GUIDE_PROBABILITY * tree.pdf(wi) + (1.0 - GUIDE_PROBABILITY) * material_pdf,
+            None => material_pdf
+        }
+    }
+
+    /// Records the light arriving at p from direction, as estimated by a path that sampled it with pdf.
+    pub fn record(&self, p: Vec3, direction: Vec3, radiance: f64, pdf: f64) {
+        let region = self.region(p);
+        region.samples.fetch_add(1, Ordering::Relaxed);
+        if radiance > 0.0 && pdf > 0.0 && radiance.is_finite() {
+            region.record(direction.normalize(), radiance / pdf);
+        }
+    }
+
+    /// Learns from the light recorded in the last pass, which had samples_per_pixel samples, splitting regions
+    /// that got a lot of samples. The recordings are then cleared for the next pass.
+    pub fn refine(&mut self, samples_per_pixel: u32) {
+        self.passes += 1;
+        let threshold = SPATIAL_THRESHOLD * (samples_per_pixel as f64).sqrt();
+        let regions: Vec<(DirectionalTree, usize)> = self.regions.iter().map(|region| {
+            let recorded: Vec<[f64; 4]> = region.recorded.iter().map(|sums| [sums[0].get(), sums[1].get(), sums[2].get(), sums[3].get()]).collect();
+            let total: f64 = recorded[0].iter().sum();
+            // regions no light was recorded in keep what they had learned
+            let tree = if total > 0.0 { region.tree.refine(&recorded) } else { region.tree.clone() };
+            (tree, region.samples.load(Ordering::Relaxed))
+        }).collect();
+
+        let mut nodes = Vec::new();
+        let mut new_regions = Vec::new();
+        self.split(0, 0, &regions, threshold, &mut nodes, &mut new_regions, None);
+        self.nodes = nodes;
+        self.regions = new_regions;
+    }
+
+    /// Copies the spatial subtree at node into nodes, splitting regions with more samples than threshold.
+    /// inherited is a region split from above, given with the samples left to each half.
+    #[allow(clippy::too_many_arguments)]
+    fn split(&self, node: usize, depth: usize, regions: &[(DirectionalTree, usize)], threshold: f64, nodes: &mut Vec<SpatialNode>, new_regions: &mut Vec<Region>, inherited: Option<(&DirectionalTree, usize)>) -> usize {
+        let index = nodes.len();
+        let (tree, samples) = match inherited {
+            Some(inherited) => inherited,
+            None => match self.nodes[node] {
+                SpatialNode::Leaf(region) => (&regions[region].0, regions[region].1),
+                SpatialNode::Inner{axis, children} => {
+                    nodes.push(SpatialNode::Inner{axis, children: [0; 2]});
+                    let left = self.split(children[0], depth + 1, regions, threshold, nodes, new_regions, None);
+                    let right = self.split(children[1], depth + 1, regions, threshold, nodes, new_regions, None);
+                    nodes[index] = SpatialNode::Inner{axis, children: [left, right]};
+                    return index;
+                }
+            }
+        };
+        if samples as f64 > threshold && depth < MAX_SPATIAL_DEPTH {
+            // each half is assumed to get half the samples
+            nodes.push(SpatialNode::Inner{axis: depth % 3, children: [0; 2]});
+            let left = self.split(node, depth + 1, regions, threshold, nodes, new_regions, Some((tree, samples / 2)));
+            let right = self.split(node, depth + 1, regions, threshold, nodes, new_regions, Some((tree, samples / 2)));
+            nodes[index] = SpatialNode::Inner{axis: depth % 3, children: [left, right]};
+        }
+        else {
+            nodes.push(SpatialNode::Leaf(new_regions.len()));
+            new_regions.push(Region::new(tree.clone()));
+        }
+        index
+    }
+
+    /// The number of times refine has been called.
+    pub fn passes(&self) -> u32 {
+        self.passes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytrace::util::drand48;
+    use crate::raytrace::renderer::tests::{comparison_renderer, seeded_mean};
+
+    #[test]
+    fn learned_distribution() {
+        let mut guide = PathGuide::new(AABB::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0)));
+        // light arrives from a small cone around the y axis
+        let light = Vec3::unit_y();
+        for _ in 0..3 {
+            for _ in 0..50000 {
+                let p = Vec3::new(2.0 * drand48() - 1.0, 2.0 * drand48() - 1.0, 2.0 * drand48() - 1.0);
+                let direction = from_square(drand48(), drand48());
+                let radiance = if Vec3::dot(direction, light) > 0.95 { 1.0 } else { 0.0 };
+                guide.record(p, direction, radiance, 1.0 / (4.0 * std::f64::consts::PI));
+            }
+            guide.refine(1);
+        }
+        assert_eq!(guide.passes(), 3);
+        assert!(guide.regions.len() > 1);
+
+        let tree = &guide.region(Vec3::new(0.3, -0.2, 0.5)).tree;
+        let n = 10000;
+        let towards_light = (0..n).filter(|_| Vec3::dot(tree.sample(drand48(), drand48()), light) > 0.9).count();
+        assert!(towards_light as f64 > 0.8 * n as f64);
+
+        // the pdf integrates to one over the sphere
+        let grid = 512;
+        let integral: f64 = (0..grid * grid)
+            .map(|k| tree.pdf(from_square(((k % grid) as f64 + 0.5) / grid as f64, ((k / grid) as f64 + 0.5) / grid as f64)))
+            .sum::<f64>() * 4.0 * std::f64::consts::PI / (grid * grid) as f64;
+        assert!((integral - 1.0).abs() < 0.02, "{}", integral);
+        // and matches sampling
+        for _ in 0..100 {
+            let direction = tree.sample(drand48(), drand48());
+            assert!(tree.pdf(direction) > 0.0);
+        }
+    }
+
+    #[test]
+    fn matches_path_tracing() {
+        let path_tracer = comparison_renderer(64, vec![], vec![]);
+        let mut guided = comparison_renderer(64, vec![], vec![]);
+        guided.set_path_guiding(true);
+
+        let (expected, actual) = (seeded_mean(&path_tracer, 1), seeded_mean(&guided, 2));
+        assert!((expected - actual).length() < 0.05 * expected.length(), "{:?} {:?}", expected, actual);
+    }
+}
@@ -0,0 +1,246 @@
+//! Loading of high dynamic range images in linear color.
+//! Radiance (.hdr) and png files are always supported, OpenEXR (.exr) needs the `exr` feature.
+
+use super::Vec3;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+/// An image of linear RGB values, stored row by row with the top row first.
+#[derive(Clone, Debug)]
+pub struct HdrImage {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<Vec3>
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+impl HdrImage {
+    pub fn new(width: usize, height: usize, data: Vec<Vec3>) -> Self {
+        assert_eq!(data.len(), width * height, "image data does not match its size");
+        HdrImage{width, height, data}
+    }
+
+    /// Loads an image, picking the format from the file extension.
+    pub fn load(path: &Path) -> io::Result<HdrImage> {
+        let extension = path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase());
+        match extension.as_deref() {
+            Some("hdr") | Some("pic") => Self::read_radiance(&mut BufReader::new(File::open(path)?)),
+            Some("png") => Self::load_png(path),
+            Some("exr") => Self::load_exr(path),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("unsupported image format {}", path.display())))
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Vec3 {
+        self.data[x + y * self.width]
+    }
+
+    /// Looks up the image at (u, v) in [0, 1]^2 with v = 0 at the top, interpolating bilinearly.
+    /// The image wraps horizontally and is clamped vertically, as suits an equirectangular map.
+    pub fn sample_bilinear(&self, u: f64, v: f64) -> Vec3 {
+        let x = u * self.width as f64 - 0.5;
+        let y = (v * self.height as f64 - 0.5).clamp(0.0, self.height as f64 - 1.0);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let wrap = |x: f64| (x as i64).rem_euclid(self.width as i64) as usize;
+        let (x0, x1) = (wrap(x0), wrap(x0 + 1.0));
+        let y0 = y0 as usize;
+        let y1 = (y0 + 1).min(self.height - 1);
+        (1.0 - fy) * ((1.0 - fx) * self.get(x0, y0) + fx * self.get(x1, y0))
+            + fy * ((1.0 - fx) * self.get(x0, y1) + fx * self.get(x1, y1))
+    }
+
+    /// Reads a Radiance RGBE image, supporting flat and run length encoded scanlines.
+    pub fn read_radiance<R: BufRead>(reader: &mut R) -> io::Result<HdrImage> {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        if !line.starts_with("#?") {
+            return Err(invalid_data("not a radiance image"));
+        }
+        // header lines continue until an empty line
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                return Err(invalid_data("unexpected end of radiance header"));
+            }
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                break;
+            }
+            if trimmed.starts_with("FORMAT=") && trimmed != "FORMAT=32-bit_rle_rgbe" {
+                return Err(invalid_data("only rgbe radiance images are supported"));
+            }
+        }
+        line.clear();
+        reader.read_line(&mut line)?;
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() != 4 || parts[0] != "-Y" || parts[2] != "+X" {
+            return Err(invalid_data("unsupported radiance image orientation"));
+        }
+        let height: usize = parts[1].parse().map_err(|_| invalid_data("invalid radiance image height"))?;
+        let width: usize = parts[3].parse().map_err(|_| invalid_data("invalid radiance image width"))?;
+        if width == 0 || height == 0 {
+            return Err(invalid_data("radiance image is empty"));
+        }
+        if width.checked_mul(height).and_then(|n| n.checked_mul(4)).is_none() {
+            return Err(invalid_data("radiance image is too large"));
+        }
+
+        // the image grows as scanlines are read, so a header claiming more than the file has fails without
+        // allocating it all up front
+        let mut data = Vec::new();
+        for _ in 0..height {
+            data.extend(read_radiance_scanline(reader, width)?.into_iter().map(rgbe_to_vec3));
+        }
+        Ok(HdrImage::new(width, height, data))
+    }
+
+    /// Loads a png, converting from sRGB to linear.
+    fn load_png(path: &Path) -> io::Result<HdrImage> {
+        // the default transformations expand palettes and reduce 16 bit images to 8 bits
+        let decoder = png::Decoder::new(File::open(path)?);
+        let (info, mut reader) = decoder.read_info()?;
+        let mut buf = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut buf)?;
+        let data = match reader.output_color_type().0 {
+            png::ColorType::RGB => buf.chunks(3).map(|c| Vec3::new(srgb_to_linear(c[0]), srgb_to_linear(c[1]), srgb_to_linear(c[2]))).collect(),
+            png::ColorType::RGBA => buf.chunks(4).map(|c| Vec3::new(srgb_to_linear(c[0]), srgb_to_linear(c[1]), srgb_to_linear(c[2]))).collect(),
+            png::ColorType::Grayscale => buf.iter().map(|&c| Vec3::new(1.0, 1.0, 1.0) * srgb_to_linear(c)).collect(),
+            png::ColorType::GrayscaleAlpha => buf.chunks(2).map(|c| Vec3::new(1.0, 1.0, 1.0) * srgb_to_linear(c[0])).collect(),
+            _ => return Err(invalid_data("unsupported png color type"))
+        };
+        Ok(HdrImage::new(info.width as usize, info.height as usize, data))
+    }
+
+    #[cfg(feature = "exr")]
+    fn load_exr(path: &Path) -> io::Result<HdrImage> {
+        let image = exr::prelude::read_first_rgba_layer_from_file(
+            path,
+            |resolution, _| HdrImage::new(resolution.width(), resolution.height(), vec![Vec3::zero(); resolution.width() * resolution.height()]),
+            |image: &mut HdrImage, position, (r, g, b, _a): (f32, f32, f32, f32)| {
+                let i = position.x() + position.y() * image.width;
+                image.data[i] = Vec3::new(r as f64, g as f64, b as f64);
+            }
+        ).map_err(|e| invalid_data(&e.to_string()))?;
+        Ok(image.layer_data.channel_data.pixels)
+    }
+
+    #[cfg(not(feature = "exr"))]
+    fn load_exr(path: &Path) -> io::Result<HdrImage> {
+        Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} can't be loaded, build with the exr feature to load OpenEXR images", path.display())))
+    }
+}
+
+fn srgb_to_linear(value: u8) -> f64 {
+    let c = value as f64 / 255.0;
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+fn rgbe_to_vec3(rgbe: [u8; 4]) -> Vec3 {
+    if rgbe[3] == 0 {
+        return Vec3::zero();
+    }
+    // the mantissas are 8 bit fractions of 2^(exponent - 128)
+    let f = 2f64.powi(rgbe[3] as i32 - 136);
+    Vec3::new((rgbe[0] as f64 + 0.5) * f, (rgbe[1] as f64 + 0.5) * f, (rgbe[2] as f64 + 0.5) * f)
+}
+
+/// Reads a scanline of width pixels, which must be more than zero.
+fn read_radiance_scanline<R: Read>(reader: &mut R, width: usize) -> io::Result<Vec<[u8; 4]>> {
+    let mut first = [0u8; 4];
+    reader.read_exact(&mut first)?;
+    // run length encoded scanlines start with 2, 2 then the width, anything else is a flat scanline
+    if !(8..=0x7fff).contains(&width) || first[0] != 2 || first[1] != 2 || first[2] & 0x80 != 0 {
+        // only allocates as many bytes as the reader actually has
+        let mut bytes = first.to_vec();
+        reader.take(4 * (width as u64 - 1)).read_to_end(&mut bytes)?;
+        if bytes.len() != 4 * width {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "radiance scanline is truncated"));
+        }
+        return Ok(bytes.chunks(4).map(|c| [c[0], c[1], c[2], c[3]]).collect());
+    }
+    if ((first[2] as usize) << 8 | first[3] as usize) != width {
+        return Err(invalid_data("radiance scanline width mismatch"));
+    }
+    let mut scanline = vec![[0u8; 4]; width];
+    // each of the four components is stored separately as runs and literal spans
+    for component in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let mut count = [0u8; 1];
+            reader.read_exact(&mut count)?;
+            let count = count[0] as usize;
+            if count > 128 {
+                let run = count - 128;
+                if x + run > width {
+                    return Err(invalid_data("radiance run overflows scanline"));
+                }
+                let mut value = [0u8; 1];
+                reader.read_exact(&mut value)?;
+                for pixel in scanline[x..x + run].iter_mut() {
+                    pixel[component] = value[0];
+                }
+                x += run;
+            }
+            else {
+                if count == 0 || x + count > width {
+                    return Err(invalid_data("invalid radiance span"));
+                }
+                let mut values = vec![0u8; count];
+                reader.read_exact(&mut values)?;
+                for (pixel, value) in scanline[x..x + count].iter_mut().zip(values) {
+                    pixel[component] = value;
+                }
+                x += count;
+            }
+        }
+    }
+    Ok(scanline)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_flat_radiance() {
+        let mut file = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 2\n".to_vec();
+        file.extend_from_slice(&[128, 64, 0, 129, 0, 0, 0, 0]);
+        let image = HdrImage::read_radiance(&mut &file[..]).unwrap();
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!(image.get(0, 0), Vec3::new(1.0 + 1.0 / 256.0, 0.5 + 1.0 / 256.0, 1.0 / 256.0));
+        assert_eq!(image.get(1, 0), Vec3::zero());
+    }
+
+    #[test]
+    fn read_rle_radiance() {
+        let mut file = b"#?RADIANCE\n\n-Y 1 +X 8\n".to_vec();
+        file.extend_from_slice(&[2, 2, 0, 8]);
+        // red is a run of 8, green a literal span of 8, blue and exponent are runs
+        file.extend_from_slice(&[136, 255]);
+        file.extend_from_slice(&[8, 0, 1, 2, 3, 4, 5, 6, 7]);
+        file.extend_from_slice(&[136, 0]);
+        file.extend_from_slice(&[136, 136]);
+        let image = HdrImage::read_radiance(&mut &file[..]).unwrap();
+        assert_eq!(image.width, 8);
+        assert_eq!(image.get(3, 0), rgbe_to_vec3([255, 3, 0, 136]));
+        assert_eq!(image.get(7, 0).x(), 255.5);
+    }
+
+    #[test]
+    fn reject_malformed_radiance() {
+        for header in &["-Y 1 +X 0", "-Y 0 +X 4", "-Y 18446744073709551615 +X 2"] {
+            let file = format!("#?RADIANCE\n\n{}\n", header).into_bytes();
+            let error = HdrImage::read_radiance(&mut &file[..]).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{}", header);
+        }
+        // a huge image that isn't in the file fails without allocating it
+        let mut file = b"#?RADIANCE\n\n-Y 1 +X 1000000000000\n".to_vec();
+        file.extend_from_slice(&[128, 64, 0, 129]);
+        assert_eq!(HdrImage::read_radiance(&mut &file[..]).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
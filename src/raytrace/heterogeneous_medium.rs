@@ -0,0 +1,143 @@
+//! Media whose density varies from place to place, like clouds and smoke. Distances are sampled with delta tracking,
+//! which pads the medium out to a constant majorant density with null particles that don't scatter, so a ray
+//! steps through it as if it were constant and keeps going at the null collisions.
+
+use super::util::drand48;
+use super::aabb::AABB;
+use super::hittable::{HitRecord, Hittable};
+use super::constant_medium::{Medium, boundary_intervals, medium_hit, medium_transmittance};
+use super::texture::Texture;
+use super::voxel_grid::GridTexture;
+use super::material::Material;
+use super::ray::Ray;
+use super::Vec3;
+use std::sync::Arc;
+
+pub struct HeterogeneousMedium {
+    boundary: Arc<dyn Hittable>,
+    density: Arc<dyn Texture>,
+    scale: f64,
+    max_density: f64,
+    phase_function: Arc<dyn Material>
+}
+
+impl HeterogeneousMedium {
+    /// Creates a medium filling boundary whose density at a point is scale times the average channel of the
+    /// density texture there. max_density is the highest density anywhere in the medium, which tracking pads
+    /// out to: densities above it come out biased, and setting it much higher than needed slows tracking down.
+    pub fn new(boundary: Arc<dyn Hittable>, density: Arc<dyn Texture>, scale: f64, max_density: f64, phase_function: Arc<dyn Material>) -> Self {
+        HeterogeneousMedium{boundary, density, scale, max_density, phase_function}
+    }
+
+    /// Creates a medium whose density is scale times a voxel grid, bounded by the largest value in the grid.
+    pub fn from_grid(boundary: Arc<dyn Hittable>, density: GridTexture, scale: f64, phase_function: Arc<dyn Material>) -> Self {
+        let max_density = scale * density.max_value();
+        Self::new(boundary, Arc::new(density), scale, max_density, phase_function)
+    }
+
+    pub fn density(&self, p: Vec3) -> f64 {
+        let value = self.density.value(0.0, 0.0, p);
+        (self.scale * (value.x() + value.y() + value.z()) / 3.0).max(0.0)
+    }
+}
+
+impl Medium for HeterogeneousMedium {
+    fn boundary(&self) -> &dyn Hittable {
+        self.boundary.as_ref()
+    }
+
+    fn sample_collision(&self, r: Ray, start: f64, end: f64) -> Option<HitRecord> {
+        if self.max_density <= 0.0 {
+            return None;
+        }
+        let ray_length = r.direction.length();
+        let mut t = start;
+        loop {
+            t -= (1.0 - drand48()).ln() / (self.max_density * ray_length);
+            if t >= end {
+                return None;
+            }
+            // a real collision in proportion to the actual density, otherwise a null one that carries on
+            if drand48() * self.max_density < self.density(r.point_at_parameter(t)) {
+                return Some(medium_hit(r, t, &self.phase_function));
+            }
+        }
+    }
+
+    /// Estimated by ratio tracking, which weights each null collision by its chance of being null instead of
+    /// stopping at one.
+    fn estimate_transmittance(&self, r: Ray, start: f64, end: f64) -> Vec3 {
+        if self.max_density <= 0.0 {
+            return Vec3::new(1.0, 1.0, 1.0);
+        }
+        let ray_length = r.direction.length();
+        let mut transmittance = 1.0;
+        let mut t = start;
+        loop {
+            t -= (1.0 - drand48()).ln() / (self.max_density * ray_length);
+            if t >= end {
+                break;
+            }
+            transmittance *= 1.0 - self.density(r.point_at_parameter(t)) / self.max_density;
+        }
+        Vec3::new(transmittance, transmittance, transmittance)
+    }
+}
+
+impl Hittable for HeterogeneousMedium {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        boundary_intervals(self.boundary.as_ref(), r, t_min, t_max).into_iter()
+            .find_map(|(start, end)| self.sample_collision(r, start, end))
+    }
+
+    fn transmittance(&self, r: Ray, t_min: f64, t_max: f64) -> Vec3 {
+        medium_transmittance(self, r, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.boundary.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytrace::sphere::Sphere;
+    use crate::raytrace::material::Isotropic;
+    use crate::raytrace::texture::{ConstantTexture, NoiseTexture};
+    use crate::raytrace::voxel_grid::DenseGrid;
+
+    #[test]
+    fn delta_and_ratio_tracking_agree() {
+        let white = Arc::new(ConstantTexture::scalar(1.0));
+        let phase_function: Arc<dyn Material> = Arc::new(Isotropic::new(white.clone()));
+        let boundary: Arc<dyn Hittable> = Arc::new(Sphere::new(Vec3::zero(), 1.0, phase_function.clone()));
+        let r = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 2.0), 0.0);
+
+        // a constant density falls off exponentially over the diameter
+        let constant = HeterogeneousMedium::new(boundary.clone(), white, 0.5, 2.0, phase_function.clone());
+        let n = 20000;
+        let escaped = (0..n).filter(|_| constant.hit(r, 0.0, f64::MAX).is_none()).count() as f64 / n as f64;
+        let ratio = (0..n).map(|_| constant.transmittance(r, 0.0, f64::MAX).x()).sum::<f64>() / n as f64;
+        let expected = (-0.5f64 * 2.0).exp();
+        assert!((escaped - expected).abs() < 0.02, "{} {}", escaped, expected);
+        assert!((ratio - expected).abs() < 0.01, "{} {}", ratio, expected);
+
+        // both track the same noise
+        let noise = HeterogeneousMedium::new(boundary.clone(), Arc::new(NoiseTexture::new(2.0)), 3.0, 3.0, phase_function.clone());
+        let escaped = (0..n).filter(|_| noise.hit(r, 0.0, f64::MAX).is_none()).count() as f64 / n as f64;
+        let ratio = (0..n).map(|_| noise.transmittance(r, 0.0, f64::MAX).x()).sum::<f64>() / n as f64;
+        assert!((escaped - ratio).abs() < 0.02, "{} {}", escaped, ratio);
+        assert!(ratio > 0.0 && ratio < 1.0);
+
+        // a grid denser than 1 isn't clipped
+        let grid = DenseGrid::from_fn([2, 2, 2], |_| 4.0);
+        let bounds = AABB::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0));
+        let dense = HeterogeneousMedium::from_grid(boundary.clone(), GridTexture::new(Arc::new(grid), bounds), 0.5, phase_function.clone());
+        let escaped = (0..n).filter(|_| dense.hit(r, 0.0, f64::MAX).is_none()).count() as f64 / n as f64;
+        let ratio = (0..n).map(|_| dense.transmittance(r, 0.0, f64::MAX).x()).sum::<f64>() / n as f64;
+        let expected = (-2.0f64 * 2.0).exp();
+        assert!((escaped - expected).abs() < 0.01, "{} {}", escaped, expected);
+        assert!((ratio - expected).abs() < 0.005, "{} {}", ratio, expected);
+    }
+}
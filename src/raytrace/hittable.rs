@@ -10,18 +10,27 @@ pub struct HitRecord {
     pub normal: Vec3,
     pub material: Arc<dyn Material>,
     pub u: f64,
-    pub v: f64
+    pub v: f64,
+    /// the hero wavelength in nanometers when rendering spectrally, set by the renderer for wavelength dependent materials
+    pub wavelength: Option<f64>
 }
 
 impl HitRecord {
     pub fn new(t: f64, p: Vec3, normal: Vec3, material: Arc<dyn Material>, u: f64, v: f64) -> Self {
-        HitRecord{t, p, normal, material, u, v}
+        HitRecord{t, p, normal, material, u, v, wavelength: None}
     }
 }
 
 pub trait Hittable: Send + Sync {
     fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
     fn bounding_box(&self) -> Option<AABB>;
+
+    /// The fraction of each channel of light that makes it along the ray between t_min and t_max, for shadow rays.
+    /// Surfaces block all of it, while media let some through without anything having to stop at a particle.
+    fn transmittance(&self, r: Ray, t_min: f64, t_max: f64) -> Vec3 {
+        if self.hit(r, t_min, t_max).is_some() { Vec3::zero() } else { Vec3::new(1.0, 1.0, 1.0) }
+    }
+
     fn required_bounding_box(&self) -> AABB {
         self.bounding_box().expect("No Bounding Box Found")
     }
@@ -41,6 +41,17 @@ impl Hittable for HittableList {
         hit
     }
 
+    fn transmittance(&self, r: Ray, t_min: f64, t_max: f64) -> Vec3 {
+        let mut transmittance = Vec3::new(1.0, 1.0, 1.0);
+        for object in &self.objects {
+            transmittance *= object.transmittance(r, t_min, t_max);
+            if transmittance.is_zero_length() {
+                break;
+            }
+        }
+        transmittance
+    }
+
     fn bounding_box(&self) -> Option<AABB> {
         if self.objects.len() == 1 { return None; }
         let mut result = AABB::new(Vec3::new(std::f64::MAX, std::f64::MAX, std::f64::MAX), Vec3::new(std::f64::MIN, std::f64::MIN, std::f64::MIN));
@@ -0,0 +1,234 @@
+//! Materials built out of other materials, blending two by a mask or layering a clear coat over a base.
+
+use super::Vec3;
+use super::ray::Ray;
+use super::hittable::HitRecord;
+use super::material::{Material, BsdfSample, Lobe};
+use super::texture::{Texture, ConstantTexture};
+use super::microfacet::{Ggx, Frame, fresnel_dielectric, reflect_about};
+use std::sync::Arc;
+
+/// Blends two materials, taking b where the mask is 1 and a where it is 0. The mask reads the red channel of its texture.
+pub struct MixMaterial {
+    a: Arc<dyn Material>,
+    b: Arc<dyn Material>,
+    mask: Arc<dyn Texture>
+}
+
+impl MixMaterial {
+    pub fn new(a: Arc<dyn Material>, b: Arc<dyn Material>, mask: Arc<dyn Texture>) -> Self {
+        MixMaterial{a, b, mask}
+    }
+
+    /// Blends the same amount of b everywhere.
+    pub fn with_amount(a: Arc<dyn Material>, b: Arc<dyn Material>, amount: f64) -> Self {
+        Self::new(a, b, Arc::new(ConstantTexture::scalar(amount)))
+    }
+
+    fn amount(&self, hit_record: &HitRecord) -> f64 {
+        self.mask.value(hit_record.u, hit_record.v, hit_record.p).x().clamp(0.0, 1.0)
+    }
+}
+
+impl Material for MixMaterial {
+    fn sample(&self, hit_record: &HitRecord, wo: Vec3, u: [f64; 3]) -> Option<BsdfSample> {
+        let amount = self.amount(hit_record);
+        // pick a material by the mask and rescale the random number so it can be reused
+        let (picked, u0) = if u[0] < amount { (&self.b, u[0] / amount) } else { (&self.a, (u[0] - amount) / (1.0 - amount)) };
+        let sample = picked.sample(hit_record, wo, [u0, u[1], u[2]])?;
+        if sample.lobe.is_specular() {
+            // the probability of picking the material cancels with its weight in the blend
+            return Some(sample);
+        }
+        let pdf = self.pdf(hit_record, wo, sample.wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample{weight: self.eval(hit_record, wo, sample.wi) / pdf, pdf, ..sample})
+    }
+
+    fn eval(&self, hit_record: &HitRecord, wo: Vec3, wi: Vec3) -> Vec3 {
+        let amount = self.amount(hit_record);
+        (1.0 - amount) * self.a.eval(hit_record, wo, wi) + amount * self.b.eval(hit_record, wo, wi)
+    }
+
+    fn pdf(&self, hit_record: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        let amount = self.amount(hit_record);
+        (1.0 - amount) * self.a.pdf(hit_record, wo, wi) + amount * self.b.pdf(hit_record, wo, wi)
+    }
+
+    fn emitted(&self, ray_in: &Ray, hit_record: &HitRecord) -> Vec3 {
+        let amount = self.amount(hit_record);
+        (1.0 - amount) * self.a.emitted(ray_in, hit_record) + amount * self.b.emitted(ray_in, hit_record)
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.a.is_dispersive() || self.b.is_dispersive()
+    }
+
+    fn is_specular(&self) -> bool {
+        self.a.is_specular() && self.b.is_specular()
+    }
+}
+
+/// A thin dielectric layer over a base material, like varnish or lacquer. The coat reflects by its Fresnel term and the
+/// base is lit by what passes through it on the way in and out, ignoring the bending of light and bounces inside the coat.
+pub struct Coated {
+    base: Arc<dyn Material>,
+    refractive_index: f64,
+    roughness: f64,
+    /// the absorption coefficient of the coat times its thickness, which tints the base
+    absorption: Vec3
+}
+
+impl Coated {
+    /// Coats the base with a clear layer, a roughness of 0 giving a perfectly smooth coat.
+    pub fn new(base: Arc<dyn Material>, refractive_index: f64, roughness: f64) -> Self {
+        Coated{base, refractive_index, roughness, absorption: Vec3::zero()}
+    }
+
+    /// Sets the color of the coat seen at normal incidence, passing through it twice, like amber varnish.
+    pub fn set_color(&mut self, color: Vec3) {
+        let channel = |c: f64| -0.5 * c.clamp(1e-4, 1.0).ln();
+        self.absorption = Vec3::new(channel(color.x()), channel(color.y()), channel(color.z()));
+    }
+
+    /// The fraction of light that gets through the coat to the base and back out again.
+    fn transmittance(&self, cos_o: f64, cos_i: f64) -> Vec3 {
+        let (cos_o, cos_i) = (cos_o.abs().max(1e-4), cos_i.abs().max(1e-4));
+        let fresnel = (1.0 - fresnel_dielectric(cos_o, self.refractive_index)) * (1.0 - fresnel_dielectric(cos_i, self.refractive_index));
+        let path = 1.0 / cos_o + 1.0 / cos_i;
+        let a = self.absorption;
+        fresnel * Vec3::new((-a.x() * path).exp(), (-a.y() * path).exp(), (-a.z() * path).exp())
+    }
+
+    /// The frame of the side of the surface wo is on, and the probability of sampling the coat rather than the base.
+    fn frame(&self, hit_record: &HitRecord, wo: Vec3) -> (Frame, f64) {
+        let normal = if Vec3::dot(wo, hit_record.normal) < 0.0 { -hit_record.normal } else { hit_record.normal };
+        let cos_o = Vec3::dot(wo, normal);
+        // the Fresnel term alone is a few percent near normal incidence, so neither layer is starved of samples
+        (Frame::new(normal), fresnel_dielectric(cos_o, self.refractive_index).clamp(0.2, 0.8))
+    }
+
+    /// The coat's reflection times the cosine and its pdf, with wo and wi in the local frame.
+    fn coat(&self, wo: Vec3, wi: Vec3) -> (f64, f64) {
+        let ggx = Ggx::from_roughness(self.roughness);
+        if ggx.is_smooth() || wi.z() <= 0.0 {
+            return (0.0, 0.0);
+        }
+        let h = (wo + wi).normalize();
+        (fresnel_dielectric(Vec3::dot(wo, h), self.refractive_index) * ggx.reflection_eval(wo, wi), ggx.reflection_pdf(wo, wi))
+    }
+}
+
+impl Material for Coated {
+    fn sample(&self, hit_record: &HitRecord, wo: Vec3, u: [f64; 3]) -> Option<BsdfSample> {
+        let (frame, coat_probability) = self.frame(hit_record, wo);
+        let local_wo = frame.to_local(wo);
+        let ggx = Ggx::from_roughness(self.roughness);
+        let sampled = if u[0] < coat_probability {
+            if ggx.is_smooth() {
+                let wi = Vec3::new(-local_wo.x(), -local_wo.y(), local_wo.z());
+                let weight = Vec3::new(1.0, 1.0, 1.0) * fresnel_dielectric(local_wo.z(), self.refractive_index) / coat_probability;
+                return Some(BsdfSample{wi: frame.to_world(wi), weight, pdf: 1.0, lobe: Lobe::SPECULAR | Lobe::REFLECTION});
+            }
+            let wi = reflect_about(local_wo, ggx.sample_visible_normal(local_wo, u[1], u[2]));
+            if wi.z() <= 0.0 {
+                return None;
+            }
+            BsdfSample{wi: frame.to_world(wi), weight: Vec3::zero(), pdf: 0.0, lobe: Lobe::GLOSSY | Lobe::REFLECTION}
+        }
+        else {
+            let u0 = (u[0] - coat_probability) / (1.0 - coat_probability);
+            let sample = self.base.sample(hit_record, wo, [u0, u[1], u[2]])?;
+            if sample.lobe.is_specular() {
+                let transmittance = self.transmittance(local_wo.z(), frame.to_local(sample.wi).z());
+                return Some(BsdfSample{weight: transmittance * sample.weight / (1.0 - coat_probability), ..sample});
+            }
+            sample
+        };
+        // weight by both layers as if sampled from their mixture
+        let pdf = self.pdf(hit_record, wo, sampled.wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample{weight: self.eval(hit_record, wo, sampled.wi) / pdf, pdf, ..sampled})
+    }
+
+    fn eval(&self, hit_record: &HitRecord, wo: Vec3, wi: Vec3) -> Vec3 {
+        let (frame, _) = self.frame(hit_record, wo);
+        let (local_wo, local_wi) = (frame.to_local(wo), frame.to_local(wi));
+        let (coat, _) = self.coat(local_wo, local_wi);
+        coat * Vec3::new(1.0, 1.0, 1.0) + self.transmittance(local_wo.z(), local_wi.z()) * self.base.eval(hit_record, wo, wi)
+    }
+
+    fn pdf(&self, hit_record: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        let (frame, coat_probability) = self.frame(hit_record, wo);
+        let (_, coat_pdf) = self.coat(frame.to_local(wo), frame.to_local(wi));
+        coat_probability * coat_pdf + (1.0 - coat_probability) * self.base.pdf(hit_record, wo, wi)
+    }
+
+    fn emitted(&self, ray_in: &Ray, hit_record: &HitRecord) -> Vec3 {
+        let cos_o = Vec3::dot(ray_in.direction.normalize(), hit_record.normal);
+        self.transmittance(cos_o, 1.0) * self.base.emitted(ray_in, hit_record)
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.base.is_dispersive()
+    }
+
+    fn is_specular(&self) -> bool {
+        Ggx::from_roughness(self.roughness).is_smooth() && self.base.is_specular()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytrace::material::{Lambertian, Metal};
+    use crate::raytrace::util::drand48;
+
+    fn albedo(material: &dyn Material, wo: Vec3) -> Vec3 {
+        let rec = HitRecord::new(1.0, Vec3::zero(), Vec3::unit_y(), Arc::new(Lambertian::new(Arc::new(ConstantTexture::scalar(0.0)))), 0.5, 0.5);
+        let n = 20000;
+        let mut total = Vec3::zero();
+        for _ in 0..n {
+            if let Some(sample) = material.sample(&rec, wo, [drand48(), drand48(), drand48()]) {
+                if !sample.lobe.is_specular() {
+                    let expected = material.eval(&rec, wo, sample.wi) / material.pdf(&rec, wo, sample.wi);
+                    assert!((sample.weight - expected).length() < 1e-6 * expected.length().max(1.0));
+                }
+                total += sample.weight;
+            }
+        }
+        total / n as f64
+    }
+
+    #[test]
+    fn mix_and_coat() {
+        let wo = Vec3::new(0.3, 0.8, 0.1).normalize();
+        let white: Arc<dyn Material> = Arc::new(Lambertian::new(Arc::new(ConstantTexture::scalar(1.0))));
+        let black: Arc<dyn Material> = Arc::new(Lambertian::new(Arc::new(ConstantTexture::scalar(0.0))));
+        let mirror: Arc<dyn Material> = Arc::new(Metal::new(Arc::new(ConstantTexture::scalar(1.0)), 0.0));
+
+        // blending reflects the blended amount of light
+        let grey = albedo(&MixMaterial::with_amount(white.clone(), black.clone(), 0.25), wo);
+        assert!((grey.x() - 0.75).abs() < 0.02, "{:?}", grey);
+        let half_mirror = albedo(&MixMaterial::with_amount(black.clone(), mirror, 0.5), wo);
+        assert!((half_mirror.x() - 0.5).abs() < 0.02, "{:?}", half_mirror);
+
+        // a clear coat over black only reflects its Fresnel term, and never adds energy
+        for &roughness in [0.0, 0.3].iter() {
+            let coated_black = albedo(&Coated::new(black.clone(), 1.5, roughness), wo);
+            assert!(coated_black.x() > 0.02 && coated_black.x() < 0.1, "{:?}", coated_black);
+            let coated_white = albedo(&Coated::new(white.clone(), 1.5, roughness), wo);
+            assert!(coated_white.x() > 0.8 && coated_white.x() <= 1.0, "{:?}", coated_white);
+        }
+
+        // a colored coat tints the base
+        let mut varnish = Coated::new(white, 1.5, 0.0);
+        varnish.set_color(Vec3::new(0.9, 0.6, 0.3));
+        let tinted = albedo(&varnish, wo);
+        assert!(tinted.x() > tinted.y() && tinted.y() > tinted.z());
+    }
+}
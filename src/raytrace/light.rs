@@ -0,0 +1,620 @@
+//! Lights that can be sampled directly, so the renderer can send shadow rays towards them.
+//! Area lights are also hittable so they can be added to the scene and seen by the camera.
+
+use super::Vec3;
+use super::ray::Ray;
+use super::aabb::AABB;
+use super::hittable::{Hittable, HitRecord};
+use super::material::{Material, DiffuseLight, EmissionSide};
+use super::texture::ConstantTexture;
+use super::sphere::hit_sphere;
+use super::sampling::{Distribution1D, uniform_cone, uniform_cone_pdf, uniform_sphere, cosine_hemisphere};
+use super::util::{drand48, orthonormal_basis};
+use std::sync::Arc;
+
+/// Light arriving at a point from a light.
+#[derive(Clone, Copy, Debug)]
+pub struct LightSample {
+    /// unit direction from the point towards the light
+    pub direction: Vec3,
+    /// radiance arriving along direction, for delta lights this is the irradiance at the point
+    pub radiance: Vec3,
+    /// probability density of sampling direction with respect to solid angle, 1 for delta lights
+    pub pdf: f64,
+    /// distance to the light, infinite for directional lights
+    pub distance: f64
+}
+
+/// Light leaving a light, for tracing paths from the lights into the scene.
+#[derive(Clone, Copy, Debug)]
+pub struct EmissionSample {
+    /// the point on the light the light leaves from
+    pub origin: Vec3,
+    /// unit direction the light travels in
+    pub direction: Vec3,
+    /// the light's normal at origin, zero for lights that aren't surfaces like point lights
+    pub normal: Vec3,
+    /// radiance leaving along direction, for point lights this is the intensity
+    pub radiance: Vec3,
+    /// probability density of sampling origin with respect to area on the light, 1 for point lights
+    pub pdf_position: f64,
+    /// probability density of sampling direction with respect to solid angle
+    pub pdf_direction: f64
+}
+
+pub trait Light: Send + Sync {
+    /// Samples a direction from the point p towards the light.
+    fn sample(&self, p: Vec3) -> Option<LightSample>;
+
+    /// Evaluates the light seen from p in a direction, with the pdf of sample picking that direction.
+    /// Returns None if the light isn't in that direction, which is always the case for delta lights.
+    fn eval(&self, _p: Vec3, _direction: Vec3) -> Option<LightSample> {
+        None
+    }
+
+    /// Lights with a single direction of incidence, like point lights, can only be reached by sampling them.
+    fn is_delta(&self) -> bool {
+        false
+    }
+
+    /// Samples a point on the light and a direction for light to leave it in.
+    /// Lights that return None, like directional lights, can't start paths traced from the lights.
+    fn sample_emission(&self) -> Option<EmissionSample> {
+        None
+    }
+
+    /// Evaluates light leaving a point on the light in a direction, with the densities of sample_emission picking them.
+    fn eval_emission(&self, _point: Vec3, _direction: Vec3) -> Option<EmissionSample> {
+        None
+    }
+}
+
+/// Samples a direction leaving a surface with a cosine distribution about the normal, from both sides if two_sided.
+/// Returns the direction and its pdf with respect to solid angle.
+fn sample_cosine_emission(normal: Vec3, two_sided: bool) -> (Vec3, f64) {
+    let (s, t) = orthonormal_basis(normal);
+    let local = cosine_hemisphere(drand48(), drand48());
+    let side = if two_sided && drand48() < 0.5 { -1.0 } else { 1.0 };
+    let direction = local.x() * s + local.y() * t + side * local.z() * normal;
+    (direction, cosine_emission_pdf(normal, direction, two_sided))
+}
+
+fn cosine_emission_pdf(normal: Vec3, direction: Vec3, two_sided: bool) -> f64 {
+    let cosine = Vec3::dot(normal, direction);
+    if two_sided {
+        0.5 * cosine.abs() / std::f64::consts::PI
+    }
+    else {
+        cosine.max(0.0) / std::f64::consts::PI
+    }
+}
+
+/// Light emitted equally in all directions from a single point, intensity is in radiance times area.
+pub struct PointLight {
+    position: Vec3,
+    intensity: Vec3
+}
+
+impl PointLight {
+    pub fn new(position: Vec3, intensity: Vec3) -> Self {
+        PointLight{position, intensity}
+    }
+}
+
+impl Light for PointLight {
+    fn sample_emission(&self) -> Option<EmissionSample> {
+        self.eval_emission(self.position, uniform_sphere(drand48(), drand48()))
+    }
+
+    fn eval_emission(&self, _point: Vec3, direction: Vec3) -> Option<EmissionSample> {
+        Some(EmissionSample{
+            origin: self.position,
+            direction,
+            normal: Vec3::zero(),
+            radiance: self.intensity,
+            pdf_position: 1.0,
+            pdf_direction: 1.0 / (4.0 * std::f64::consts::PI)
+        })
+    }
+
+    fn sample(&self, p: Vec3) -> Option<LightSample> {
+        let to_light = self.position - p;
+        let distance = to_light.length();
+        Some(LightSample{direction: to_light / distance, radiance: self.intensity / (distance * distance), pdf: 1.0, distance})
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+}
+
+/// A point light that shines in a cone, fading out smoothly between the falloff start angle and the edge of the cone.
+pub struct SpotLight {
+    position: Vec3,
+    direction: Vec3,
+    intensity: Vec3,
+    cos_total: f64,
+    cos_falloff_start: f64
+}
+
+impl SpotLight {
+    /// Angles are in radians from the direction the light points in.
+    pub fn new(position: Vec3, direction: Vec3, intensity: Vec3, total_angle: f64, falloff_start: f64) -> Self {
+        assert!(falloff_start <= total_angle, "The falloff must start within the cone");
+        SpotLight{position, direction: direction.normalize(), intensity, cos_total: total_angle.cos(), cos_falloff_start: falloff_start.cos()}
+    }
+
+    /// The fraction of the intensity emitted in a direction.
+    fn falloff(&self, direction: Vec3) -> f64 {
+        let cos_theta = Vec3::dot(direction, self.direction);
+        if cos_theta <= self.cos_total {
+            return 0.0;
+        }
+        if cos_theta >= self.cos_falloff_start {
+            return 1.0;
+        }
+        let t = (cos_theta - self.cos_total) / (self.cos_falloff_start - self.cos_total);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl Light for SpotLight {
+    fn sample_emission(&self) -> Option<EmissionSample> {
+        let local = uniform_cone(drand48(), drand48(), self.cos_total);
+        let (s, t) = orthonormal_basis(self.direction);
+        self.eval_emission(self.position, local.x() * s + local.y() * t + local.z() * self.direction)
+    }
+
+    fn eval_emission(&self, _point: Vec3, direction: Vec3) -> Option<EmissionSample> {
+        let falloff = self.falloff(direction);
+        if falloff == 0.0 {
+            return None;
+        }
+        Some(EmissionSample{
+            origin: self.position,
+            direction,
+            normal: Vec3::zero(),
+            radiance: falloff * self.intensity,
+            pdf_position: 1.0,
+            pdf_direction: uniform_cone_pdf(self.cos_total)
+        })
+    }
+
+    fn sample(&self, p: Vec3) -> Option<LightSample> {
+        let to_light = self.position - p;
+        let distance = to_light.length();
+        let direction = to_light / distance;
+        let falloff = self.falloff(-direction);
+        if falloff == 0.0 {
+            return None;
+        }
+        Some(LightSample{direction, radiance: falloff * self.intensity / (distance * distance), pdf: 1.0, distance})
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+}
+
+/// Parallel light from infinitely far away, like sunlight.
+pub struct DirectionalLight {
+    /// the direction the light travels in
+    direction: Vec3,
+    irradiance: Vec3
+}
+
+impl DirectionalLight {
+    /// The irradiance is measured on a surface facing the light.
+    pub fn new(direction: Vec3, irradiance: Vec3) -> Self {
+        DirectionalLight{direction: direction.normalize(), irradiance}
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _p: Vec3) -> Option<LightSample> {
+        Some(LightSample{direction: -self.direction, radiance: self.irradiance, pdf: 1.0, distance: f64::INFINITY})
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+}
+
+/// Converts a pdf with respect to area on a light to one with respect to solid angle at a point at distance
+/// from it, where cosine is between the light's normal and the direction.
+fn area_to_solid_angle(pdf: f64, distance: f64, cosine: f64) -> f64 {
+    if cosine == 0.0 { 0.0 } else { pdf * distance * distance / cosine.abs() }
+}
+
+/// The material of an area light, which emits from both sides to match sampling the light.
+fn emitter(radiance: Vec3) -> Arc<dyn Material> {
+    let mut material = DiffuseLight::new(Arc::new(ConstantTexture::new(radiance)));
+    material.set_side(EmissionSide::Both);
+    Arc::new(material)
+}
+
+/// A sphere emitting radiance in every direction from its surface.
+pub struct SphereLight {
+    center: Vec3,
+    radius: f64,
+    radiance: Vec3,
+    material: Arc<dyn Material>
+}
+
+impl SphereLight {
+    pub fn new(center: Vec3, radius: f64, radiance: Vec3) -> Self {
+        let material = emitter(radiance);
+        SphereLight{center, radius, radiance, material}
+    }
+
+    /// The cosine of the half angle of the cone the sphere covers from p, None if p is inside the sphere.
+    fn cos_max(&self, p: Vec3) -> Option<f64> {
+        let distance_squared = (self.center - p).length_squared();
+        if distance_squared <= self.radius * self.radius {
+            return None;
+        }
+        Some((1.0 - self.radius * self.radius / distance_squared).max(0.0).sqrt())
+    }
+}
+
+impl Light for SphereLight {
+    fn sample(&self, p: Vec3) -> Option<LightSample> {
+        let direction = match self.cos_max(p) {
+            // sample the cone of directions towards the sphere, which only includes visible points
+            Some(cos_max) => {
+                let axis = (self.center - p).normalize();
+                let local = uniform_cone(drand48(), drand48(), cos_max);
+                let (s, t) = orthonormal_basis(axis);
+                local.x() * s + local.y() * t + local.z() * axis
+            }
+            // inside the sphere every point is visible, so pick one uniformly
+            None => (self.center + self.radius * super::util::random_unit_vector() - p).normalize()
+        };
+        self.eval(p, direction)
+    }
+
+    fn eval(&self, p: Vec3, direction: Vec3) -> Option<LightSample> {
+        let direction = direction.normalize();
+        let rec = hit_sphere(self.center, self.radius, &self.material, Ray::new(p, direction, 0.0), 0.0, f64::INFINITY)?;
+        let pdf = match self.cos_max(p) {
+            Some(cos_max) => uniform_cone_pdf(cos_max),
+            None => {
+                let area = 4.0 * std::f64::consts::PI * self.radius * self.radius;
+                area_to_solid_angle(1.0 / area, rec.t, Vec3::dot(rec.normal, direction))
+            }
+        };
+        Some(LightSample{direction, radiance: self.radiance, pdf, distance: rec.t})
+    }
+
+    fn sample_emission(&self) -> Option<EmissionSample> {
+        let normal = uniform_sphere(drand48(), drand48());
+        let (direction, _) = sample_cosine_emission(normal, false);
+        self.eval_emission(self.center + self.radius * normal, direction)
+    }
+
+    fn eval_emission(&self, point: Vec3, direction: Vec3) -> Option<EmissionSample> {
+        let normal = (point - self.center).normalize();
+        let pdf_direction = cosine_emission_pdf(normal, direction, false);
+        if pdf_direction == 0.0 {
+            return None;
+        }
+        let area = 4.0 * std::f64::consts::PI * self.radius * self.radius;
+        Some(EmissionSample{origin: point, direction, normal, radiance: self.radiance, pdf_position: 1.0 / area, pdf_direction})
+    }
+}
+
+impl Hittable for SphereLight {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        hit_sphere(self.center, self.radius, &self.material, r, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        Some(AABB::new(self.center - self.radius, self.center + self.radius))
+    }
+}
+
+/// A parallelogram with one corner at corner and the sides edge1 and edge2, emitting from both faces.
+pub struct QuadLight {
+    corner: Vec3,
+    edge1: Vec3,
+    edge2: Vec3,
+    normal: Vec3,
+    area: f64,
+    radiance: Vec3,
+    material: Arc<dyn Material>
+}
+
+impl QuadLight {
+    pub fn new(corner: Vec3, edge1: Vec3, edge2: Vec3, radiance: Vec3) -> Self {
+        let n = Vec3::cross(edge1, edge2);
+        let area = n.length();
+        assert!(area > 0.0, "The edges of a quad light must not be parallel");
+        let material = emitter(radiance);
+        QuadLight{corner, edge1, edge2, normal: n / area, area, radiance, material}
+    }
+
+    /// Intersects the ray with the quad, returning the ray parameter and the coordinates of the hit along each edge.
+    fn intersect(&self, r: Ray, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
+        let denominator = Vec3::dot(self.normal, r.direction);
+        if denominator.abs() < 1e-12 {
+            return None;
+        }
+        let t = Vec3::dot(self.normal, self.corner - r.origin) / denominator;
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+        let offset = r.point_at_parameter(t) - self.corner;
+        // project onto the edges using the reciprocal basis, which works for any parallelogram
+        let w = self.normal / self.area;
+        let u = Vec3::dot(w, Vec3::cross(offset, self.edge2));
+        let v = Vec3::dot(w, Vec3::cross(self.edge1, offset));
+        if !(0.0..=1.0).contains(&u) || !(0.0..=1.0).contains(&v) {
+            return None;
+        }
+        Some((t, u, v))
+    }
+}
+
+impl Light for QuadLight {
+    fn sample(&self, p: Vec3) -> Option<LightSample> {
+        let point = self.corner + drand48() * self.edge1 + drand48() * self.edge2;
+        self.eval(p, point - p)
+    }
+
+    fn eval(&self, p: Vec3, direction: Vec3) -> Option<LightSample> {
+        let direction = direction.normalize();
+        let (t, _, _) = self.intersect(Ray::new(p, direction, 0.0), 0.0, f64::INFINITY)?;
+        let pdf = area_to_solid_angle(1.0 / self.area, t, Vec3::dot(self.normal, direction));
+        if pdf == 0.0 {
+            return None;
+        }
+        Some(LightSample{direction, radiance: self.radiance, pdf, distance: t})
+    }
+
+    fn sample_emission(&self) -> Option<EmissionSample> {
+        let point = self.corner + drand48() * self.edge1 + drand48() * self.edge2;
+        let (direction, _) = sample_cosine_emission(self.normal, true);
+        self.eval_emission(point, direction)
+    }
+
+    fn eval_emission(&self, point: Vec3, direction: Vec3) -> Option<EmissionSample> {
+        let pdf_direction = cosine_emission_pdf(self.normal, direction, true);
+        if pdf_direction == 0.0 {
+            return None;
+        }
+        Some(EmissionSample{origin: point, direction, normal: self.normal, radiance: self.radiance, pdf_position: 1.0 / self.area, pdf_direction})
+    }
+}
+
+impl Hittable for QuadLight {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (t, u, v) = self.intersect(r, t_min, t_max)?;
+        Some(HitRecord::new(t, r.point_at_parameter(t), self.normal, Arc::clone(&self.material), u, v))
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        let corners = [self.corner + self.edge1, self.corner + self.edge2, self.corner + self.edge1 + self.edge2];
+        let bbox = corners.iter().fold(AABB::new(self.corner, self.corner), |b, &c| AABB::merge(&b, &AABB::new(c, c)));
+        // pad the box so axis aligned quads don't have a flat one
+        Some(AABB::new(bbox.min - super::EPSILON, bbox.max + super::EPSILON))
+    }
+}
+
+/// A triangle mesh emitting from both faces of every triangle.
+pub struct MeshLight {
+    triangles: Vec<[Vec3; 3]>,
+    /// the triangles are picked in proportion to their area
+    distribution: Distribution1D,
+    area: f64,
+    radiance: Vec3,
+    material: Arc<dyn Material>
+}
+
+impl MeshLight {
+    pub fn new(triangles: Vec<[Vec3; 3]>, radiance: Vec3) -> Self {
+        let areas: Vec<f64> = triangles.iter().map(|t| 0.5 * Vec3::cross(t[1] - t[0], t[2] - t[0]).length()).collect();
+        let area = areas.iter().sum();
+        assert!(area > 0.0, "A mesh light needs at least one triangle with an area");
+        let material = emitter(radiance);
+        MeshLight{triangles, distribution: Distribution1D::new(areas), area, radiance, material}
+    }
+
+    /// Builds the mesh from a list of vertices and triangles indexing into it.
+    pub fn from_indices(vertices: &[Vec3], indices: &[[usize; 3]], radiance: Vec3) -> Self {
+        Self::new(indices.iter().map(|i| [vertices[i[0]], vertices[i[1]], vertices[i[2]]]).collect(), radiance)
+    }
+
+    /// Finds the closest triangle hit by the ray, returning its index, the ray parameter and the barycentric coordinates.
+    fn intersect(&self, r: Ray, t_min: f64, t_max: f64) -> Option<(usize, f64, f64, f64)> {
+        let mut closest = None;
+        let mut t_max = t_max;
+        for (i, triangle) in self.triangles.iter().enumerate() {
+            if let Some((t, u, v)) = hit_triangle(triangle, r, t_min, t_max) {
+                t_max = t;
+                closest = Some((i, t, u, v));
+            }
+        }
+        closest
+    }
+
+    fn normal(&self, i: usize) -> Vec3 {
+        let t = &self.triangles[i];
+        Vec3::cross(t[1] - t[0], t[2] - t[0]).normalize()
+    }
+
+    /// The index of the triangle a point on the mesh lies on.
+    fn triangle_at(&self, point: Vec3) -> Option<usize> {
+        (0..self.triangles.len()).find(|&i| {
+            let normal = self.normal(i);
+            // cast a short ray back through the point along the normal
+            hit_triangle(&self.triangles[i], Ray::new(point + super::EPSILON * normal, -normal, 0.0), 0.0, 2.0 * super::EPSILON).is_some()
+        })
+    }
+}
+
+/// Moller-Trumbore ray triangle intersection.
+fn hit_triangle(triangle: &[Vec3; 3], r: Ray, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
+    let edge1 = triangle[1] - triangle[0];
+    let edge2 = triangle[2] - triangle[0];
+    let h = Vec3::cross(r.direction, edge2);
+    let a = Vec3::dot(edge1, h);
+    if a.abs() < 1e-12 {
+        return None;
+    }
+    let f = 1.0 / a;
+    let s = r.origin - triangle[0];
+    let u = f * Vec3::dot(s, h);
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = Vec3::cross(s, edge1);
+    let v = f * Vec3::dot(r.direction, q);
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = f * Vec3::dot(edge2, q);
+    if t <= t_min || t >= t_max {
+        return None;
+    }
+    Some((t, u, v))
+}
+
+impl Light for MeshLight {
+    fn sample(&self, p: Vec3) -> Option<LightSample> {
+        let (i, _) = self.distribution.sample_discrete(drand48());
+        let t = &self.triangles[i];
+        // uniform barycentric coordinates
+        let s = drand48().sqrt();
+        let (u, v) = (1.0 - s, drand48() * s);
+        let point = t[0] + u * (t[1] - t[0]) + v * (t[2] - t[0]);
+        self.eval(p, point - p)
+    }
+
+    fn eval(&self, p: Vec3, direction: Vec3) -> Option<LightSample> {
+        let direction = direction.normalize();
+        let (i, t, _, _) = self.intersect(Ray::new(p, direction, 0.0), 0.0, f64::INFINITY)?;
+        let pdf = area_to_solid_angle(1.0 / self.area, t, Vec3::dot(self.normal(i), direction));
+        if pdf == 0.0 {
+            return None;
+        }
+        Some(LightSample{direction, radiance: self.radiance, pdf, distance: t})
+    }
+
+    fn sample_emission(&self) -> Option<EmissionSample> {
+        let (i, _) = self.distribution.sample_discrete(drand48());
+        let t = &self.triangles[i];
+        let s = drand48().sqrt();
+        let (u, v) = (1.0 - s, drand48() * s);
+        let (direction, pdf_direction) = sample_cosine_emission(self.normal(i), true);
+        Some(EmissionSample{
+            origin: t[0] + u * (t[1] - t[0]) + v * (t[2] - t[0]),
+            direction,
+            normal: self.normal(i),
+            radiance: self.radiance,
+            pdf_position: 1.0 / self.area,
+            pdf_direction
+        })
+    }
+
+    fn eval_emission(&self, point: Vec3, direction: Vec3) -> Option<EmissionSample> {
+        let i = self.triangle_at(point)?;
+        let normal = self.normal(i);
+        let pdf_direction = cosine_emission_pdf(normal, direction, true);
+        if pdf_direction == 0.0 {
+            return None;
+        }
+        Some(EmissionSample{origin: point, direction, normal, radiance: self.radiance, pdf_position: 1.0 / self.area, pdf_direction})
+    }
+}
+
+impl Hittable for MeshLight {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (i, t, u, v) = self.intersect(r, t_min, t_max)?;
+        Some(HitRecord::new(t, r.point_at_parameter(t), self.normal(i), Arc::clone(&self.material), u, v))
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        let first = self.triangles[0][0];
+        let bbox = self.triangles.iter().flat_map(|t| t.iter()).fold(AABB::new(first, first), |b, &c| AABB::merge(&b, &AABB::new(c, c)));
+        Some(AABB::new(bbox.min - super::EPSILON, bbox.max + super::EPSILON))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks that samples from an area light agree with evaluating the light in the sampled direction.
+    fn check_samples(light: &dyn Light, p: Vec3) {
+        for _ in 0..100 {
+            let sample = light.sample(p).unwrap();
+            let eval = light.eval(p, sample.direction).unwrap();
+            assert!((sample.pdf - eval.pdf).abs() < 1e-6 * eval.pdf);
+            assert!((sample.distance - eval.distance).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn area_lights() {
+        let p = Vec3::new(0.3, -2.0, 0.1);
+        check_samples(&SphereLight::new(Vec3::new(0.0, 1.0, 0.0), 0.5, Vec3::new(1.0, 1.0, 1.0)), p);
+        check_samples(&SphereLight::new(Vec3::new(0.0, 1.0, 0.0), 5.0, Vec3::new(1.0, 1.0, 1.0)), p);
+        check_samples(&QuadLight::new(Vec3::new(-1.0, 1.0, -1.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.5, 0.0, 2.0), Vec3::new(1.0, 1.0, 1.0)), p);
+        let vertices = [Vec3::new(-1.0, 1.0, -1.0), Vec3::new(1.0, 1.0, -1.0), Vec3::new(0.0, 1.5, 1.0), Vec3::new(0.0, 3.0, 0.0)];
+        check_samples(&MeshLight::from_indices(&vertices, &[[0, 1, 2], [0, 1, 3]], Vec3::new(1.0, 1.0, 1.0)), p);
+    }
+
+    #[test]
+    fn quad_light_pdf_integrates_to_one() {
+        // the solid angle pdf over the hemisphere facing the light integrates to one
+        let light = QuadLight::new(Vec3::new(-1.0, 1.0, -1.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 2.0), Vec3::new(1.0, 1.0, 1.0));
+        let n = 400;
+        let mut total = 0.0;
+        for i in 0..n {
+            let theta = 0.5 * std::f64::consts::PI * (i as f64 + 0.5) / n as f64;
+            for j in 0..4 * n {
+                let phi = 2.0 * std::f64::consts::PI * (j as f64 + 0.5) / (4 * n) as f64;
+                let direction = Vec3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin());
+                if let Some(sample) = light.eval(Vec3::zero(), direction) {
+                    total += sample.pdf * theta.sin() * (0.5 * std::f64::consts::PI / n as f64) * (2.0 * std::f64::consts::PI / (4 * n) as f64);
+                }
+            }
+        }
+        assert!((total - 1.0).abs() < 0.01, "{}", total);
+    }
+
+    #[test]
+    fn spot_light_falloff() {
+        let light = SpotLight::new(Vec3::zero(), -Vec3::unit_y(), Vec3::new(1.0, 1.0, 1.0), 0.5, 0.25);
+        let below = light.sample(Vec3::new(0.0, -2.0, 0.0)).unwrap();
+        assert_eq!(below.radiance, Vec3::new(0.25, 0.25, 0.25));
+        // halfway between the start of the falloff and the edge
+        let angle: f64 = 0.375;
+        let p = Vec3::new(angle.tan(), -1.0, 0.0);
+        let partial = light.sample(p).unwrap().radiance.x() * p.length_squared();
+        assert!(partial > 0.0 && partial < 1.0);
+        assert!(light.sample(Vec3::new(0.0, 2.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn emission() {
+        let lights: Vec<Box<dyn Light>> = vec![
+            Box::new(PointLight::new(Vec3::zero(), Vec3::new(1.0, 1.0, 1.0))),
+            Box::new(SpotLight::new(Vec3::zero(), Vec3::unit_y(), Vec3::new(1.0, 1.0, 1.0), 0.5, 0.3)),
+            Box::new(SphereLight::new(Vec3::new(1.0, 2.0, 3.0), 0.5, Vec3::new(1.0, 1.0, 1.0))),
+            Box::new(QuadLight::new(Vec3::zero(), Vec3::unit_x(), Vec3::new(0.0, 1.0, 1.0), Vec3::new(1.0, 1.0, 1.0))),
+            Box::new(MeshLight::new(vec![[Vec3::zero(), Vec3::unit_x(), Vec3::unit_z()], [Vec3::unit_y(), Vec3::unit_x(), Vec3::new(1.0, 1.0, 1.0)]], Vec3::new(1.0, 1.0, 1.0)))
+        ];
+        // evaluating a sample gives back the same densities
+        for light in lights.iter() {
+            for _ in 0..100 {
+                let sample = light.sample_emission().unwrap();
+                let eval = light.eval_emission(sample.origin, sample.direction).unwrap();
+                assert!((sample.pdf_position - eval.pdf_position).abs() < 1e-9 * eval.pdf_position);
+                assert!((sample.pdf_direction - eval.pdf_direction).abs() < 1e-9 * eval.pdf_direction);
+                assert!((sample.normal - eval.normal).length() < 1e-9);
+            }
+        }
+        assert!(DirectionalLight::new(Vec3::unit_y(), Vec3::new(1.0, 1.0, 1.0)).sample_emission().is_none());
+    }
+}
@@ -1,18 +1,151 @@
 use super::ray::Ray;
 use super::Vec3;
 use super::hittable::HitRecord;
-use super::util::{random_in_unit_sphere, drand48};
-use super::texture::Texture;
+use super::util::{drand48, degrees_to_radians, luminance};
+use super::texture::{Texture, ConstantTexture};
+use super::spectrum::{blackbody_color, Dispersion, D_LINE};
+use super::sampling::{cosine_hemisphere, uniform_sphere};
+use super::microfacet::{Ggx, Frame, fresnel_conductor};
+use std::ops::BitOr;
 use std::sync::Arc;
 
+/// Flags describing the kind of lobe a direction was sampled from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Lobe(u8);
+
+impl Lobe {
+    pub const REFLECTION: Lobe = Lobe(1);
+    pub const TRANSMISSION: Lobe = Lobe(2);
+    pub const DIFFUSE: Lobe = Lobe(4);
+    pub const GLOSSY: Lobe = Lobe(8);
+    /// a single direction, like a mirror, which can't be evaluated for other directions
+    pub const SPECULAR: Lobe = Lobe(16);
+
+    pub fn contains(self, other: Lobe) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn is_specular(self) -> bool {
+        self.contains(Lobe::SPECULAR)
+    }
+}
+
+impl BitOr for Lobe {
+    type Output = Lobe;
+
+    fn bitor(self, other: Lobe) -> Lobe {
+        Lobe(self.0 | other.0)
+    }
+}
+
+/// A direction sampled from a material.
+#[derive(Clone, Copy, Debug)]
+pub struct BsdfSample {
+    /// the unit direction light arrives from, pointing away from the hit
+    pub wi: Vec3,
+    /// the bsdf times the cosine over the pdf, which light arriving along wi is multiplied by
+    pub weight: Vec3,
+    /// the solid angle pdf of sampling wi, which is 1 for specular lobes
+    pub pdf: f64,
+    pub lobe: Lobe
+}
+
+/// Materials describe how light scatters at a hit. Directions are unit vectors pointing away from the hit,
+/// wo towards where the light leaves to (back along the incoming ray) and wi towards where it arrives from.
 pub trait Material: Send + Sync {
-    fn scatter(&self, _ray_in: &Ray, _hit_record: &HitRecord) -> Option<(Ray, Vec3)> {
+    /// Samples a direction wi using three uniform random numbers. Returns None if the light is absorbed.
+    fn sample(&self, _hit_record: &HitRecord, _wo: Vec3, _u: [f64; 3]) -> Option<BsdfSample> {
         None
     }
 
-    fn emitted(&self, _u: f64, _v: f64, _p: Vec3) -> Vec3 {
+    /// The bsdf times the absolute cosine of wi to the normal, or the phase function for media.
+    /// Specular lobes can't be evaluated and are left out.
+    fn eval(&self, _hit_record: &HitRecord, _wo: Vec3, _wi: Vec3) -> Vec3 {
+        Vec3::zero()
+    }
+
+    /// The solid angle pdf of sample returning wi, leaving out specular lobes.
+    fn pdf(&self, _hit_record: &HitRecord, _wo: Vec3, _wi: Vec3) -> f64 {
+        0.0
+    }
+
+    /// The radiance emitted from the hit back along the incoming ray.
+    fn emitted(&self, _ray_in: &Ray, _hit_record: &HitRecord) -> Vec3 {
         Vec3::new(0.0, 0.0, 0.0)
     }
+
+    /// Whether the directions sample picks depend on the hit's wavelength, so only the hero wavelength can follow them.
+    fn is_dispersive(&self) -> bool {
+        false
+    }
+
+    /// Whether sample only picks specular directions, so no light arrives from directions picked any other way.
+    fn is_specular(&self) -> bool {
+        false
+    }
+
+    /// Whether light goes in below the surface at the hit and comes back out somewhere else, which is found with
+    /// sample_subsurface instead of scattering it at the hit.
+    fn is_subsurface(&self) -> bool {
+        false
+    }
+
+    /// Follows light that goes in below the surface at the hit from wo to where it comes back out, returning the hit
+    /// there, whose material scatters it out, and the fraction of each channel that makes it. Returns None if the
+    /// light is absorbed.
+    fn sample_subsurface(&self, _hit_record: &HitRecord, _wo: Vec3, _time: f64) -> Option<(HitRecord, Vec3)> {
+        None
+    }
+
+    /// Scatters the incoming ray, returning the scattered ray and its attenuation.
+    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<(Ray, Vec3)> {
+        let sample = self.sample(hit_record, -ray_in.direction.normalize(), [drand48(), drand48(), drand48()])?;
+        Some((Ray::new(offset_origin(hit_record, sample.wi), sample.wi, ray_in.time), sample.weight))
+    }
+}
+
+/// The origin for a ray leaving the hit in direction, nudged off the surface to the side it travels to.
+pub fn offset_origin(hit_record: &HitRecord, direction: Vec3) -> Vec3 {
+    if Vec3::dot(direction, hit_record.normal) < 0.0 { hit_record.p - super::EPSILON * hit_record.normal } else { hit_record.p + super::EPSILON * hit_record.normal }
+}
+
+/// Where light that reached the hit from wo scatters, along with the direction it's seen from there and the
+/// fraction of each channel that gets there. That's the hit itself, except for subsurface materials, which carry the
+/// light below the surface to where it comes back out. Returns None if the light is absorbed on the way.
+pub fn scattering_hit(hit_record: HitRecord, wo: Vec3, time: f64) -> Option<(HitRecord, Vec3, Vec3)> {
+    if !hit_record.material.is_subsurface() {
+        return Some((hit_record, wo, Vec3::new(1.0, 1.0, 1.0)));
+    }
+    let (exit, weight) = hit_record.material.sample_subsurface(&hit_record, wo, time)?;
+    // light leaves out of the surface, so it's seen from outside
+    let wo = exit.normal;
+    Some((exit, wo, weight))
+}
+
+/// The normal on the side of the surface wo is on, for materials that look the same from both sides.
+fn facing_normal(hit_record: &HitRecord, wo: Vec3) -> Vec3 {
+    if Vec3::dot(wo, hit_record.normal) < 0.0 { -hit_record.normal } else { hit_record.normal }
+}
+
+/// A diffuse bsdf of a color, times the cosine, for directions on the side of the normal.
+fn diffuse_eval(color: Vec3, normal: Vec3, wo: Vec3, wi: Vec3) -> (Vec3, f64) {
+    let normal = if Vec3::dot(wo, normal) < 0.0 { -normal } else { normal };
+    let cosine = Vec3::dot(wi, normal);
+    if cosine <= 0.0 {
+        return (Vec3::zero(), 0.0);
+    }
+    (color * cosine / std::f64::consts::PI, cosine / std::f64::consts::PI)
+}
+
+/// Samples a cosine weighted direction about the normal facing wo.
+fn diffuse_sample(color: Vec3, hit_record: &HitRecord, wo: Vec3, u: [f64; 3]) -> Option<BsdfSample> {
+    let frame = Frame::new(facing_normal(hit_record, wo));
+    let local = cosine_hemisphere(u[0], u[1]);
+    if local.z() <= 0.0 {
+        return None;
+    }
+    let wi = frame.to_world(local);
+    Some(BsdfSample{wi, weight: color, pdf: local.z() / std::f64::consts::PI, lobe: Lobe::DIFFUSE | Lobe::REFLECTION})
 }
 
 pub struct Lambertian {
@@ -26,14 +159,21 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _ray_in: &Ray, hit_record: &HitRecord) -> Option<(Ray, Vec3)> {
-        let target: Vec3 = hit_record.p + hit_record.normal + random_in_unit_sphere();
-        let scattered = Ray::new(hit_record.p, target - hit_record.p);
-        let albedo = self.albedo.value(hit_record.u, hit_record.v, hit_record.p);
-        Some((scattered, albedo))
+    fn sample(&self, hit_record: &HitRecord, wo: Vec3, u: [f64; 3]) -> Option<BsdfSample> {
+        diffuse_sample(self.albedo.value(hit_record.u, hit_record.v, hit_record.p), hit_record, wo, u)
+    }
+
+    fn eval(&self, hit_record: &HitRecord, wo: Vec3, wi: Vec3) -> Vec3 {
+        diffuse_eval(self.albedo.value(hit_record.u, hit_record.v, hit_record.p), hit_record.normal, wo, wi).0
+    }
+
+    fn pdf(&self, hit_record: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        diffuse_eval(Vec3::zero(), hit_record.normal, wo, wi).1
     }
 }
 
+/// A mirror made rough by randomly perturbing the reflection. The perturbation isn't a distribution
+/// that can be evaluated so it's treated as specular, RoughConductor is the physically based alternative.
 pub struct Metal {
     pub albedo: Arc<dyn Texture>,
     pub roughness: f64
@@ -46,86 +186,370 @@ impl Metal {
 }
 
 impl Material for Metal {
-    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<(Ray, Vec3)> {
-        let reflected = reflect(ray_in.direction.normalize(), hit_record.normal);
-        let scattered = Ray::new(hit_record.p,
-            reflected + self.roughness * random_in_unit_sphere());
-        if Vec3::dot(scattered.direction, hit_record.normal) > 0.0 {
+    fn sample(&self, hit_record: &HitRecord, wo: Vec3, u: [f64; 3]) -> Option<BsdfSample> {
+        let reflected = reflect(-wo, hit_record.normal);
+        // a uniform point in the unit ball
+        let fuzz = u[0].cbrt() * uniform_sphere(u[1], u[2]);
+        let wi = (reflected + self.roughness * fuzz).normalize();
+        if Vec3::dot(wi, hit_record.normal) > 0.0 {
             let albedo = self.albedo.value(hit_record.u, hit_record.v, hit_record.p);
-            Some((scattered, albedo))
+            Some(BsdfSample{wi, weight: albedo, pdf: 1.0, lobe: Lobe::SPECULAR | Lobe::REFLECTION})
         }
         else {
             None
         }
     }
+
+    fn is_specular(&self) -> bool {
+        true
+    }
 }
 
 pub struct Dielectric {
-    refractive_index: f64
+    refractive_index: f64,
+    dispersion: Option<Dispersion>
 }
 
 impl Dielectric {
     pub fn new(refractive_index: f64) -> Self {
-        Dielectric{ refractive_index }
+        Dielectric{ refractive_index, dispersion: None }
+    }
+
+    /// A dielectric whose index of refraction varies with wavelength. Without spectral rendering it uses the index at the D line.
+    pub fn dispersive(dispersion: Dispersion) -> Self {
+        Dielectric{ refractive_index: dispersion.refractive_index(D_LINE), dispersion: Some(dispersion) }
+    }
+
+    fn refractive_index_at(&self, hit_record: &HitRecord) -> f64 {
+        match (self.dispersion, hit_record.wavelength) {
+            (Some(dispersion), Some(wavelength)) => dispersion.refractive_index(wavelength),
+            _ => self.refractive_index
+        }
     }
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<(Ray, Vec3)> {
+    fn sample(&self, hit_record: &HitRecord, wo: Vec3, u: [f64; 3]) -> Option<BsdfSample> {
+        let refractive_index = self.refractive_index_at(hit_record);
+        let direction = -wo;
         let outward_normal: Vec3;
-        let reflected = reflect(ray_in.direction, hit_record.normal);
         let ni_over_nt: f64;
-        let attenuation = Vec3::new(1.0, 1.0, 1.0);
-        let refracted: Vec3;
-        let refracted_prob: f64;
         let mut cosine: f64;
-        let scattered: Ray;
 
-        if Vec3::dot(ray_in.direction, hit_record.normal) > 0.0 {
+        if Vec3::dot(direction, hit_record.normal) > 0.0 {
             outward_normal = -hit_record.normal;
-            ni_over_nt = self.refractive_index;
-            cosine = Vec3::dot(ray_in.direction, hit_record.normal) / ray_in.direction.length();
-            cosine = f64::sqrt(1.0-self.refractive_index*self.refractive_index*(1.0-cosine*cosine));
+            ni_over_nt = refractive_index;
+            cosine = Vec3::dot(direction, hit_record.normal);
+            cosine = f64::sqrt(1.0-refractive_index*refractive_index*(1.0-cosine*cosine));
         }
         else {
             outward_normal = hit_record.normal;
-            ni_over_nt = 1.0 / self.refractive_index;
-            cosine = -Vec3::dot(ray_in.direction, hit_record.normal) / ray_in.direction.length();
+            ni_over_nt = 1.0 / refractive_index;
+            cosine = -Vec3::dot(direction, hit_record.normal);
         }
-        match refract(ray_in.direction, outward_normal, ni_over_nt) {
-            Some(v) => {
-                refracted = v;
-                refracted_prob = schlick(cosine, self.refractive_index);
-            }
-            None => {
-                scattered = Ray::new(hit_record.p/*  + super::EPSILON * hit_record.normal */, reflected);
-                return Some((scattered, attenuation));
+        let reflected = BsdfSample{wi: reflect(direction, hit_record.normal), weight: Vec3::new(1.0, 1.0, 1.0), pdf: 1.0, lobe: Lobe::SPECULAR | Lobe::REFLECTION};
+        match refract(direction, outward_normal, ni_over_nt) {
+            Some(refracted) if u[0] >= schlick(cosine, refractive_index) => {
+                Some(BsdfSample{wi: refracted.normalize(), lobe: Lobe::SPECULAR | Lobe::TRANSMISSION, ..reflected})
             }
+            _ => Some(reflected)
+        }
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.dispersion.is_some()
+    }
+
+    fn is_specular(&self) -> bool {
+        true
+    }
+}
+
+/// A metal with a GGX microfacet surface and the Fresnel reflectance of its complex index of refraction.
+pub struct RoughConductor {
+    eta: Vec3,
+    k: Vec3,
+    roughness: f64,
+    /// multiplies the reflectance, for metals not covered by the presets
+    tint: Arc<dyn Texture>
+}
+
+impl RoughConductor {
+    /// Creates a conductor from its index of refraction eta + ik at red, green and blue wavelengths.
+    pub fn new(eta: Vec3, k: Vec3, roughness: f64) -> Self {
+        RoughConductor{eta, k, roughness, tint: Arc::new(ConstantTexture::new(Vec3::new(1.0, 1.0, 1.0)))}
+    }
+
+    pub fn gold(roughness: f64) -> Self {
+        Self::new(Vec3::new(0.143, 0.374, 1.442), Vec3::new(3.983, 2.385, 1.603), roughness)
+    }
+
+    pub fn copper(roughness: f64) -> Self {
+        Self::new(Vec3::new(0.200, 0.924, 1.102), Vec3::new(3.912, 2.452, 2.142), roughness)
+    }
+
+    pub fn silver(roughness: f64) -> Self {
+        Self::new(Vec3::new(0.155, 0.117, 0.138), Vec3::new(4.828, 3.122, 2.147), roughness)
+    }
+
+    pub fn aluminum(roughness: f64) -> Self {
+        Self::new(Vec3::new(1.657, 0.880, 0.521), Vec3::new(9.224, 6.270, 4.837), roughness)
+    }
+
+    pub fn set_tint(&mut self, tint: Arc<dyn Texture>) {
+        self.tint = tint;
+    }
+
+    fn reflectance(&self, hit_record: &HitRecord, cosine: f64) -> Vec3 {
+        fresnel_conductor(cosine, self.eta, self.k) * self.tint.value(hit_record.u, hit_record.v, hit_record.p)
+    }
+}
+
+impl Material for RoughConductor {
+    fn sample(&self, hit_record: &HitRecord, wo: Vec3, u: [f64; 3]) -> Option<BsdfSample> {
+        // metals are opaque, so shade whichever side was hit
+        let frame = Frame::new(facing_normal(hit_record, wo));
+        let wo = frame.to_local(wo);
+        let ggx = Ggx::from_roughness(self.roughness);
+        if ggx.is_smooth() {
+            let wi = Vec3::new(-wo.x(), -wo.y(), wo.z());
+            return Some(BsdfSample{wi: frame.to_world(wi), weight: self.reflectance(hit_record, wo.z()), pdf: 1.0, lobe: Lobe::SPECULAR | Lobe::REFLECTION});
+        }
+        let h = ggx.sample_visible_normal(wo, u[0], u[1]);
+        let wi = super::microfacet::reflect_about(wo, h);
+        // light reflected below the surface would scatter again between the microfacets, which isn't modeled
+        if wi.z() <= 0.0 {
+            return None;
         }
-        if drand48() < refracted_prob {
-            scattered = Ray::new(hit_record.p, reflected);
+        // with visible normal sampling the weight is the fresnel term times the masking of wi given wo is visible
+        let weight = self.reflectance(hit_record, Vec3::dot(wo, h)) * (ggx.g(wo, wi) / ggx.g1(wo));
+        Some(BsdfSample{wi: frame.to_world(wi), weight, pdf: ggx.reflection_pdf(wo, wi), lobe: Lobe::GLOSSY | Lobe::REFLECTION})
+    }
+
+    fn eval(&self, hit_record: &HitRecord, wo: Vec3, wi: Vec3) -> Vec3 {
+        let ggx = Ggx::from_roughness(self.roughness);
+        let frame = Frame::new(facing_normal(hit_record, wo));
+        let (wo, wi) = (frame.to_local(wo), frame.to_local(wi));
+        if ggx.is_smooth() || wi.z() <= 0.0 {
+            return Vec3::zero();
+        }
+        let h = (wo + wi).normalize();
+        ggx.reflection_eval(wo, wi) * self.reflectance(hit_record, Vec3::dot(wo, h))
+    }
+
+    fn pdf(&self, hit_record: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        let ggx = Ggx::from_roughness(self.roughness);
+        let frame = Frame::new(facing_normal(hit_record, wo));
+        if ggx.is_smooth() { 0.0 } else { ggx.reflection_pdf(frame.to_local(wo), frame.to_local(wi)) }
+    }
+
+    fn is_specular(&self) -> bool {
+        Ggx::from_roughness(self.roughness).is_smooth()
+    }
+}
+
+/// Glass with a GGX microfacet surface, which gives frosted reflection and refraction.
+/// See Walter et al, "Microfacet Models for Refraction through Rough Surfaces".
+pub struct RoughDielectric {
+    refractive_index: f64,
+    roughness: f64
+}
+
+impl RoughDielectric {
+    pub fn new(refractive_index: f64, roughness: f64) -> Self {
+        RoughDielectric{refractive_index, roughness}
+    }
+
+    /// The frame on the side of the surface wo is on, with eta the index of the other side over this one.
+    fn frame(&self, hit_record: &HitRecord, wo: Vec3) -> (Frame, f64) {
+        if Vec3::dot(wo, hit_record.normal) > 0.0 {
+            (Frame::new(hit_record.normal), self.refractive_index)
         }
         else {
-            scattered = Ray::new(hit_record.p - 2.0 * super::EPSILON * outward_normal, refracted);
+            (Frame::new(-hit_record.normal), 1.0 / self.refractive_index)
+        }
+    }
+}
+
+impl Material for RoughDielectric {
+    fn sample(&self, hit_record: &HitRecord, wo: Vec3, u: [f64; 3]) -> Option<BsdfSample> {
+        let (frame, eta) = self.frame(hit_record, wo);
+        let wo = frame.to_local(wo);
+        let ggx = Ggx::from_roughness(self.roughness);
+        if ggx.is_smooth() {
+            return Dielectric::new(self.refractive_index).sample(hit_record, frame.to_world(wo), u);
+        }
+        let (wi, weight) = ggx.sample_dielectric(wo, eta, u)?;
+        let lobe = if wi.z() > 0.0 { Lobe::GLOSSY | Lobe::REFLECTION } else { Lobe::GLOSSY | Lobe::TRANSMISSION };
+        Some(BsdfSample{wi: frame.to_world(wi), weight: Vec3::new(1.0, 1.0, 1.0) * weight, pdf: ggx.dielectric_eval(wo, wi, eta).1, lobe})
+    }
+
+    fn eval(&self, hit_record: &HitRecord, wo: Vec3, wi: Vec3) -> Vec3 {
+        let (frame, eta) = self.frame(hit_record, wo);
+        let ggx = Ggx::from_roughness(self.roughness);
+        if ggx.is_smooth() {
+            return Vec3::zero();
+        }
+        Vec3::new(1.0, 1.0, 1.0) * ggx.dielectric_eval(frame.to_local(wo), frame.to_local(wi), eta).0
+    }
+
+    fn pdf(&self, hit_record: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        let (frame, eta) = self.frame(hit_record, wo);
+        let ggx = Ggx::from_roughness(self.roughness);
+        if ggx.is_smooth() { 0.0 } else { ggx.dielectric_eval(frame.to_local(wo), frame.to_local(wi), eta).1 }
+    }
+
+    fn is_specular(&self) -> bool {
+        Ggx::from_roughness(self.roughness).is_smooth()
+    }
+}
+
+/// Which sides of a surface a light emits from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EmissionSide {
+    /// the side the surface normal points to, the outside of closed objects
+    Front,
+    Both,
+    /// only the front of parts of the surface facing along the direction, so a thin cuboid can emit from one face and not its edges
+    Facing(Vec3)
+}
+
+/// The total power emitted by a light.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Power {
+    /// radiant power, the mean of the color channels
+    Watts(f64),
+    /// luminous power, at the maximum efficacy of 683 lumens per watt
+    Lumens(f64)
+}
+
+/// How the intensity of a light varies with the angle from the direction it faces, like the vertical angles of an IES profile.
+/// Values are linearly interpolated between the angles and are zero beyond the last one.
+#[derive(Clone, Debug)]
+pub struct AngularProfile {
+    /// increasing angles in radians
+    angles: Vec<f64>,
+    values: Vec<f64>
+}
+
+impl AngularProfile {
+    pub fn new(angles: Vec<f64>, values: Vec<f64>) -> Self {
+        assert!(!angles.is_empty() && angles.len() == values.len(), "An angular profile needs a value for each angle");
+        assert!(angles.windows(2).all(|w| w[0] < w[1]), "The angles of an angular profile must be increasing");
+        AngularProfile{angles, values}
+    }
+
+    /// Builds a profile from angles in degrees and candela values, as listed in an IES file.
+    pub fn from_degrees(angles: &[f64], candela: &[f64]) -> Self {
+        Self::new(angles.iter().map(|a| degrees_to_radians(*a)).collect(), candela.to_vec())
+    }
+
+    pub fn value(&self, angle: f64) -> f64 {
+        let i = self.angles.iter().position(|a| *a > angle).unwrap_or(self.angles.len());
+        if i == 0 {
+            return self.values[0];
         }
+        if i == self.angles.len() {
+            return if angle <= self.angles[i - 1] + 1e-9 { self.values[i - 1] } else { 0.0 };
+        }
+        let t = (angle - self.angles[i - 1]) / (self.angles[i] - self.angles[i - 1]);
+        (1.0 - t) * self.values[i - 1] + t * self.values[i]
+    }
 
-        Some((scattered, attenuation))
+    /// The integral of the profile times the cosine over the hemisphere, which is pi for a constant profile of 1.
+    fn projected_integral(&self) -> f64 {
+        const STEPS: usize = 1024;
+        let d_theta = 0.5 * std::f64::consts::PI / STEPS as f64;
+        (0..STEPS).map(|i| {
+            let theta = (i as f64 + 0.5) * d_theta;
+            self.value(theta) * theta.cos() * theta.sin() * d_theta
+        }).sum::<f64>() * 2.0 * std::f64::consts::PI
     }
 }
 
+/// A diffuse emitter, the emitted radiance is the texture times a scale which is 1 unless a total power is given.
 pub struct DiffuseLight {
-    emit: Arc<dyn Texture>
+    emit: Arc<dyn Texture>,
+    side: EmissionSide,
+    profile: Option<AngularProfile>,
+    /// the power and the area of the surface it's spread over
+    power: Option<(Power, f64)>,
+    scale: f64
 }
 
 impl DiffuseLight {
     pub fn new(emit: Arc<dyn Texture>) -> Self {
-        DiffuseLight{emit}
+        DiffuseLight{emit, side: EmissionSide::Front, profile: None, power: None, scale: 1.0}
+    }
+
+    /// A light of a color that emits a total power from a surface with an area.
+    /// The color is only used for its hue, it's normalized to match the type of power.
+    pub fn with_power(color: Vec3, power: Power, area: f64) -> Self {
+        let mut light = Self::new(Arc::new(ConstantTexture::new(color)));
+        light.set_power(power, area);
+        light
+    }
+
+    /// A light with the color of a blackbody at a temperature in kelvin, with the luminance given by intensity.
+    pub fn blackbody(temperature: f64, intensity: f64) -> Self {
+        Self::new(Arc::new(ConstantTexture::new(intensity * blackbody_color(temperature))))
+    }
+
+    pub fn set_side(&mut self, side: EmissionSide) {
+        self.side = side;
+        self.update_scale();
+    }
+
+    pub fn set_profile(&mut self, profile: AngularProfile) {
+        self.profile = Some(profile);
+        self.update_scale();
+    }
+
+    /// Scales the emission so the light emits a total power from a surface with an area. This assumes
+    /// the surface is flat when a profile is used, and for EmissionSide::Facing that the surface faces the direction.
+    pub fn set_power(&mut self, power: Power, area: f64) {
+        assert!(area > 0.0, "The area of a light must be positive");
+        self.power = Some((power, area));
+        self.update_scale();
+    }
+
+    fn update_scale(&mut self) {
+        self.scale = match self.power {
+            Some((power, area)) => {
+                let color = self.emit.value(0.5, 0.5, Vec3::zero());
+                let (power, reference) = match power {
+                    Power::Watts(watts) => (watts, (color.x() + color.y() + color.z()) / 3.0),
+                    Power::Lumens(lumens) => (lumens / 683.0, luminance(color))
+                };
+                // for a constant radiance L the power is L times the area times the projected solid angle
+                let projected = match &self.profile {
+                    Some(profile) => profile.projected_integral(),
+                    None => std::f64::consts::PI
+                };
+                let sides = if self.side == EmissionSide::Both { 2.0 } else { 1.0 };
+                if reference > 0.0 { power / (reference * area * projected * sides) } else { 0.0 }
+            }
+            None => 1.0
+        };
     }
 }
 
 impl Material for DiffuseLight {
-    fn emitted(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
-        self.emit.value(u, v, p)
+    fn emitted(&self, ray_in: &Ray, hit_record: &HitRecord) -> Vec3 {
+        let outgoing = -ray_in.direction.normalize();
+        let cosine = Vec3::dot(outgoing, hit_record.normal);
+        // the direction the emitting side faces
+        let facing = match self.side {
+            EmissionSide::Front if cosine > 0.0 => hit_record.normal,
+            EmissionSide::Both => if cosine > 0.0 { hit_record.normal } else { -hit_record.normal },
+            EmissionSide::Facing(direction) if cosine > 0.0 && Vec3::dot(hit_record.normal, direction) > 1e-6 => direction.normalize(),
+            _ => return Vec3::zero()
+        };
+        let mut radiance = self.scale * self.emit.value(hit_record.u, hit_record.v, hit_record.p);
+        if let Some(profile) = &self.profile {
+            radiance *= profile.value(Vec3::dot(outgoing, facing).clamp(-1.0, 1.0).acos());
+        }
+        radiance
     }
 }
 
@@ -140,10 +564,17 @@ impl Isotropic {
 }
 
 impl Material for Isotropic {
-    fn scatter(&self, _ray_in: &Ray, hit_record: &HitRecord) -> Option<(Ray, Vec3)> {
-        let scattered = Ray::new(hit_record.p, random_in_unit_sphere());
-        let attenuation = self.albedo.value(hit_record.u, hit_record.v, hit_record.p);
-        Some((scattered, attenuation))
+    fn sample(&self, hit_record: &HitRecord, _wo: Vec3, u: [f64; 3]) -> Option<BsdfSample> {
+        let albedo = self.albedo.value(hit_record.u, hit_record.v, hit_record.p);
+        Some(BsdfSample{wi: uniform_sphere(u[0], u[1]), weight: albedo, pdf: 1.0 / (4.0 * std::f64::consts::PI), lobe: Lobe::DIFFUSE | Lobe::REFLECTION | Lobe::TRANSMISSION})
+    }
+
+    fn eval(&self, hit_record: &HitRecord, _wo: Vec3, _wi: Vec3) -> Vec3 {
+        self.albedo.value(hit_record.u, hit_record.v, hit_record.p) / (4.0 * std::f64::consts::PI)
+    }
+
+    fn pdf(&self, _hit_record: &HitRecord, _wo: Vec3, _wi: Vec3) -> f64 {
+        1.0 / (4.0 * std::f64::consts::PI)
     }
 }
 
@@ -156,11 +587,16 @@ impl NormalMaterial {
 }
 
 impl Material for NormalMaterial {
-    fn scatter(&self, _ray_in: &Ray, hit_record: &HitRecord) -> Option<(Ray, Vec3)> {
-        let target: Vec3 = hit_record.p + hit_record.normal + random_in_unit_sphere();
-        let scattered = Ray::new(hit_record.p, target - hit_record.p);
-        let attenuation = 0.5 * (hit_record.normal + 1.0);
-        Some((scattered, attenuation))
+    fn sample(&self, hit_record: &HitRecord, wo: Vec3, u: [f64; 3]) -> Option<BsdfSample> {
+        diffuse_sample(0.5 * (hit_record.normal + 1.0), hit_record, wo, u)
+    }
+
+    fn eval(&self, hit_record: &HitRecord, wo: Vec3, wi: Vec3) -> Vec3 {
+        diffuse_eval(0.5 * (hit_record.normal + 1.0), hit_record.normal, wo, wi).0
+    }
+
+    fn pdf(&self, hit_record: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        diffuse_eval(Vec3::zero(), hit_record.normal, wo, wi).1
     }
 }
 
@@ -198,4 +634,90 @@ mod tests{
         assert_eq!(v.y(), 1.0);
         assert_eq!(v.z(), 0.0);
     }
-}
\ No newline at end of file
+
+    fn light_hit(light: DiffuseLight, normal: Vec3, direction: Vec3) -> Vec3 {
+        let rec = HitRecord::new(1.0, Vec3::zero(), normal, Arc::new(NormalMaterial::new()), 0.5, 0.5);
+        light.emitted(&Ray::new(-direction, direction, 0.0), &rec)
+    }
+
+    #[test]
+    fn emission_sides() {
+        let white = || DiffuseLight::new(Arc::new(ConstantTexture::new(Vec3::new(1.0, 1.0, 1.0))));
+        let down = -Vec3::unit_y();
+        assert_eq!(light_hit(white(), Vec3::unit_y(), down), Vec3::new(1.0, 1.0, 1.0));
+        assert_eq!(light_hit(white(), -Vec3::unit_y(), down), Vec3::zero());
+        let mut light = white();
+        light.set_side(EmissionSide::Both);
+        assert_eq!(light_hit(light, -Vec3::unit_y(), down), Vec3::new(1.0, 1.0, 1.0));
+        // the edge of a thin panel facing down doesn't emit
+        let mut light = white();
+        light.set_side(EmissionSide::Facing(-Vec3::unit_y()));
+        assert_eq!(light_hit(light, Vec3::unit_x(), -Vec3::unit_x()), Vec3::zero());
+    }
+
+    #[test]
+    fn emission_power() {
+        // a one sided lambertian emitter of area 2 emitting pi watts has a radiance of 0.5
+        let light = DiffuseLight::with_power(Vec3::new(2.0, 1.0, 0.0), Power::Watts(std::f64::consts::PI), 2.0);
+        assert!((light_hit(light, Vec3::unit_y(), -Vec3::unit_y()) - Vec3::new(1.0, 0.5, 0.0)).length() < 1e-12);
+        // a profile that only emits within 60 degrees of the normal
+        let mut light = DiffuseLight::with_power(Vec3::new(1.0, 1.0, 1.0), Power::Lumens(683.0), 1.0);
+        light.set_profile(AngularProfile::from_degrees(&[0.0, 60.0], &[1.0, 1.0]));
+        // the projected solid angle of the cone is pi sin^2(60)
+        let expected = 1.0 / (std::f64::consts::PI * 0.75);
+        assert!((light_hit(light, Vec3::unit_y(), -Vec3::unit_y()).y() - expected).abs() < 1e-2 * expected);
+    }
+
+    #[test]
+    fn rough_dielectric_reflectance() {
+        // a nearly smooth glass surface reflects about 4% of light at normal incidence
+        let glass = RoughDielectric::new(1.5, 0.01);
+        let rec = HitRecord::new(1.0, Vec3::zero(), Vec3::unit_y(), Arc::new(NormalMaterial::new()), 0.5, 0.5);
+        let ray = Ray::new(Vec3::unit_y(), -Vec3::unit_y(), 0.0);
+        let n = 20000;
+        let reflected = (0..n).filter(|_| glass.scatter(&ray, &rec).unwrap().0.direction.y() > 0.0).count();
+        let fraction = reflected as f64 / n as f64;
+        assert!((fraction - 0.04).abs() < 0.01, "{}", fraction);
+    }
+
+    #[test]
+    fn rough_conductor_energy() {
+        // single scattering never reflects more light than arrives
+        let gold = RoughConductor::gold(0.7);
+        let rec = HitRecord::new(1.0, Vec3::zero(), Vec3::unit_y(), Arc::new(NormalMaterial::new()), 0.5, 0.5);
+        let ray = Ray::new(Vec3::new(-1.0, 1.0, 0.0), Vec3::new(1.0, -1.0, 0.0), 0.0);
+        for _ in 0..1000 {
+            if let Some((scattered, attenuation)) = gold.scatter(&ray, &rec) {
+                assert!(scattered.direction.y() > 0.0);
+                assert!(attenuation.x() <= 1.0 && attenuation.y() <= 1.0 && attenuation.z() <= 1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn sample_matches_eval() {
+        // for non specular lobes the sample weight is eval over pdf, which multiple importance sampling relies on
+        let mut principled = crate::raytrace::principled::Principled::new(Arc::new(ConstantTexture::new(Vec3::new(0.8, 0.3, 0.2))));
+        principled.set_clearcoat(Arc::new(ConstantTexture::scalar(0.5)), Arc::new(ConstantTexture::scalar(0.3)));
+        let materials: Vec<Arc<dyn Material>> = vec![
+            Arc::new(Lambertian::new(Arc::new(ConstantTexture::new(Vec3::new(0.5, 0.5, 0.5))))),
+            Arc::new(RoughConductor::copper(0.4)),
+            Arc::new(RoughDielectric::new(1.5, 0.3)),
+            Arc::new(Isotropic::new(Arc::new(ConstantTexture::new(Vec3::new(0.5, 0.5, 0.5))))),
+            Arc::new(crate::raytrace::phase::PhaseMaterial::new(Arc::new(crate::raytrace::phase::HenyeyGreenstein::new(0.6)), Arc::new(ConstantTexture::scalar(0.9)))),
+            Arc::new(principled)
+        ];
+        let wo = Vec3::new(0.3, 0.8, -0.2).normalize();
+        for material in materials {
+            let rec = HitRecord::new(1.0, Vec3::zero(), Vec3::unit_y(), material.clone(), 0.5, 0.5);
+            for _ in 0..1000 {
+                if let Some(sample) = material.sample(&rec, wo, [drand48(), drand48(), drand48()]) {
+                    assert!(!sample.lobe.is_specular());
+                    let expected = material.eval(&rec, wo, sample.wi) / material.pdf(&rec, wo, sample.wi);
+                    assert!((sample.weight - expected).length() < 1e-6 * expected.length().max(1.0), "{:?} {:?}", sample.weight, expected);
+                    assert!((material.pdf(&rec, wo, sample.wi) - sample.pdf).abs() < 1e-6 * sample.pdf.max(1.0));
+                }
+            }
+        }
+    }
+}
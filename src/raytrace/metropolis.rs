@@ -0,0 +1,191 @@
+//! Primary sample space Metropolis light transport, after Kelemen et al. A path is made from a vector of random
+//! numbers, and a Markov chain mutates the numbers, keeping paths in proportion to the light they carry. Once a
+//! chain finds a hard to reach light path it explores the paths near it instead of starting over.
+
+use super::util::RandomStream;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// The number of mutations and how each one changes the random numbers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MetropolisSettings {
+    /// mutations made for each pixel of the image on average
+    pub mutations_per_pixel: u32,
+    /// paths traced to estimate the brightness of the image and pick where chains start
+    pub bootstrap_samples: usize,
+    pub chains: usize,
+    /// the standard deviation of the small changes made to each random number
+    pub sigma: f64,
+    /// the probability of a mutation replacing every random number, to find other paths
+    pub large_step_probability: f64
+}
+
+impl Default for MetropolisSettings {
+    fn default() -> Self {
+        MetropolisSettings{mutations_per_pixel: 100, bootstrap_samples: 100_000, chains: 1000, sigma: 0.01, large_step_probability: 0.3}
+    }
+}
+
+#[derive(Clone, Copy)]
+struct PrimarySample {
+    value: f64,
+    /// the iteration the value was last changed in
+    modified: u64,
+    /// the value and iteration before the current mutation, put back if it's rejected
+    backup: (f64, u64)
+}
+
+/// The random numbers a path is made from, mutated lazily as a path asks for them. Numbers a path doesn't reach
+/// in an iteration catch up on the mutations they missed the next time they're used.
+pub struct MltSampler {
+    samples: Vec<PrimarySample>,
+    index: usize,
+    iteration: u64,
+    last_large_step: u64,
+    large_step: bool,
+    sigma: f64,
+    large_step_probability: f64,
+    rng: StdRng
+}
+
+impl MltSampler {
+    /// Creates a sampler whose first iteration is a large step, so a seed always gives the same path.
+    pub fn new(seed: u64, sigma: f64, large_step_probability: f64) -> Self {
+        MltSampler{
+            samples: Vec::new(),
+            index: 0,
+            iteration: 0,
+            last_large_step: 0,
+            large_step: true,
+            sigma,
+            large_step_probability,
+            rng: StdRng::seed_from_u64(seed)
+        }
+    }
+
+    /// Starts a mutation.
+    pub fn start_iteration(&mut self) {
+        self.iteration += 1;
+        self.large_step = self.rng.gen::<f64>() < self.large_step_probability;
+        self.index = 0;
+    }
+
+    /// Keeps the mutated numbers.
+    pub fn accept(&mut self) {
+        if self.large_step {
+            self.last_large_step = self.iteration;
+        }
+    }
+
+    /// Puts back the numbers from before the mutation.
+    pub fn reject(&mut self) {
+        let iteration = self.iteration;
+        for sample in self.samples.iter_mut().filter(|sample| sample.modified == iteration) {
+            sample.value = sample.backup.0;
+            sample.modified = sample.backup.1;
+        }
+        self.iteration -= 1;
+    }
+
+    /// Starts reading the numbers from the beginning again without mutating them, for replaying a path.
+    pub fn restart(&mut self) {
+        self.index = 0;
+    }
+
+    fn ensure_ready(&mut self, index: usize) {
+        while index >= self.samples.len() {
+            // numbers a path hasn't asked for before start out random, as if drawn by the last large step
+            let value = self.rng.gen();
+            self.samples.push(PrimarySample{value, modified: self.last_large_step, backup: (value, self.last_large_step)});
+        }
+        let sample = &mut self.samples[index];
+        // numbers untouched since before the last large step would have been replaced by it
+        if sample.modified < self.last_large_step {
+            sample.value = self.rng.gen();
+            sample.modified = self.last_large_step;
+        }
+        sample.backup = (sample.value, sample.modified);
+        if self.large_step {
+            sample.value = self.rng.gen();
+        }
+        else {
+            // catch up on the small steps missed, which together have a wider spread
+            let steps = (self.iteration - sample.modified) as f64;
+            let u1: f64 = self.rng.gen();
+            let u2: f64 = self.rng.gen();
+            let normal = (-2.0 * (1.0 - u1).ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+            sample.value += normal * self.sigma * steps.sqrt();
+            sample.value -= sample.value.floor();
+        }
+        sample.modified = self.iteration;
+    }
+}
+
+impl RandomStream for MltSampler {
+    fn next(&mut self) -> f64 {
+        let index = self.index;
+        self.index += 1;
+        if index >= self.samples.len() || self.samples[index].modified < self.iteration {
+            self.ensure_ready(index);
+        }
+        self.samples[index].value
+    }
+}
+
+/// Lets a sampler be handed to with_random_stream while the chain keeps using it.
+pub struct SharedSampler(pub Rc<RefCell<MltSampler>>);
+
+impl RandomStream for SharedSampler {
+    fn next(&mut self) -> f64 {
+        self.0.borrow_mut().next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytrace::renderer::Integrator;
+    use crate::raytrace::renderer::tests::{comparison_renderer, seeded_mean};
+
+    fn values(sampler: &mut MltSampler, n: usize) -> Vec<f64> {
+        (0..n).map(|_| sampler.next()).collect()
+    }
+
+    #[test]
+    fn mutations() {
+        let mut sampler = MltSampler::new(7, 0.01, 0.0);
+        let first = values(&mut sampler, 5);
+        assert_eq!(values(&mut MltSampler::new(7, 0.01, 0.0), 5), first);
+
+        // small steps stay close and rejecting puts the numbers back
+        sampler.start_iteration();
+        let mutated = values(&mut sampler, 5);
+        for (a, b) in first.iter().zip(mutated.iter()) {
+            let distance = (a - b).abs();
+            assert!(distance.min(1.0 - distance) < 0.1);
+        }
+        sampler.reject();
+        sampler.restart();
+        assert_eq!(values(&mut sampler, 5), first);
+
+        // numbers the last path didn't use are still ready for the next one
+        sampler.start_iteration();
+        values(&mut sampler, 3);
+        sampler.accept();
+        sampler.start_iteration();
+        assert!(values(&mut sampler, 8).iter().all(|&v| (0.0..1.0).contains(&v)));
+    }
+
+    #[test]
+    fn matches_path_tracing() {
+        let path_tracer = comparison_renderer(64, vec![], vec![]);
+        let mut metropolis = comparison_renderer(64, vec![], vec![]);
+        let settings = MetropolisSettings{mutations_per_pixel: 64, bootstrap_samples: 20000, chains: 64, ..MetropolisSettings::default()};
+        metropolis.set_integrator(Integrator::Metropolis(settings));
+
+        let (expected, actual) = (seeded_mean(&path_tracer, 1), seeded_mean(&metropolis, 2));
+        assert!((expected - actual).length() < 0.05 * expected.length(), "{:?} {:?}", expected, actual);
+    }
+}
@@ -0,0 +1,299 @@
+//! The GGX (Trowbridge-Reitz) microfacet distribution with Smith masking-shadowing, and Fresnel equations.
+//! Directions are in a local frame around the surface normal, which is +z.
+
+use super::Vec3;
+use super::util::orthonormal_basis;
+use std::f64::consts::PI;
+
+/// An isotropic GGX distribution of microfacet normals.
+#[derive(Clone, Copy, Debug)]
+pub struct Ggx {
+    pub alpha: f64
+}
+
+impl Ggx {
+    /// The smallest alpha used, below it the distribution is too peaked to evaluate reliably.
+    const MIN_ALPHA: f64 = 1e-4;
+
+    /// Creates the distribution from a perceptual roughness in [0, 1], using the common alpha = roughness^2 mapping.
+    pub fn from_roughness(roughness: f64) -> Self {
+        let roughness = roughness.clamp(0.0, 1.0);
+        Ggx{alpha: (roughness * roughness).max(Self::MIN_ALPHA)}
+    }
+
+    /// The density of microfacets with normal h.
+    pub fn d(&self, h: Vec3) -> f64 {
+        if h.z() <= 0.0 {
+            return 0.0;
+        }
+        let a2 = self.alpha * self.alpha;
+        let cos2 = h.z() * h.z();
+        let denominator = cos2 * (a2 - 1.0) + 1.0;
+        a2 / (PI * denominator * denominator)
+    }
+
+    /// Smith's auxiliary function, the ratio of hidden to visible microfacet area in direction w.
+    pub fn lambda(&self, w: Vec3) -> f64 {
+        let cos2 = w.z() * w.z();
+        if cos2 == 0.0 {
+            return f64::INFINITY;
+        }
+        let tan2 = (1.0 - cos2).max(0.0) / cos2;
+        0.5 * (-1.0 + (1.0 + self.alpha * self.alpha * tan2).sqrt())
+    }
+
+    /// The fraction of microfacets visible from w.
+    pub fn g1(&self, w: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// The height correlated fraction of microfacets visible from both wo and wi.
+    pub fn g(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Samples a microfacet normal visible from wo, which must be above the surface, with two uniform random numbers.
+    /// See Heitz, "Sampling the GGX Distribution of Visible Normals".
+    pub fn sample_visible_normal(&self, wo: Vec3, u1: f64, u2: f64) -> Vec3 {
+        // stretch the view direction to the hemisphere configuration
+        let vh = Vec3::new(self.alpha * wo.x(), self.alpha * wo.y(), wo.z()).normalize();
+        let length_squared = vh.x() * vh.x() + vh.y() * vh.y();
+        let t1 = if length_squared > 0.0 { Vec3::new(-vh.y(), vh.x(), 0.0) / length_squared.sqrt() } else { Vec3::unit_x() };
+        let t2 = Vec3::cross(vh, t1);
+        // sample a disk, warped to the projection of the visible hemisphere
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z());
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+        // and unstretch
+        Vec3::new(self.alpha * nh.x(), self.alpha * nh.y(), nh.z().max(0.0)).normalize()
+    }
+
+    /// The pdf of sample_visible_normal returning h.
+    pub fn visible_normal_pdf(&self, wo: Vec3, h: Vec3) -> f64 {
+        if wo.z() <= 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * Vec3::dot(wo, h).max(0.0) * self.d(h) / wo.z()
+    }
+
+    /// Whether the surface is so smooth it should be treated as a perfect mirror.
+    pub fn is_smooth(&self) -> bool {
+        self.alpha < 1e-3
+    }
+
+    /// The reflection brdf without the fresnel term, times the cosine of wi, for wo and wi above the surface.
+    pub fn reflection_eval(&self, wo: Vec3, wi: Vec3) -> f64 {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0;
+        }
+        let h = (wo + wi).normalize();
+        self.d(h) * self.g(wo, wi) / (4.0 * wo.z())
+    }
+
+    /// The pdf of reflecting wo about a visible normal to get wi.
+    pub fn reflection_pdf(&self, wo: Vec3, wi: Vec3) -> f64 {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0;
+        }
+        let h = (wo + wi).normalize();
+        self.visible_normal_pdf(wo, h) / (4.0 * Vec3::dot(wo, h))
+    }
+
+    /// The bsdf times the cosine of wi, and the pdf of sample_dielectric returning wi, for a rough interface between
+    /// two dielectrics where wo is above the surface and eta is the index below over the index above.
+    /// Transmitted light isn't rescaled by the change in index, which cancels out for closed objects.
+    pub fn dielectric_eval(&self, wo: Vec3, wi: Vec3, eta: f64) -> (f64, f64) {
+        if wo.z() <= 0.0 || wi.z() == 0.0 {
+            return (0.0, 0.0);
+        }
+        if wi.z() > 0.0 {
+            let h = (wo + wi).normalize();
+            let fresnel = fresnel_dielectric(Vec3::dot(wo, h), eta);
+            return (fresnel * self.reflection_eval(wo, wi), fresnel * self.reflection_pdf(wo, wi));
+        }
+        // the generalized half vector for refraction, facing up
+        let mut h = (wo + eta * wi).normalize();
+        if h.z() < 0.0 {
+            h = -h;
+        }
+        let (cos_o, cos_i) = (Vec3::dot(wo, h), Vec3::dot(wi, h));
+        if cos_o <= 0.0 || cos_i >= 0.0 {
+            return (0.0, 0.0);
+        }
+        let transmitted = 1.0 - fresnel_dielectric(cos_o, eta);
+        let denominator = (cos_o + eta * cos_i) * (cos_o + eta * cos_i);
+        let value = transmitted * self.d(h) * self.g(wo, wi) * cos_o * -cos_i * eta * eta / (wo.z() * denominator);
+        let pdf = transmitted * self.visible_normal_pdf(wo, h) * eta * eta * -cos_i / denominator;
+        (value, pdf)
+    }
+
+    /// Samples reflection or refraction through a rough dielectric interface, see dielectric_eval.
+    /// Returns the direction and the weight, the bsdf times the cosine over the pdf.
+    pub fn sample_dielectric(&self, wo: Vec3, eta: f64, u: [f64; 3]) -> Option<(Vec3, f64)> {
+        let h = self.sample_visible_normal(wo, u[1], u[2]);
+        let fresnel = fresnel_dielectric(Vec3::dot(wo, h), eta);
+        // choosing between reflection and refraction by the fresnel term cancels it from the weight
+        let wi = match refract_about(wo, h, eta) {
+            Some(refracted) if u[0] >= fresnel => {
+                if refracted.z() >= 0.0 {
+                    return None;
+                }
+                refracted
+            }
+            _ => {
+                let reflected = reflect_about(wo, h);
+                if reflected.z() <= 0.0 {
+                    return None;
+                }
+                reflected
+            }
+        };
+        Some((wi, self.g(wo, wi) / self.g1(wo)))
+    }
+}
+
+/// An orthonormal frame around a normal for moving directions to and from the local space microfacet functions use.
+#[derive(Clone, Copy, Debug)]
+pub struct Frame {
+    s: Vec3,
+    t: Vec3,
+    n: Vec3
+}
+
+impl Frame {
+    pub fn new(normal: Vec3) -> Self {
+        let n = normal.normalize();
+        let (s, t) = orthonormal_basis(n);
+        Frame{s, t, n}
+    }
+
+    pub fn to_local(&self, v: Vec3) -> Vec3 {
+        Vec3::new(Vec3::dot(v, self.s), Vec3::dot(v, self.t), Vec3::dot(v, self.n))
+    }
+
+    pub fn to_world(&self, v: Vec3) -> Vec3 {
+        v.x() * self.s + v.y() * self.t + v.z() * self.n
+    }
+}
+
+/// The fraction of light reflected by a dielectric interface with relative index of refraction eta (transmitted over incident),
+/// for light arriving at an angle with cosine cos_i to the normal. Total internal reflection gives 1.
+pub fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let rs = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let rp = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    0.5 * (rs * rs + rp * rp)
+}
+
+/// The reflectance of a conductor with complex index of refraction eta + ik per color channel.
+pub fn fresnel_conductor(cos_i: f64, eta: Vec3, k: Vec3) -> Vec3 {
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let channel = |eta: f64, k: f64| {
+        let cos2 = cos_i * cos_i;
+        let sin2 = 1.0 - cos2;
+        let t0 = eta * eta - k * k - sin2;
+        let a2_plus_b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+        let t1 = a2_plus_b2 + cos2;
+        let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+        let t2 = 2.0 * cos_i * a;
+        let rs = (t1 - t2) / (t1 + t2);
+        let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+        0.5 * (rp + rs)
+    };
+    Vec3::new(channel(eta.x(), k.x()), channel(eta.y(), k.y()), channel(eta.z(), k.z()))
+}
+
+/// Schlick's approximation of the Fresnel reflectance, given the reflectance at normal incidence.
+pub fn fresnel_schlick(cos_i: f64, f0: Vec3) -> Vec3 {
+    let m = (1.0 - cos_i.clamp(0.0, 1.0)).powi(5);
+    f0 + (Vec3::new(1.0, 1.0, 1.0) - f0) * m
+}
+
+/// Reflects wo about the normal h, both pointing away from the surface.
+pub fn reflect_about(wo: Vec3, h: Vec3) -> Vec3 {
+    2.0 * Vec3::dot(wo, h) * h - wo
+}
+
+/// Refracts wo through the microfacet normal h on its side of the surface, with eta the transmitted over incident index.
+/// Returns None on total internal reflection.
+pub fn refract_about(wo: Vec3, h: Vec3, eta: f64) -> Option<Vec3> {
+    let cos_i = Vec3::dot(wo, h);
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-wo / eta + (cos_i / eta - cos_t) * h)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytrace::util::drand48;
+
+    #[test]
+    fn ggx_normalized() {
+        // the projected area of the microfacets is the area of the surface
+        for &roughness in [0.3, 0.6, 1.0].iter() {
+            let ggx = Ggx::from_roughness(roughness);
+            let n = 1000;
+            let mut total = 0.0;
+            for i in 0..n {
+                let theta = 0.5 * PI * (i as f64 + 0.5) / n as f64;
+                let h = Vec3::new(theta.sin(), 0.0, theta.cos());
+                total += ggx.d(h) * theta.cos() * theta.sin() * 0.5 * PI / n as f64 * 2.0 * PI;
+            }
+            assert!((total - 1.0).abs() < 1e-3, "{}", total);
+        }
+    }
+
+    #[test]
+    fn visible_normals() {
+        let ggx = Ggx::from_roughness(0.5);
+        let wo = Vec3::new(0.6, 0.0, 0.8);
+        for _ in 0..1000 {
+            let h = ggx.sample_visible_normal(wo, drand48(), drand48());
+            assert!((h.length() - 1.0).abs() < 1e-9);
+            assert!(h.z() >= 0.0 && Vec3::dot(wo, h) >= -1e-9);
+            assert!(ggx.visible_normal_pdf(wo, h) > 0.0);
+        }
+    }
+
+    #[test]
+    fn dielectric_sampling() {
+        // the sample weights match evaluating the bsdf over the pdf
+        let ggx = Ggx::from_roughness(0.4);
+        let wo = Vec3::new(0.5, 0.1, 0.7).normalize();
+        for &eta in [1.5, 1.0 / 1.5].iter() {
+            for _ in 0..1000 {
+                if let Some((wi, weight)) = ggx.sample_dielectric(wo, eta, [drand48(), drand48(), drand48()]) {
+                    let (value, pdf) = ggx.dielectric_eval(wo, wi, eta);
+                    assert!(pdf > 0.0);
+                    assert!((value / pdf - weight).abs() < 1e-6 * weight.max(1.0), "{} {}", value / pdf, weight);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fresnel() {
+        // glass reflects 4% at normal incidence
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-12);
+        assert_eq!(fresnel_dielectric(0.1, 1.0 / 1.5), 1.0);
+        // with no absorption a conductor's reflectance matches a dielectric
+        let reflectance = fresnel_conductor(0.7, Vec3::new(1.5, 1.5, 1.5), Vec3::zero());
+        assert!((reflectance.x() - fresnel_dielectric(0.7, 1.5)).abs() < 1e-9);
+        let wi = refract_about(Vec3::new(0.0, 0.0, 1.0), Vec3::unit_z(), 1.5).unwrap();
+        assert!((wi - Vec3::new(0.0, 0.0, -1.0)).length() < 1e-12);
+    }
+}
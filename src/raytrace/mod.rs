@@ -11,6 +11,7 @@ pub mod hittable;
 pub mod sphere;
 pub mod hittable_list;
 pub mod camera;
+pub mod aperture;
 pub mod util;
 pub mod material;
 pub mod aabb;
@@ -18,8 +19,30 @@ pub mod bvh;
 pub mod texture;
 pub mod renderer;
 pub mod cuboid;
+pub mod disk;
+pub mod cylinder;
+pub mod cone;
+pub mod torus;
 pub mod modify;
 pub mod quaternion;
 pub mod constant_medium;
+pub mod phase;
+pub mod heterogeneous_medium;
+pub mod subsurface;
+pub mod voxel_grid;
+pub mod animation;
+pub mod sampling;
+pub mod hdr_image;
+pub mod environment_map;
+pub mod sky;
+pub mod light;
+pub mod spectrum;
+pub mod microfacet;
+pub mod principled;
+pub mod layered;
+pub mod bidirectional;
+pub mod photon_map;
+pub mod metropolis;
+pub mod guiding;
 
 pub const EPSILON:f64 = 0.0001;
\ No newline at end of file
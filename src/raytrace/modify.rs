@@ -5,48 +5,125 @@ use super::Vec3;
 use super::ray::Ray;
 use super::quaternion::Quaternion;
 use super::hittable::{HitRecord, Hittable};
+use super::util::{approx_equal, time_fraction};
 use std::sync::Arc;
 
 pub struct Translate {
     object: Arc<dyn Hittable>,
-    offset: Vec3
+    offset: Vec3,
+    end_offset: Vec3,
+    time0: f64,
+    time1: f64
 }
 
 impl Translate {
     pub fn new(object: Arc<dyn Hittable>, offset: Vec3) -> Self {
-        Translate{object, offset}
+        Translate{object, offset, end_offset: offset, time0: 0.0, time1: 0.0}
+    }
+
+    /// Creates a translation that moves linearly from offset0 at time0 to offset1 at time1.
+    pub fn animated(object: Arc<dyn Hittable>, offset0: Vec3, offset1: Vec3, time0: f64, time1: f64) -> Self {
+        Translate{object, offset: offset0, end_offset: offset1, time0, time1}
+    }
+
+    pub fn offset(&self, time: f64) -> Vec3 {
+        self.offset + time_fraction(time, self.time0, self.time1) * (self.end_offset - self.offset)
     }
 }
 
 impl Hittable for Translate {
     fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let ray = Ray::new(r.origin - self.offset, r.direction);
+        let offset = self.offset(r.time);
+        let ray = Ray::new(r.origin - offset, r.direction, r.time);
         match self.object.hit(ray, t_min, t_max) {
             Some(rec) => Some(HitRecord{
-                p: rec.p + self.offset,
+                p: rec.p + offset,
                 ..rec
             }),
             None => None
         }
     }
 
+    fn transmittance(&self, r: Ray, t_min: f64, t_max: f64) -> Vec3 {
+        let ray = Ray::new(r.origin - self.offset(r.time), r.direction, r.time);
+        self.object.transmittance(ray, t_min, t_max)
+    }
+
     fn bounding_box(&self) -> Option<AABB> {
-        match self.object.bounding_box() {
-            Some(bbox) => Some(AABB::new(bbox.min + self.offset, bbox.max + self.offset)),
-            None => None
-        }
+        // the motion is linear so the boxes at either end of it cover the whole interval
+        self.object.bounding_box().map(|bbox| AABB::merge(
+            &AABB::new(bbox.min + self.offset, bbox.max + self.offset),
+            &AABB::new(bbox.min + self.end_offset, bbox.max + self.end_offset)
+        ))
+    }
+}
+
+/// Scales a hittable about the origin, either uniformly or per axis.
+pub struct Scale {
+    object: Arc<dyn Hittable>,
+    scale: Vec3,
+    bounding_box: Option<AABB>
+}
+
+impl Scale {
+    /// Creates a per axis scale, none of the components of scale can be zero.
+    pub fn new(object: Arc<dyn Hittable>, scale: Vec3) -> Self {
+        assert!(scale.x() != 0.0 && scale.y() != 0.0 && scale.z() != 0.0, "Scale components must be non zero");
+        let bounding_box = object.bounding_box().map(|bbox| Self::scale_bounding_box(scale, bbox));
+        Scale{object, scale, bounding_box}
+    }
+
+    /// Creates a scale that is the same along all three axis.
+    pub fn uniform(object: Arc<dyn Hittable>, scale: f64) -> Self {
+        Self::new(object, Vec3::new(scale, scale, scale))
+    }
+
+    pub fn scale(&self) -> Vec3 {
+        self.scale
+    }
+
+    fn scale_bounding_box(scale: Vec3, bounding_box: AABB) -> AABB {
+        // a negative scale mirrors the box so min and max have to be recalculated
+        let a = bounding_box.min * scale;
+        let b = bounding_box.max * scale;
+        AABB::new(
+            Vec3::new(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z())),
+            Vec3::new(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z()))
+        )
+    }
+}
+
+impl Hittable for Scale {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        // the direction is scaled with the origin so t is the same in object and world space
+        let ray = Ray::new(r.origin / self.scale, r.direction / self.scale, r.time);
+        self.object.hit(ray, t_min, t_max).map(|rec| HitRecord{
+            p: rec.p * self.scale,
+            // normals are transformed by the inverse transpose, which for a scale is 1 / scale
+            normal: (rec.normal / self.scale).normalize(),
+            ..rec
+        })
+    }
+
+    fn transmittance(&self, r: Ray, t_min: f64, t_max: f64) -> Vec3 {
+        self.object.transmittance(Ray::new(r.origin / self.scale, r.direction / self.scale, r.time), t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.bounding_box
     }
 }
 
 pub trait Rotation: Hittable {
-    fn rotation(&self) -> Quaternion;
+    /// The rotation at the given time.
+    fn rotation(&self, time: f64) -> Quaternion;
     fn object(&self) -> Arc<dyn Hittable>;
     fn bbox(&self) -> Option<AABB>;
 
     fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let q = Self::rotation(self);
+        let q = Self::rotation(self, r.time);
         let qr = q.conjugate();
-        let rotated_ray = Ray::new(qr * r.origin, qr * r.direction);
+        let rotated_ray = Ray::new(qr * r.origin, qr * r.direction, r.time);
         match Self::object(self).hit(rotated_ray, t_min, t_max) {
             Some(rec) => {
                 let p = q * rec.p;
@@ -62,6 +139,11 @@ pub trait Rotation: Hittable {
 
     }
 
+    fn transmittance(&self, r: Ray, t_min: f64, t_max: f64) -> Vec3 {
+        let qr = Self::rotation(self, r.time).conjugate();
+        Self::object(self).transmittance(Ray::new(qr * r.origin, qr * r.direction, r.time), t_min, t_max)
+    }
+
     fn bounding_box(&self) -> Option<AABB> {
         Self::bbox(self)
     }
@@ -104,13 +186,38 @@ pub trait Rotation: Hittable {
 
         AABB::new(min, max)
     }
+
+    /// Calculates a bounding box that contains the object for every rotation slerped between start and end.
+    fn sweep_bounding_box(start: Quaternion, end: Quaternion, bounding_box: AABB) -> AABB {
+        const STEPS: usize = 16;
+        let mut result = Self::rotate_bounding_box(start, bounding_box);
+        let dot = Quaternion::dot(start, end).abs().min(1.0);
+        if approx_equal(dot, 1.0) {
+            return result;
+        }
+        for i in 1..=STEPS {
+            let q = start.slerp(end, i as f64 / STEPS as f64);
+            result = AABB::merge(&result, &Self::rotate_bounding_box(q, bounding_box));
+        }
+        // between the sampled rotations the corners travel along arcs, pad the box by the furthest
+        // an arc can bulge out from the chord between its end points
+        let step_angle = 2.0 * dot.acos() / STEPS as f64;
+        let radius = bounding_box.get_corners().iter().fold(0.0f64, |r, c| r.max(c.length()));
+        let padding = radius * (1.0 - (step_angle / 2.0).cos());
+        AABB::new(result.min - padding, result.max + padding)
+    }
 }
 
 macro_rules! impl_rotation {
     ($op: ident) => {
         impl Rotation for $op {
-            fn rotation(&self) -> Quaternion {
-                self._rotation
+            fn rotation(&self, time: f64) -> Quaternion {
+                if self._time1 > self._time0 {
+                    self._rotation.slerp(self._end_rotation, time_fraction(time, self._time0, self._time1))
+                }
+                else {
+                    self._rotation
+                }
             }
 
             fn object(&self) -> Arc<dyn Hittable> {
@@ -121,6 +228,13 @@ macro_rules! impl_rotation {
                 self._bounding_box
             }
         }
+
+        impl $op {
+            fn update_bounding_box(&mut self) {
+                let (start, end) = (self._rotation, self._end_rotation);
+                self._bounding_box = self._object.bounding_box().map(|bbox| Self::sweep_bounding_box(start, end, bbox));
+            }
+        }
     };
 }
 
@@ -132,6 +246,11 @@ macro_rules! impl_hittable {
                 Rotation::hit(self, r, t_min, t_max)
             }
 
+            #[inline]
+            fn transmittance(&self, r: Ray, t_min: f64, t_max: f64) -> Vec3 {
+                Rotation::transmittance(self, r, t_min, t_max)
+            }
+
             #[inline]
             fn bounding_box(&self) -> Option<AABB> {
                 Rotation::bounding_box(self)
@@ -143,20 +262,29 @@ macro_rules! impl_hittable {
 macro_rules! impl_axis_rotation {
     ($op: ident, $axis: expr) => {
         impl $op {
-            pub fn new(object: Arc<dyn Hittable>, angle: f64) -> RotateY {
-                let q = Quaternion::from_rotation_axis($axis, angle);
-                let boundingbox: Option<AABB> =  match object.bounding_box() {
-                    Some(bbox) => Some(Self::rotate_bounding_box(q, bbox)),
-                    None => None
+            pub fn new(object: Arc<dyn Hittable>, angle: f64) -> $op {
+                Self::animated(object, angle, angle, 0.0, 0.0)
+            }
+
+            /// Creates a rotation that turns from angle0 at time0 to angle1 at time1.
+            /// The rotation is slerped so it takes the shortest path between the two angles.
+            pub fn animated(object: Arc<dyn Hittable>, angle0: f64, angle1: f64, time0: f64, time1: f64) -> $op {
+                let mut rotation = $op{
+                    _object: object,
+                    _rotation: Quaternion::from_rotation_axis($axis, angle0),
+                    _end_rotation: Quaternion::from_rotation_axis($axis, angle1),
+                    _time0: time0,
+                    _time1: time1,
+                    _bounding_box: None
                 };
-                RotateY{_object: object, _rotation: q, _bounding_box: boundingbox}
+                rotation.update_bounding_box();
+                rotation
             }
 
             pub fn set_angle(&mut self, angle: f64) {
                 self._rotation = Quaternion::from_rotation_axis($axis, angle);
-                if let Some(bbox) = self._bounding_box {
-                    self._bounding_box = Some(Self::rotate_bounding_box(self._rotation, bbox));
-                };
+                self._end_rotation = self._rotation;
+                self.update_bounding_box();
             }
         }
     };
@@ -165,30 +293,32 @@ macro_rules! impl_axis_rotation {
 pub struct Rotate {
     _object: Arc<dyn Hittable>,
     _rotation: Quaternion,
+    _end_rotation: Quaternion,
+    _time0: f64,
+    _time1: f64,
     _bounding_box: Option<AABB>
 }
 
 impl Rotate {
     pub fn new(object: Arc<dyn Hittable>, rotation: Quaternion) -> Rotate {
-        let boundingbox: Option<AABB> =  match object.bounding_box() {
-            Some(bbox) => Some(Self::rotate_bounding_box(rotation, bbox)),
-            None => None
-        };
-        Rotate{_object: object, _rotation: rotation, _bounding_box: boundingbox}
+        Self::animated(object, rotation, rotation, 0.0, 0.0)
+    }
+
+    /// Creates a rotation that is slerped from start at time0 to end at time1.
+    pub fn animated(object: Arc<dyn Hittable>, start: Quaternion, end: Quaternion, time0: f64, time1: f64) -> Rotate {
+        let mut rotate = Rotate{_object: object, _rotation: start, _end_rotation: end, _time0: time0, _time1: time1, _bounding_box: None};
+        rotate.update_bounding_box();
+        rotate
     }
 
     pub fn set_rotation(&mut self, q: Quaternion) {
         self._rotation = q;
-        if let Some(bbox) = self._bounding_box {
-            self._bounding_box = Some(Self::rotate_bounding_box(q, bbox));
-        };
+        self._end_rotation = q;
+        self.update_bounding_box();
     }
 
     pub fn set_rotation_from_axis_angle(&mut self, axis: Vec3, angle: f64) {
-        self._rotation = Quaternion::from_rotation_axis(axis, angle);
-        if let Some(bbox) = self._bounding_box {
-            self._bounding_box = Some(Self::rotate_bounding_box(self._rotation, bbox));
-        };
+        self.set_rotation(Quaternion::from_rotation_axis(axis, angle));
     }
 }
 
@@ -197,6 +327,9 @@ impl_rotation!(Rotate);
 
 pub struct RotateX {
     _rotation: Quaternion,
+    _end_rotation: Quaternion,
+    _time0: f64,
+    _time1: f64,
     _object: Arc<dyn Hittable>,
     _bounding_box: Option<AABB>
 }
@@ -206,6 +339,9 @@ impl_hittable!(RotateX);
 
 pub struct RotateY {
     _rotation: Quaternion,
+    _end_rotation: Quaternion,
+    _time0: f64,
+    _time1: f64,
     _object: Arc<dyn Hittable>,
     _bounding_box: Option<AABB>
 }
@@ -215,9 +351,119 @@ impl_hittable!(RotateY);
 
 pub struct RotateZ {
     _rotation: Quaternion,
+    _end_rotation: Quaternion,
+    _time0: f64,
+    _time1: f64,
     _object: Arc<dyn Hittable>,
     _bounding_box: Option<AABB>
 }
 impl_axis_rotation!(RotateZ, Vec3::unit_z());
 impl_rotation!(RotateZ);
-impl_hittable!(RotateZ);
\ No newline at end of file
+impl_hittable!(RotateZ);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytrace::sphere::Sphere;
+    use crate::raytrace::material::NormalMaterial;
+
+    #[test]
+    fn scale_hit() {
+        let sphere = Arc::new(Sphere::new(Vec3::zero(), 1.0, Arc::new(NormalMaterial::new())));
+        let ellipsoid = Scale::new(sphere, Vec3::new(2.0, 1.0, 1.0));
+        let rec = ellipsoid.hit(Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::unit_x(), 0.0), 0.0, f64::MAX).unwrap();
+        assert!((rec.t - 3.0).abs() < 1e-9);
+        assert!((rec.p - Vec3::new(-2.0, 0.0, 0.0)).length() < 1e-9);
+        assert!((rec.normal - Vec3::new(-1.0, 0.0, 0.0)).length() < 1e-9);
+    }
+
+    #[test]
+    fn scale_normal() {
+        let sphere = Arc::new(Sphere::new(Vec3::zero(), 1.0, Arc::new(NormalMaterial::new())));
+        let ellipsoid = Scale::new(sphere, Vec3::new(2.0, 1.0, 1.0));
+        // hit the ellipse x^2/4 + y^2 = 1 at (sqrt(2), sqrt(0.5)), the gradient there is (x/2, 2y)
+        let p = Vec3::new(2.0f64.sqrt(), 0.5f64.sqrt(), 0.0);
+        let rec = ellipsoid.hit(Ray::new(Vec3::new(p.x(), 5.0, 0.0), -Vec3::unit_y(), 0.0), 0.0, f64::MAX).unwrap();
+        let expected = Vec3::new(p.x() / 2.0, 2.0 * p.y(), 0.0).normalize();
+        assert!((rec.p - p).length() < 1e-9);
+        assert!((rec.normal - expected).length() < 1e-9);
+    }
+
+    #[test]
+    fn scale_bounding_box() {
+        let sphere = Arc::new(Sphere::new(Vec3::new(1.0, 0.0, 0.0), 1.0, Arc::new(NormalMaterial::new())));
+        let bbox = Scale::new(sphere, Vec3::new(-2.0, 3.0, 1.0)).bounding_box().unwrap();
+        assert_eq!(bbox.min, Vec3::new(-4.0, -3.0, -1.0));
+        assert_eq!(bbox.max, Vec3::new(0.0, 3.0, 1.0));
+    }
+
+    #[test]
+    fn animated_translate() {
+        let sphere = Arc::new(Sphere::new(Vec3::zero(), 1.0, Arc::new(NormalMaterial::new())));
+        let moving = Translate::animated(sphere, Vec3::zero(), Vec3::new(0.0, 0.0, 4.0), 0.0, 1.0);
+        let ray = |time| Ray::new(Vec3::new(-5.0, 0.0, 2.0), Vec3::unit_x(), time);
+        assert!(moving.hit(ray(0.0), 0.0, f64::MAX).is_none());
+        let rec = moving.hit(ray(0.5), 0.0, f64::MAX).unwrap();
+        assert!((rec.p - Vec3::new(-1.0, 0.0, 2.0)).length() < 1e-9);
+        let bbox = moving.bounding_box().unwrap();
+        assert_eq!(bbox.min, Vec3::new(-1.0, -1.0, -1.0));
+        assert_eq!(bbox.max, Vec3::new(1.0, 1.0, 5.0));
+    }
+
+    #[test]
+    fn animated_rotation_bounding_box() {
+        let cuboid = Arc::new(crate::raytrace::cuboid::Cuboid::new(Vec3::new(1.0, -0.5, -0.5), Vec3::new(3.0, 0.5, 0.5), Arc::new(NormalMaterial::new())));
+        let rotation = RotateY::animated(cuboid, 0.0, std::f64::consts::FRAC_PI_2 * 1.5, 0.0, 1.0);
+        let bbox = Hittable::bounding_box(&rotation).unwrap();
+        for i in 0..=100 {
+            let q = rotation.rotation(i as f64 / 100.0);
+            for corner in AABB::new(Vec3::new(1.0, -0.5, -0.5), Vec3::new(3.0, 0.5, 0.5)).get_corners().iter() {
+                let c = q * *corner;
+                assert!(c.x() >= bbox.min.x() && c.y() >= bbox.min.y() && c.z() >= bbox.min.z());
+                assert!(c.x() <= bbox.max.x() && c.y() <= bbox.max.y() && c.z() <= bbox.max.z());
+            }
+        }
+    }
+
+    #[test]
+    fn transformed_shapes_in_bvh() {
+        use crate::raytrace::{bvh::BVHNode, hittable_list::HittableList, util::random_unit_vector};
+        use crate::raytrace::{cone::Cone, cylinder::Cylinder, disk::{Annulus, Disk}, torus::Torus};
+        let material = Arc::new(NormalMaterial::new());
+        let mut open = Cylinder::new(Vec3::zero(), 0.5, 1.0, material.clone());
+        open.set_capped(false);
+        let shapes: Vec<Arc<dyn Hittable>> = vec![
+            Arc::new(Cylinder::new(Vec3::zero(), 0.5, 1.5, material.clone())),
+            Arc::new(open),
+            Arc::new(Cone::new(Vec3::zero(), 0.7, 1.2, material.clone())),
+            Arc::new(Disk::new(Vec3::zero(), 0.8, material.clone())),
+            Arc::new(Annulus::new(Vec3::zero(), 0.3, 0.8, material.clone())),
+            Arc::new(Torus::new(Vec3::zero(), 0.8, 0.25, material))
+        ];
+        let placed = || -> Vec<Box<dyn Hittable>> {
+            shapes.iter().enumerate().map(|(i, shape)| {
+                let rotated = Arc::new(Rotate::new(shape.clone(), Quaternion::from_rotation_axis(Vec3::new(1.0, 2.0, -1.0).normalize(), i as f64)));
+                Box::new(Translate::new(rotated, Vec3::new(2.0 * (i % 3) as f64, 2.0 * (i / 3) as f64, 0.0))) as Box<dyn Hittable>
+            }).collect()
+        };
+        let boxes: Vec<AABB> = placed().iter().map(|object| object.bounding_box().unwrap()).collect();
+        let (list, bvh) = (HittableList::from_vec(placed()), BVHNode::construct(placed()));
+
+        // rays from all around aimed at the middle of the shapes
+        let mut hits = 0;
+        for _ in 0..5000 {
+            let origin = Vec3::new(2.0, 1.0, 0.0) + 6.0 * random_unit_vector();
+            let r = Ray::new(origin, Vec3::new(2.0, 1.0, 0.0) + 1.5 * random_unit_vector() - origin, 0.0);
+            let expected = list.hit(r, 0.0, f64::MAX);
+            assert_eq!(expected.as_ref().map(|rec| rec.t), bvh.hit(r, 0.0, f64::MAX).map(|rec| rec.t));
+            if let Some(rec) = expected {
+                hits += 1;
+                assert!((rec.normal.length() - 1.0).abs() < 1e-9);
+                assert!(rec.u >= 0.0 && rec.u <= 1.0 && rec.v >= 0.0 && rec.v <= 1.0);
+                let inside = |b: &AABB| (0..3).all(|i| rec.p.elements()[i] >= b.min.elements()[i] - 1e-9 && rec.p.elements()[i] <= b.max.elements()[i] + 1e-9);
+                assert!(boxes.iter().any(inside));
+            }
+        }
+        assert!(hits > 1000, "{}", hits);
+    }
+}
@@ -0,0 +1,194 @@
+//! Phase functions, which describe the directions light scatters in when it hits a particle in a medium.
+
+use super::Vec3;
+use super::hittable::HitRecord;
+use super::material::{Material, BsdfSample, Lobe};
+use super::microfacet::Frame;
+use super::sampling::uniform_sphere;
+use super::texture::Texture;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+/// The distribution of directions light scatters in within a medium. As with materials, wo points back along
+/// the ray that arrived and wi along the scattered ray, so light carrying straight on has wi = -wo.
+pub trait PhaseFunction: Send + Sync {
+    /// The density of light scattering from wo to wi, which integrates to 1 over the sphere.
+    fn p(&self, wo: Vec3, wi: Vec3) -> f64;
+
+    /// Picks a direction in proportion to p, so the pdf is p.
+    fn sample(&self, wo: Vec3, u: [f64; 2]) -> Vec3;
+}
+
+/// The cosine of the angle the light is turned through.
+fn cos_scatter(wo: Vec3, wi: Vec3) -> f64 {
+    -Vec3::dot(wo, wi) / (wo.length() * wi.length())
+}
+
+fn henyey_greenstein(cos_theta: f64, g: f64) -> f64 {
+    let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+    (1.0 - g * g) / (4.0 * PI * denominator * denominator.max(1e-12).sqrt())
+}
+
+/// The direction at an angle with cosine cos_theta to the direction the light was going in.
+fn scattered(wo: Vec3, cos_theta: f64, u: f64) -> Vec3 {
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * u;
+    Frame::new(-wo).to_world(Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta))
+}
+
+/// Scatters light evenly in every direction.
+pub struct IsotropicPhase {}
+
+impl PhaseFunction for IsotropicPhase {
+    fn p(&self, _wo: Vec3, _wi: Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn sample(&self, _wo: Vec3, u: [f64; 2]) -> Vec3 {
+        uniform_sphere(u[0], u[1])
+    }
+}
+
+/// The Henyey-Greenstein phase function, whose asymmetry g is the average cosine of the scattering angle,
+/// from -1 for scattering straight back through 0 for isotropic to 1 for carrying straight on.
+/// Clouds and fog scatter strongly forward, with g around 0.8.
+pub struct HenyeyGreenstein {
+    g: f64
+}
+
+impl HenyeyGreenstein {
+    pub fn new(g: f64) -> Self {
+        HenyeyGreenstein{g: g.clamp(-0.99, 0.99)}
+    }
+
+    fn sample_cos_theta(g: f64, u: f64) -> f64 {
+        if g.abs() < 1e-3 {
+            return 1.0 - 2.0 * u;
+        }
+        let s = (1.0 - g * g) / (1.0 + g - 2.0 * g * u);
+        ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+    }
+}
+
+impl PhaseFunction for HenyeyGreenstein {
+    fn p(&self, wo: Vec3, wi: Vec3) -> f64 {
+        henyey_greenstein(cos_scatter(wo, wi), self.g)
+    }
+
+    fn sample(&self, wo: Vec3, u: [f64; 2]) -> Vec3 {
+        scattered(wo, Self::sample_cos_theta(self.g, u[0]), u[1])
+    }
+}
+
+/// A blend of two Henyey-Greenstein lobes, usually one forward and one back, for media like clouds whose strong
+/// forward peak comes with some back scattering.
+pub struct DoubleHenyeyGreenstein {
+    g1: f64,
+    g2: f64,
+    /// the fraction of light scattered by the first lobe
+    weight: f64
+}
+
+impl DoubleHenyeyGreenstein {
+    pub fn new(g1: f64, g2: f64, weight: f64) -> Self {
+        DoubleHenyeyGreenstein{g1: g1.clamp(-0.99, 0.99), g2: g2.clamp(-0.99, 0.99), weight: weight.clamp(0.0, 1.0)}
+    }
+}
+
+impl PhaseFunction for DoubleHenyeyGreenstein {
+    fn p(&self, wo: Vec3, wi: Vec3) -> f64 {
+        let cos_theta = cos_scatter(wo, wi);
+        self.weight * henyey_greenstein(cos_theta, self.g1) + (1.0 - self.weight) * henyey_greenstein(cos_theta, self.g2)
+    }
+
+    fn sample(&self, wo: Vec3, u: [f64; 2]) -> Vec3 {
+        let cos_theta = if u[0] < self.weight {
+            HenyeyGreenstein::sample_cos_theta(self.g1, u[0] / self.weight)
+        }
+        else {
+            HenyeyGreenstein::sample_cos_theta(self.g2, (u[0] - self.weight) / (1.0 - self.weight))
+        };
+        scattered(wo, cos_theta, u[1])
+    }
+}
+
+/// Scattering by particles much smaller than the wavelength, like air molecules, which is the same forwards
+/// and backwards and weakest to the side.
+pub struct Rayleigh {}
+
+impl PhaseFunction for Rayleigh {
+    fn p(&self, wo: Vec3, wi: Vec3) -> f64 {
+        let cos_theta = cos_scatter(wo, wi);
+        3.0 / (16.0 * PI) * (1.0 + cos_theta * cos_theta)
+    }
+
+    fn sample(&self, wo: Vec3, u: [f64; 2]) -> Vec3 {
+        // invert the cdf (cos³θ + 3cosθ + 4) / 8 with Cardano's formula
+        let z = 4.0 * u[0] - 2.0;
+        let root = (z * z + 1.0).sqrt();
+        let cos_theta = ((z + root).cbrt() + (z - root).cbrt()).clamp(-1.0, 1.0);
+        scattered(wo, cos_theta, u[1])
+    }
+}
+
+/// A material scattering light by a phase function, for the particles of a medium. The albedo is the fraction
+/// of light scattered rather than absorbed.
+pub struct PhaseMaterial {
+    phase_function: Arc<dyn PhaseFunction>,
+    albedo: Arc<dyn Texture>
+}
+
+impl PhaseMaterial {
+    pub fn new(phase_function: Arc<dyn PhaseFunction>, albedo: Arc<dyn Texture>) -> Self {
+        PhaseMaterial{phase_function, albedo}
+    }
+}
+
+impl Material for PhaseMaterial {
+    fn sample(&self, hit_record: &HitRecord, wo: Vec3, u: [f64; 3]) -> Option<BsdfSample> {
+        let wi = self.phase_function.sample(wo, [u[0], u[1]]);
+        let pdf = self.phase_function.p(wo, wi);
+        let albedo = self.albedo.value(hit_record.u, hit_record.v, hit_record.p);
+        Some(BsdfSample{wi, weight: albedo, pdf, lobe: Lobe::DIFFUSE | Lobe::REFLECTION | Lobe::TRANSMISSION})
+    }
+
+    fn eval(&self, hit_record: &HitRecord, wo: Vec3, wi: Vec3) -> Vec3 {
+        self.albedo.value(hit_record.u, hit_record.v, hit_record.p) * self.phase_function.p(wo, wi)
+    }
+
+    fn pdf(&self, _hit_record: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        self.phase_function.p(wo, wi)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytrace::util::drand48;
+
+    #[test]
+    fn sampling_matches_density() {
+        let phase_functions: Vec<(Box<dyn PhaseFunction>, f64)> = vec![
+            (Box::new(IsotropicPhase{}), 0.0),
+            (Box::new(HenyeyGreenstein::new(0.7)), 0.7),
+            (Box::new(HenyeyGreenstein::new(-0.3)), -0.3),
+            (Box::new(DoubleHenyeyGreenstein::new(0.8, -0.4, 0.75)), 0.75 * 0.8 - 0.25 * 0.4),
+            (Box::new(Rayleigh{}), 0.0)
+        ];
+        let wo = Vec3::new(0.2, -0.6, 0.5).normalize();
+        for (phase_function, mean_cos) in phase_functions {
+            let n = 200000;
+            let mut sum_cos = 0.0;
+            let mut integral = 0.0;
+            for _ in 0..n {
+                let wi = phase_function.sample(wo, [drand48(), drand48()]);
+                assert!((wi.length() - 1.0).abs() < 1e-9);
+                sum_cos += cos_scatter(wo, wi);
+                // integrates to 1 over the sphere
+                integral += phase_function.p(wo, uniform_sphere(drand48(), drand48())) * 4.0 * PI;
+            }
+            assert!((sum_cos / n as f64 - mean_cos).abs() < 0.01, "{} {}", sum_cos / n as f64, mean_cos);
+            assert!((integral / n as f64 - 1.0).abs() < 0.02, "{}", integral / n as f64);
+        }
+    }
+}
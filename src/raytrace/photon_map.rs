@@ -0,0 +1,293 @@
+//! Photon mapping, which traces photons from the lights, stores where they land in a kd-tree and estimates the light
+//! arriving at a point from the density of photons around it. Caustics, light focused through glass or off mirrors
+//! onto diffuse surfaces, are found far faster this way than by tracing paths from the camera.
+
+use super::Vec3;
+use super::ray::Ray;
+use super::hittable::{Hittable, HitRecord};
+use super::light::Light;
+use super::material::{offset_origin, scattering_hit};
+use super::util::drand48;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::Arc;
+use rayon::prelude::*;
+
+/// The most times a photon bounces before it's dropped.
+const MAX_BOUNCES: usize = 16;
+
+/// How many photons to trace, and how many of the nearest within a radius to use when estimating the light at a point.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PhotonSettings {
+    pub photons: usize,
+    pub neighbours: usize,
+    /// keeps photons from lighting places far from where they landed, where there are few of them
+    pub radius: f64
+}
+
+impl Default for PhotonSettings {
+    fn default() -> Self {
+        PhotonSettings{photons: 500_000, neighbours: 100, radius: f64::INFINITY}
+    }
+}
+
+/// Light arriving at a point on a surface.
+#[derive(Clone, Copy, Debug)]
+pub struct Photon {
+    pub position: Vec3,
+    /// unit direction the photon arrived from, pointing away from the surface
+    pub wi: Vec3,
+    pub power: Vec3
+}
+
+/// A photon in the neighbourhood of a point, ordered by its distance to the point.
+struct Neighbour {
+    distance_squared: f64,
+    index: usize
+}
+
+impl PartialEq for Neighbour {
+    fn eq(&self, other: &Self) -> bool {
+        self.distance_squared == other.distance_squared
+    }
+}
+
+impl Eq for Neighbour {}
+
+impl PartialOrd for Neighbour {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Neighbour {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance_squared.partial_cmp(&other.distance_squared).unwrap_or(Ordering::Equal)
+    }
+}
+
+/// Photons stored in a balanced kd-tree. Each range of the array is a subtree with its root in the middle,
+/// splitting the range along the axis stored for it.
+pub struct PhotonMap {
+    photons: Vec<Photon>,
+    axes: Vec<usize>
+}
+
+impl PhotonMap {
+    pub fn new(mut photons: Vec<Photon>) -> Self {
+        let mut axes = vec![0; photons.len()];
+        build(&mut photons, &mut axes);
+        PhotonMap{photons, axes}
+    }
+
+    pub fn len(&self) -> usize {
+        self.photons.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.photons.is_empty()
+    }
+
+    /// Returns the k photons nearest to p within radius, with their squared distances to it, in no particular order.
+    pub fn nearest(&self, p: Vec3, k: usize, radius: f64) -> Vec<(f64, &Photon)> {
+        let mut heap = BinaryHeap::with_capacity(k + 1);
+        if k > 0 {
+            self.search(0, self.photons.len(), p.elements(), k, radius * radius, &mut heap);
+        }
+        heap.into_iter().map(|n| (n.distance_squared, &self.photons[n.index])).collect()
+    }
+
+    fn search(&self, start: usize, end: usize, p: [f64; 3], k: usize, max_squared: f64, heap: &mut BinaryHeap<Neighbour>) {
+        if start >= end {
+            return;
+        }
+        let middle = start + (end - start) / 2;
+        let position = self.photons[middle].position;
+        let axis = self.axes[middle];
+        let offset = p[axis] - position.elements()[axis];
+        let (near, far) = if offset < 0.0 { ((start, middle), (middle + 1, end)) } else { ((middle + 1, end), (start, middle)) };
+        self.search(near.0, near.1, p, k, max_squared, heap);
+
+        let distance_squared = (position - Vec3::new(p[0], p[1], p[2])).length_squared();
+        if distance_squared <= max_squared {
+            if heap.len() == k && distance_squared < heap.peek().unwrap().distance_squared {
+                heap.pop();
+            }
+            if heap.len() < k {
+                heap.push(Neighbour{distance_squared, index: middle});
+            }
+        }
+        // the far side can only hold nearer photons if the splitting plane is nearer than the furthest found
+        let furthest = if heap.len() < k { max_squared } else { heap.peek().unwrap().distance_squared };
+        if offset * offset < furthest {
+            self.search(far.0, far.1, p, k, max_squared, heap);
+        }
+    }
+
+    /// Estimates the radiance reflected from the hit towards wo from the density of the k nearest photons within radius.
+    pub fn estimate(&self, rec: &HitRecord, wo: Vec3, k: usize, radius: f64) -> Vec3 {
+        let neighbours = self.nearest(rec.p, k, radius);
+        // with fewer than k photons nearby they're spread over the whole radius
+        let radius_squared = if neighbours.len() < k { radius * radius } else { neighbours.iter().fold(0.0, |r: f64, &(d, _)| r.max(d)) };
+        if radius_squared == 0.0 || radius_squared.is_infinite() {
+            return Vec3::zero();
+        }
+        let mut total = Vec3::zero();
+        // particles in a medium have no surface, so their photons are spread through a sphere instead of a disc
+        if rec.normal.is_zero_length() {
+            for (_, photon) in neighbours {
+                total += rec.material.eval(rec, wo, photon.wi) * photon.power;
+            }
+            return total / (4.0 / 3.0 * std::f64::consts::PI * radius_squared * radius_squared.sqrt());
+        }
+        for (_, photon) in neighbours {
+            let cosine = Vec3::dot(rec.normal, photon.wi).abs();
+            if cosine > 1e-6 {
+                // the photon's power already accounts for the angle it arrived at
+                total += rec.material.eval(rec, wo, photon.wi) / cosine * photon.power;
+            }
+        }
+        total / (std::f64::consts::PI * radius_squared)
+    }
+}
+
+/// Arranges the photons into a kd-tree, splitting each range at its median along its widest axis.
+fn build(photons: &mut [Photon], axes: &mut [usize]) {
+    if photons.is_empty() {
+        return;
+    }
+    let (min, max) = photons.iter().fold((Vec3::new(f64::MAX, f64::MAX, f64::MAX), Vec3::new(f64::MIN, f64::MIN, f64::MIN)), |(min, max), photon| {
+        let p = photon.position;
+        (Vec3::new(min.x().min(p.x()), min.y().min(p.y()), min.z().min(p.z())), Vec3::new(max.x().max(p.x()), max.y().max(p.y()), max.z().max(p.z())))
+    });
+    let extent = (max - min).elements();
+    let axis = (0..3).fold(0, |a, i| if extent[i] > extent[a] { i } else { a });
+    let middle = photons.len() / 2;
+    photons.select_nth_unstable_by(middle, |a, b| {
+        a.position.elements()[axis].partial_cmp(&b.position.elements()[axis]).unwrap_or(Ordering::Equal)
+    });
+    axes[middle] = axis;
+    let (left, right) = photons.split_at_mut(middle);
+    let (left_axes, right_axes) = axes.split_at_mut(middle);
+    build(left, left_axes);
+    build(&mut right[1..], &mut right_axes[1..]);
+}
+
+/// The photons landing anywhere, and those that only bounced specularly before landing, which make the caustics.
+pub struct PhotonMaps {
+    pub global: PhotonMap,
+    pub caustic: PhotonMap
+}
+
+impl PhotonMaps {
+    /// Traces photons from the lights, each light emitting an equal share. Photons are stored wherever they
+    /// scatter off a surface other than specularly. Lights that can't emit photons, like directional lights, are skipped.
+    pub fn trace(objects: &dyn Hittable, lights: &[Arc<dyn Light>], photons: usize) -> Self {
+        let traced: Vec<(Photon, bool)> = (0..photons)
+            .into_par_iter()
+            .flat_map(|_| trace_photon(objects, lights, photons))
+            .collect();
+        let caustic = traced.iter().filter(|(_, caustic)| *caustic).map(|&(photon, _)| photon).collect();
+        let global = traced.into_iter().map(|(photon, _)| photon).collect();
+        PhotonMaps{global: PhotonMap::new(global), caustic: PhotonMap::new(caustic)}
+    }
+}
+
+/// Traces one photon from a light, returning where it landed and whether it had only bounced specularly.
+fn trace_photon(objects: &dyn Hittable, lights: &[Arc<dyn Light>], photons: usize) -> Vec<(Photon, bool)> {
+    let mut stored = Vec::new();
+    if lights.is_empty() {
+        return stored;
+    }
+    let index = ((drand48() * lights.len() as f64) as usize).min(lights.len() - 1);
+    let light_choice = 1.0 / lights.len() as f64;
+    let emission = match lights[index].sample_emission() {
+        Some(emission) if emission.pdf_position > 0.0 && emission.pdf_direction > 0.0 => emission,
+        _ => return stored
+    };
+    let cosine = if emission.normal.is_zero_length() { 1.0 } else { Vec3::dot(emission.normal, emission.direction).abs() };
+    let mut power = emission.radiance * cosine / (light_choice * emission.pdf_position * emission.pdf_direction * photons as f64);
+    let side = Vec3::dot(emission.normal, emission.direction).signum();
+    let mut ray = Ray::new(emission.origin + side * super::EPSILON * emission.normal, emission.direction, 0.0);
+    let mut specular = true;
+    for bounce in 0..MAX_BOUNCES {
+        let rec = match objects.hit(ray, 0.0, f64::MAX) {
+            Some(rec) => rec,
+            None => break
+        };
+        let (rec, wo, through) = match scattering_hit(rec, -ray.direction.normalize(), ray.time) {
+            Some(scattering) => scattering,
+            None => break
+        };
+        power *= through;
+        let sample = match rec.material.sample(&rec, wo, [drand48(), drand48(), drand48()]) {
+            Some(sample) => sample,
+            None => break
+        };
+        if !sample.lobe.is_specular() {
+            stored.push((Photon{position: rec.p, wi: wo, power}, specular && bounce > 0));
+            specular = false;
+        }
+        // keep the photon's power about the same by dropping photons as often as their surfaces absorb them
+        let survival = sample.weight.x().max(sample.weight.y()).max(sample.weight.z()).min(1.0);
+        if survival <= 0.0 || drand48() >= survival {
+            break;
+        }
+        power *= sample.weight / survival;
+        ray = Ray::new(offset_origin(&rec, sample.wi), sample.wi, ray.time);
+    }
+    stored
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytrace::material::Lambertian;
+    use crate::raytrace::texture::ConstantTexture;
+    use crate::raytrace::renderer::Integrator;
+    use crate::raytrace::renderer::tests::{comparison_renderer, seeded_mean};
+
+    #[test]
+    fn nearest_photons() {
+        let photons: Vec<Photon> = (0..2000)
+            .map(|_| Photon{position: Vec3::new(drand48(), drand48(), 0.1 * drand48()), wi: Vec3::unit_y(), power: Vec3::zero()})
+            .collect();
+        let map = PhotonMap::new(photons.clone());
+        assert_eq!(map.len(), photons.len());
+        for _ in 0..20 {
+            let p = Vec3::new(drand48(), drand48(), drand48());
+            let mut expected: Vec<f64> = photons.iter().map(|photon| (photon.position - p).length_squared()).collect();
+            expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            let mut found: Vec<f64> = map.nearest(p, 10, f64::INFINITY).into_iter().map(|(d, _)| d).collect();
+            found.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            assert_eq!(found, expected[..10].to_vec());
+            let within: Vec<f64> = expected.iter().cloned().filter(|&d| d <= 0.01).take(10).collect();
+            assert_eq!(map.nearest(p, 10, 0.1).len(), within.len());
+        }
+    }
+
+    #[test]
+    fn density_estimate() {
+        // photons carrying a total power of 1 spread over a unit square give an irradiance of 1
+        let n = 100_000;
+        let photons = (0..n)
+            .map(|_| Photon{position: Vec3::new(drand48(), 0.0, drand48()), wi: Vec3::new(0.3, 1.0, 0.2).normalize(), power: Vec3::new(1.0, 1.0, 1.0) / n as f64})
+            .collect();
+        let map = PhotonMap::new(photons);
+        let rec = HitRecord::new(1.0, Vec3::new(0.5, 0.0, 0.5), Vec3::unit_y(), Arc::new(Lambertian::new(Arc::new(ConstantTexture::scalar(0.5)))), 0.0, 0.0);
+        // which a diffuse surface reflects as a radiance of albedo / pi
+        let radiance = map.estimate(&rec, Vec3::unit_y(), 2000, f64::INFINITY);
+        assert!((radiance.x() - 0.5 / std::f64::consts::PI).abs() < 0.02, "{:?}", radiance);
+    }
+
+    #[test]
+    fn matches_path_tracing() {
+        let path_tracer = comparison_renderer(64, vec![], vec![]);
+        let mut photon_mapper = comparison_renderer(64, vec![], vec![]);
+        photon_mapper.set_integrator(Integrator::PhotonMapping(PhotonSettings{photons: 100_000, neighbours: 50, radius: f64::INFINITY}));
+
+        // density estimation blurs the light a little, so only roughly the same
+        let (expected, actual) = (seeded_mean(&path_tracer, 1), seeded_mean(&photon_mapper, 2));
+        assert!((expected - actual).length() < 0.08 * expected.length(), "{:?} {:?}", expected, actual);
+    }
+}
@@ -0,0 +1,380 @@
+//! A principled uber material in the style of the Disney BRDF, combining diffuse, sheen, specular, clearcoat,
+//! transmission and emission lobes controlled by a small set of artist friendly parameters.
+//! See Burley, "Physically Based Shading at Disney".
+
+use super::Vec3;
+use super::ray::Ray;
+use super::hittable::HitRecord;
+use super::material::{Material, BsdfSample, Lobe};
+use super::texture::{Texture, ConstantTexture};
+use super::microfacet::{Ggx, Frame, fresnel_dielectric, fresnel_schlick, reflect_about, refract_about};
+use super::sampling::cosine_hemisphere;
+use super::util::luminance;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+/// Every parameter can be driven by a texture. Scalar parameters read the red channel of their texture,
+/// so grayscale textures and ConstantTexture::scalar work as expected. Scalars are in [0, 1] except the index of refraction.
+pub struct Principled {
+    base_color: Arc<dyn Texture>,
+    metallic: Arc<dyn Texture>,
+    roughness: Arc<dyn Texture>,
+    /// the reflectance of dielectrics at normal incidence, where 0.5 is 4%
+    specular: Arc<dyn Texture>,
+    /// a grazing retroreflection for cloth
+    sheen: Arc<dyn Texture>,
+    /// how much the sheen takes the hue of the base color instead of being white
+    sheen_tint: Arc<dyn Texture>,
+    /// a second clear specular layer, like varnish
+    clearcoat: Arc<dyn Texture>,
+    clearcoat_roughness: Arc<dyn Texture>,
+    /// how much of the non metallic part is transmissive glass rather than diffuse
+    transmission: Arc<dyn Texture>,
+    refractive_index: f64,
+    emission: Arc<dyn Texture>
+}
+
+/// The components of the material, one of which is picked when sampling.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Component {
+    Diffuse,
+    Specular,
+    Clearcoat,
+    Transmission
+}
+
+/// The parameters of a Principled material looked up at a hit.
+struct Parameters {
+    base_color: Vec3,
+    metallic: f64,
+    roughness: f64,
+    specular: f64,
+    sheen: f64,
+    sheen_tint: f64,
+    clearcoat: f64,
+    clearcoat_roughness: f64,
+    transmission: f64
+}
+
+fn scalar(value: f64) -> Arc<dyn Texture> {
+    Arc::new(ConstantTexture::scalar(value))
+}
+
+impl Principled {
+    /// A rough dielectric of the base color, with the other parameters at their defaults.
+    pub fn new(base_color: Arc<dyn Texture>) -> Self {
+        Principled{
+            base_color,
+            metallic: scalar(0.0),
+            roughness: scalar(0.5),
+            specular: scalar(0.5),
+            sheen: scalar(0.0),
+            sheen_tint: scalar(0.5),
+            clearcoat: scalar(0.0),
+            clearcoat_roughness: scalar(0.03),
+            transmission: scalar(0.0),
+            refractive_index: 1.5,
+            emission: Arc::new(ConstantTexture::new(Vec3::zero()))
+        }
+    }
+
+    pub fn set_base_color(&mut self, base_color: Arc<dyn Texture>) {
+        self.base_color = base_color;
+    }
+
+    pub fn set_metallic(&mut self, metallic: Arc<dyn Texture>) {
+        self.metallic = metallic;
+    }
+
+    pub fn set_roughness(&mut self, roughness: Arc<dyn Texture>) {
+        self.roughness = roughness;
+    }
+
+    pub fn set_specular(&mut self, specular: Arc<dyn Texture>) {
+        self.specular = specular;
+    }
+
+    pub fn set_sheen(&mut self, sheen: Arc<dyn Texture>, sheen_tint: Arc<dyn Texture>) {
+        self.sheen = sheen;
+        self.sheen_tint = sheen_tint;
+    }
+
+    pub fn set_clearcoat(&mut self, clearcoat: Arc<dyn Texture>, clearcoat_roughness: Arc<dyn Texture>) {
+        self.clearcoat = clearcoat;
+        self.clearcoat_roughness = clearcoat_roughness;
+    }
+
+    pub fn set_transmission(&mut self, transmission: Arc<dyn Texture>, refractive_index: f64) {
+        self.transmission = transmission;
+        self.refractive_index = refractive_index;
+    }
+
+    /// Sets the emitted radiance, which is emitted from the front of the surface.
+    pub fn set_emission(&mut self, emission: Arc<dyn Texture>) {
+        self.emission = emission;
+    }
+
+    fn parameters(&self, hit_record: &HitRecord) -> Parameters {
+        let (u, v, p) = (hit_record.u, hit_record.v, hit_record.p);
+        let scalar = |texture: &Arc<dyn Texture>| texture.value(u, v, p).x().clamp(0.0, 1.0);
+        Parameters{
+            base_color: self.base_color.value(u, v, p),
+            metallic: scalar(&self.metallic),
+            roughness: scalar(&self.roughness),
+            specular: scalar(&self.specular),
+            sheen: scalar(&self.sheen),
+            sheen_tint: scalar(&self.sheen_tint),
+            clearcoat: scalar(&self.clearcoat),
+            clearcoat_roughness: scalar(&self.clearcoat_roughness),
+            transmission: scalar(&self.transmission)
+        }
+    }
+}
+
+impl Parameters {
+    /// The specular reflectance at normal incidence, blending from dielectric to the base color for metals.
+    fn specular_f0(&self) -> Vec3 {
+        let dielectric = 0.08 * self.specular * Vec3::new(1.0, 1.0, 1.0);
+        (1.0 - self.metallic) * dielectric + self.metallic * self.base_color
+    }
+
+    /// The weights for picking each lobe given the cosine of the outgoing direction to the normal.
+    fn lobe_weights(&self, cos_o: f64) -> [(Component, f64); 4] {
+        let dielectric = 1.0 - self.metallic;
+        [
+            (Component::Diffuse, dielectric * (1.0 - self.transmission) * luminance(self.base_color).max(self.sheen)),
+            (Component::Specular, luminance(fresnel_schlick(cos_o, self.specular_f0()))),
+            (Component::Clearcoat, 0.25 * self.clearcoat * luminance(fresnel_schlick(cos_o, Vec3::new(0.04, 0.04, 0.04)))),
+            (Component::Transmission, dielectric * self.transmission)
+        ]
+    }
+}
+
+/// The state of a Principled material at a hit, in the frame of the side of the surface wo is on.
+struct Shading {
+    params: Parameters,
+    frame: Frame,
+    entering: bool,
+    /// the index of refraction of the other side of the surface over this side
+    eta: f64,
+    /// the probability of sampling each component
+    probabilities: [(Component, f64); 4]
+}
+
+impl Principled {
+    fn shading(&self, hit_record: &HitRecord, wo: Vec3) -> Shading {
+        let params = self.parameters(hit_record);
+        let entering = Vec3::dot(wo, hit_record.normal) > 0.0;
+        let frame = Frame::new(if entering { hit_record.normal } else { -hit_record.normal });
+        let eta = if entering { self.refractive_index } else { 1.0 / self.refractive_index };
+        // inside a transmissive object the only thing to do is leave through the interface
+        let mut probabilities = if !entering && params.transmission > 0.0 && params.metallic < 1.0 {
+            [(Component::Diffuse, 0.0), (Component::Specular, 0.0), (Component::Clearcoat, 0.0), (Component::Transmission, 1.0)]
+        }
+        else {
+            params.lobe_weights(frame.to_local(wo).z())
+        };
+        let total: f64 = probabilities.iter().map(|(_, w)| w).sum();
+        for (_, weight) in probabilities.iter_mut() {
+            *weight = if total > 0.0 { *weight / total } else { 0.0 };
+        }
+        Shading{params, frame, entering, eta, probabilities}
+    }
+}
+
+impl Shading {
+    /// The microfacet distribution and scale of a reflective or transmissive component.
+    fn ggx(&self, component: Component) -> Ggx {
+        match component {
+            Component::Clearcoat => Ggx::from_roughness(self.params.clearcoat_roughness),
+            _ => Ggx::from_roughness(self.params.roughness)
+        }
+    }
+
+    /// The reflectance at normal incidence and overall weight of the specular and clearcoat components.
+    fn reflection(&self, component: Component) -> (Vec3, f64) {
+        let params = &self.params;
+        if component == Component::Specular {
+            (params.specular_f0(), 1.0 - (1.0 - params.metallic) * params.transmission)
+        }
+        else {
+            (Vec3::new(0.04, 0.04, 0.04), 0.25 * params.clearcoat)
+        }
+    }
+
+    /// The tint of light passing through the surface from wo to wi, light going into the object takes the base color.
+    fn transmission_tint(&self, wi: Vec3) -> Vec3 {
+        let tint = if self.entering && wi.z() < 0.0 { self.params.base_color } else { Vec3::new(1.0, 1.0, 1.0) };
+        (1.0 - self.params.metallic) * self.params.transmission * tint
+    }
+
+    /// Whether a component is a perfectly smooth specular lobe that can't be evaluated.
+    fn is_specular(&self, component: Component) -> bool {
+        component != Component::Diffuse && self.ggx(component).is_smooth()
+    }
+
+    /// The bsdf of one component times the cosine, and its pdf, with wo and wi in the local frame.
+    fn eval(&self, component: Component, wo: Vec3, wi: Vec3) -> (Vec3, f64) {
+        let params = &self.params;
+        match component {
+            Component::Diffuse => {
+                if wi.z() <= 0.0 {
+                    return (Vec3::zero(), 0.0);
+                }
+                let h = (wo + wi).normalize();
+                let cos_d = Vec3::dot(wi, h);
+                // the Disney diffuse retroreflection, brightening grazing angles on rough surfaces
+                let fd90 = 0.5 + 2.0 * params.roughness * cos_d * cos_d;
+                let fd = (1.0 + (fd90 - 1.0) * (1.0 - wi.z()).powi(5)) * (1.0 + (fd90 - 1.0) * (1.0 - wo.z()).powi(5));
+                let tint = if luminance(params.base_color) > 0.0 { params.base_color / luminance(params.base_color) } else { Vec3::new(1.0, 1.0, 1.0) };
+                let sheen_color = (1.0 - params.sheen_tint) * Vec3::new(1.0, 1.0, 1.0) + params.sheen_tint * tint;
+                let sheen = params.sheen * (1.0 - cos_d).powi(5) * sheen_color;
+                let value = (1.0 - params.metallic) * (1.0 - params.transmission) * (fd * params.base_color / PI + sheen) * wi.z();
+                (value, wi.z() / PI)
+            }
+            Component::Specular | Component::Clearcoat => {
+                let ggx = self.ggx(component);
+                if wi.z() <= 0.0 {
+                    return (Vec3::zero(), 0.0);
+                }
+                let (f0, scale) = self.reflection(component);
+                let h = (wo + wi).normalize();
+                (scale * fresnel_schlick(Vec3::dot(wo, h), f0) * ggx.reflection_eval(wo, wi), ggx.reflection_pdf(wo, wi))
+            }
+            Component::Transmission => {
+                let (value, pdf) = self.ggx(component).dielectric_eval(wo, wi, self.eta);
+                (value * self.transmission_tint(wi), pdf)
+            }
+        }
+    }
+
+    /// The bsdf times the cosine and the pdf summed over the components that can be evaluated.
+    fn eval_total(&self, wo: Vec3, wi: Vec3) -> (Vec3, f64) {
+        let mut value = Vec3::zero();
+        let mut pdf = 0.0;
+        for &(component, probability) in self.probabilities.iter() {
+            if probability > 0.0 && !self.is_specular(component) {
+                let (v, p) = self.eval(component, wo, wi);
+                value += v;
+                pdf += probability * p;
+            }
+        }
+        (value, pdf)
+    }
+}
+
+impl Material for Principled {
+    fn sample(&self, hit_record: &HitRecord, wo: Vec3, u: [f64; 3]) -> Option<BsdfSample> {
+        let shading = self.shading(hit_record, wo);
+        let wo = shading.frame.to_local(wo);
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        // pick a component and rescale the random number so it can be reused
+        let mut u0 = u[0];
+        let mut picked = None;
+        for &(component, probability) in shading.probabilities.iter() {
+            if probability > 0.0 {
+                picked = Some((component, probability));
+                if u0 < probability {
+                    break;
+                }
+                u0 -= probability;
+            }
+        }
+        let (component, probability) = picked?;
+        let u0 = (u0 / probability).clamp(0.0, 1.0);
+
+        let ggx = shading.ggx(component);
+        let (wi, lobe) = match component {
+            Component::Diffuse => (cosine_hemisphere(u[1], u[2]), Lobe::DIFFUSE | Lobe::REFLECTION),
+            Component::Specular | Component::Clearcoat if ggx.is_smooth() => {
+                let wi = Vec3::new(-wo.x(), -wo.y(), wo.z());
+                let (f0, scale) = shading.reflection(component);
+                let weight = scale * fresnel_schlick(wo.z(), f0) / probability;
+                return Some(BsdfSample{wi: shading.frame.to_world(wi), weight, pdf: 1.0, lobe: Lobe::SPECULAR | Lobe::REFLECTION});
+            }
+            Component::Specular | Component::Clearcoat => {
+                let h = ggx.sample_visible_normal(wo, u[1], u[2]);
+                (reflect_about(wo, h), Lobe::GLOSSY | Lobe::REFLECTION)
+            }
+            Component::Transmission if ggx.is_smooth() => {
+                let h = Vec3::unit_z();
+                let wi = match refract_about(wo, h, shading.eta) {
+                    Some(refracted) if u0 >= fresnel_dielectric(wo.z(), shading.eta) => refracted,
+                    _ => reflect_about(wo, h)
+                };
+                let lobe = if wi.z() > 0.0 { Lobe::SPECULAR | Lobe::REFLECTION } else { Lobe::SPECULAR | Lobe::TRANSMISSION };
+                let weight = shading.transmission_tint(wi) / probability;
+                return Some(BsdfSample{wi: shading.frame.to_world(wi), weight, pdf: 1.0, lobe});
+            }
+            Component::Transmission => {
+                let (wi, _) = ggx.sample_dielectric(wo, shading.eta, [u0, u[1], u[2]])?;
+                (wi, if wi.z() > 0.0 { Lobe::GLOSSY | Lobe::REFLECTION } else { Lobe::GLOSSY | Lobe::TRANSMISSION })
+            }
+        };
+        if wi.z() == 0.0 {
+            return None;
+        }
+
+        // the non specular components overlap, so weight by all of them as if sampled from their mixture
+        let (value, pdf) = shading.eval_total(wo, wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample{wi: shading.frame.to_world(wi), weight: value / pdf, pdf, lobe})
+    }
+
+    fn eval(&self, hit_record: &HitRecord, wo: Vec3, wi: Vec3) -> Vec3 {
+        let shading = self.shading(hit_record, wo);
+        shading.eval_total(shading.frame.to_local(wo), shading.frame.to_local(wi)).0
+    }
+
+    fn pdf(&self, hit_record: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        let shading = self.shading(hit_record, wo);
+        shading.eval_total(shading.frame.to_local(wo), shading.frame.to_local(wi)).1
+    }
+
+    fn emitted(&self, ray_in: &Ray, hit_record: &HitRecord) -> Vec3 {
+        if Vec3::dot(ray_in.direction, hit_record.normal) < 0.0 {
+            self.emission.value(hit_record.u, hit_record.v, hit_record.p)
+        }
+        else {
+            Vec3::zero()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytrace::material::NormalMaterial;
+
+    #[test]
+    fn principled_lobes() {
+        let rec = HitRecord::new(1.0, Vec3::zero(), Vec3::unit_y(), Arc::new(NormalMaterial::new()), 0.5, 0.5);
+        let ray = Ray::new(Vec3::new(-1.0, 1.0, 0.0), Vec3::new(1.0, -1.0, 0.0), 0.0);
+        // a white diffuse material reflects everything above the surface and an opaque one never transmits
+        let mut white = Principled::new(Arc::new(ConstantTexture::new(Vec3::new(1.0, 1.0, 1.0))));
+        white.set_specular(scalar(0.0));
+        white.set_roughness(scalar(0.0));
+        let n = 20000;
+        let mut total = Vec3::zero();
+        for _ in 0..n {
+            let (scattered, weight) = white.scatter(&ray, &rec).unwrap();
+            assert!(scattered.direction.y() > 0.0);
+            total += weight;
+        }
+        // the smooth Disney diffuse darkens grazing views slightly
+        let albedo = total.y() / n as f64;
+        assert!(albedo > 0.7 && albedo <= 1.05, "{}", albedo);
+
+        // fully transmissive glass sends most light into the surface
+        let mut glass = Principled::new(Arc::new(ConstantTexture::new(Vec3::new(1.0, 1.0, 1.0))));
+        glass.set_transmission(scalar(1.0), 1.5);
+        glass.set_roughness(scalar(0.0));
+        let below = (0..1000).filter_map(|_| glass.scatter(&ray, &rec)).filter(|(s, _)| s.direction.y() < 0.0).count();
+        assert!(below > 800);
+    }
+}
@@ -169,18 +169,25 @@ impl Quaternion {
         // http://number-none.com/product/Understanding%20Slerp,%20Then%20Not%20Using%20It/
         let threshold = 0.9995f64;
         let mut dot = Quaternion::dot(self, other);
+        let mut other = other;
+
+        // q and -q are the same rotation, flip one so the interpolation takes the shortest path
+        if dot < 0.0 {
+            other = -other;
+            dot = -dot;
+        }
 
         if dot > threshold {
             // too close just use nlerp
             self.nlerp(other, ammount)
         }
         else {
-            dot = if dot > 1.0 { 1.0 } else if dot < -1.0 { -1.0 } else { dot };
+            dot = if dot > 1.0 { 1.0 } else { dot };
             let theta = dot.acos();
             let scale1 = f64::sin(theta * (1.0 - ammount));
             let scale2 = f64::sin(theta * ammount);
 
-            (self * scale1 + other * scale2) * f64::sin(theta)
+            (self * scale1 + other * scale2) / f64::sin(theta)
         }
     }
 
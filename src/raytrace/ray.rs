@@ -4,13 +4,16 @@ use super::Vec3;
 pub struct Ray {
     pub origin: Vec3,
     pub direction: Vec3,
+    /// the time within the camera shutter interval the ray exists at
+    pub time: f64,
 }
 
 impl Ray {
-    pub fn new(origin: Vec3, direction: Vec3) -> Self {
+    pub fn new(origin: Vec3, direction: Vec3, time: f64) -> Self {
         Ray {
-            origin: origin,
-            direction: direction,
+            origin,
+            direction,
+            time,
         }
     }
 
@@ -25,18 +28,19 @@ mod tests {
 
     #[test]
     fn new() {
-        let ray = Ray::new(Vec3::new(0.0,0.0,0.0), Vec3::new(4.0,4.0,4.0));
+        let ray = Ray::new(Vec3::new(0.0,0.0,0.0), Vec3::new(4.0,4.0,4.0), 0.5);
         assert_eq!(ray.origin.x(), 0.0);
         assert_eq!(ray.origin.y(), 0.0);
         assert_eq!(ray.origin.z(), 0.0);
         assert_eq!(ray.direction.x(), 4.0);
         assert_eq!(ray.direction.y(), 4.0);
         assert_eq!(ray.direction.z(), 4.0);
+        assert_eq!(ray.time, 0.5);
     }
 
     #[test]
     fn point_at_parameter() {
-        let ray = Ray::new(Vec3::new(0.0,0.0,0.0), Vec3::new(4.0,4.0,4.0));
+        let ray = Ray::new(Vec3::new(0.0,0.0,0.0), Vec3::new(4.0,4.0,4.0), 0.0);
         let p = ray.point_at_parameter(0.0);
         assert_eq!(p.x(), 0.0);
         assert_eq!(p.y(), 0.0);
@@ -3,12 +3,40 @@ use crate::raytrace::hittable::Hittable;
 use crate::raytrace::ray::Ray;
 use crate::raytrace::Vec3;
 use crate::raytrace::camera::Camera;
+use crate::raytrace::hittable::HitRecord;
+use crate::raytrace::sampling::power_heuristic;
+use crate::raytrace::light::{Light, LightSample};
+use crate::raytrace::material::{offset_origin, scattering_hit};
+use crate::raytrace::spectrum::{RgbSpectrum, SampledWavelengths};
+use crate::raytrace::bidirectional::BidirectionalTracer;
+use crate::raytrace::photon_map::{PhotonMaps, PhotonSettings};
+use crate::raytrace::metropolis::{MetropolisSettings, MltSampler, SharedSampler};
+use crate::raytrace::guiding::PathGuide;
+use crate::raytrace::sampling::Distribution1D;
+use crate::raytrace::util::{luminance, with_random_stream};
+use std::cell::RefCell;
+use std::rc::Rc;
 use std::sync::Arc;
+use std::path::Path;
+use std::fs::File;
+use std::io::BufWriter;
+use rayon::prelude::*;
 
 pub type BgFunc = Box<dyn Fn(Ray) -> Vec3 + Send + Sync>;
 
 pub trait Background: Send + Sync {
     fn get(&self, ray: Ray) -> Vec3;
+
+    /// Picks a direction towards the background for direct lighting, returning the direction, the radiance
+    /// from it and the pdf of picking it with respect to solid angle. Backgrounds that can't be sampled return None.
+    fn sample(&self) -> Option<(Vec3, Vec3, f64)> {
+        None
+    }
+
+    /// The pdf of sample returning direction.
+    fn pdf(&self, _direction: Vec3) -> f64 {
+        0.0
+    }
 }
 
 pub struct GradientBackground {}
@@ -23,54 +51,536 @@ impl Background for GradientBackground {
     }
 }
 
+/// The algorithm used to find the light reaching the camera.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Integrator {
+    /// Traces paths from the camera, sampling the lights at each bounce.
+    PathTracing,
+    /// Traces paths from both the camera and the lights and connects them, which finds light through small
+    /// openings and caustics much faster. Ignores spectral rendering.
+    Bidirectional,
+    /// Traces photons from the lights before rendering and estimates caustics and indirect light from them,
+    /// sampling direct light like the path tracer. Smooth but slightly blurred. Ignores spectral rendering.
+    PhotonMapping(PhotonSettings),
+    /// Mutates the random numbers of paths the path tracer found light along, to find nearby paths carrying light.
+    /// Finds hard to reach light well but converges unevenly. Ignores spectral rendering and the samples per pixel.
+    Metropolis(MetropolisSettings)
+}
+
 pub struct Renderer  {
     pub width: u32,
     pub height: u32,
     pub samples: u32,
     objects: Arc<dyn Hittable>,
-    camera: Camera,
-    background: Option<Box<dyn Background>>
+    camera: Box<dyn Camera>,
+    background: Option<Box<dyn Background>>,
+    lights: Vec<Arc<dyn Light>>,
+    /// set when tracing wavelengths instead of RGB
+    spectrum: Option<RgbSpectrum>,
+    integrator: Integrator,
+    path_guiding: bool
 }
 
 impl Renderer {
-    pub fn new(width: u32, height: u32, samples: u32, camera: Camera, objects: Arc<dyn Hittable>, background: Option<Box<dyn Background>>) -> Self {
-        Renderer{ width, height, samples, objects, camera, background }
+    pub fn new(width: u32, height: u32, samples: u32, camera: Box<dyn Camera>, objects: Arc<dyn Hittable>, background: Option<Box<dyn Background>>) -> Self {
+        Renderer{ width, height, samples, objects, camera, background, lights: Vec::new(), spectrum: None, integrator: Integrator::PathTracing, path_guiding: false }
+    }
+
+    pub fn set_camera(&mut self, camera: Box<dyn Camera>) {
+        self.camera = camera;
+    }
+
+    /// Sets the lights that are sampled directly, area lights also need adding to the objects to be visible.
+    pub fn set_lights(&mut self, lights: Vec<Arc<dyn Light>>) {
+        self.lights = lights;
+    }
+
+    /// Switches between tracing RGB colors and tracing wavelengths, which is slower and noisier
+    /// but needed for wavelength dependent effects like dispersion.
+    pub fn set_spectral(&mut self, spectral: bool) {
+        self.spectrum = if spectral { Some(RgbSpectrum::new()) } else { None };
+    }
+
+    pub fn set_integrator(&mut self, integrator: Integrator) {
+        self.integrator = integrator;
+    }
+
+    /// Switches path guiding on or off for the path tracer. When on, the image is rendered in passes of doubling
+    /// samples, and paths learn from the earlier passes which directions light comes from.
+    pub fn set_path_guiding(&mut self, path_guiding: bool) {
+        self.path_guiding = path_guiding;
+    }
+
+    /// Renders the whole image in parallel, returning it as gamma corrected RGBA bytes with the top row first.
+    pub fn render(&self) -> Vec<u8> {
+        let mut data = vec![0u8; (self.width * self.height * 4) as usize];
+        for (rgba, color) in data.chunks_mut(4).zip(self.render_linear()) {
+            rgba[0] = (255.99 * color.x().sqrt()) as u8;
+            rgba[1] = (255.99 * color.y().sqrt()) as u8;
+            rgba[2] = (255.99 * color.z().sqrt()) as u8;
+            rgba[3] = 255;
+        }
+        data
+    }
+
+    /// Renders the whole image in parallel, returning the radiance of each pixel with the top row first.
+    pub fn render_linear(&self) -> Vec<Vec3> {
+        let mut pixels = vec![Vec3::zero(); (self.width * self.height) as usize];
+        match self.integrator {
+            Integrator::PathTracing if self.path_guiding => self.render_guided(&mut pixels),
+            Integrator::PathTracing => self.render_unguided(&mut pixels),
+            Integrator::Bidirectional => self.render_bidirectional(&mut pixels),
+            Integrator::PhotonMapping(settings) => {
+                let maps = PhotonMaps::trace(self.objects.as_ref(), &self.lights, settings.photons);
+                pixels
+                    .par_chunks_mut(self.width as usize)
+                    .rev()
+                    .enumerate()
+                    .for_each(|(j, row)| {
+                        for (i, pixel) in row.iter_mut().enumerate() {
+                            for _s in 0..self.samples {
+                                let u = (i as f64 + drand48()) / self.width as f64;
+                                let v = (j as f64 + drand48()) / self.height as f64;
+                                if let Some(ray) = self.camera.get_ray(u, v) {
+                                    *pixel += self.photon_mapped_color(&maps, &settings, ray, 0);
+                                }
+                            }
+                            *pixel *= self.camera.exposure() / self.samples as f64;
+                        }
+                    });
+            }
+            Integrator::Metropolis(settings) => self.render_metropolis(&settings, &mut pixels)
+        }
+        pixels
+    }
+
+    /// Path traces the image in passes of 1, 2, 4 and so on samples, refining the guide after each pass.
+    fn render_guided(&self, pixels: &mut [Vec3]) {
+        let mut guide = match self.objects.bounding_box() {
+            Some(bounds) => PathGuide::new(bounds),
+            None => return self.render_unguided(pixels)
+        };
+        let mut pass_samples = 1;
+        let mut done = 0;
+        while done < self.samples {
+            let samples = pass_samples.min(self.samples - done);
+            pixels
+                .par_chunks_mut(self.width as usize)
+                .rev()
+                .enumerate()
+                .for_each(|(j, row)| {
+                    for (i, pixel) in row.iter_mut().enumerate() {
+                        *pixel += self.color_samples(i as f64, j as f64, samples, Some(&guide));
+                    }
+                });
+            guide.refine(samples);
+            done += samples;
+            pass_samples *= 2;
+        }
+        let scale = self.camera.exposure() / self.samples as f64;
+        for pixel in pixels.iter_mut() {
+            *pixel *= scale;
+        }
+    }
+
+    fn render_unguided(&self, pixels: &mut [Vec3]) {
+        pixels
+            .par_chunks_mut(self.width as usize)
+            .rev()
+            .enumerate()
+            .for_each(|(j, row)| {
+                for (i, pixel) in row.iter_mut().enumerate() {
+                    *pixel = self.color_at(i as f64, j as f64);
+                }
+            });
+    }
+
+    fn render_bidirectional(&self, pixels: &mut [Vec3]) {
+        let tracer = BidirectionalTracer::new(self.objects.as_ref(), self.camera.as_ref(), &self.lights, self.background.as_deref());
+        let (width, height) = (self.width as usize, self.height as usize);
+        // light traced to the camera lands anywhere on the image, so each thread gathers it separately
+        let splats = pixels
+            .par_chunks_mut(width)
+            .rev()
+            .enumerate()
+            .fold(|| vec![Vec3::zero(); width * height], |mut splats, (j, row)| {
+                let mut splat = |u: f64, v: f64, color: Vec3| {
+                    let i = ((u * width as f64) as usize).min(width - 1);
+                    let j = ((v * height as f64) as usize).min(height - 1);
+                    splats[(height - 1 - j) * width + i] += color;
+                };
+                for (i, pixel) in row.iter_mut().enumerate() {
+                    for _s in 0..self.samples {
+                        let u = (i as f64 + drand48()) / width as f64;
+                        let v = (j as f64 + drand48()) / height as f64;
+                        *pixel += tracer.trace(u, v, &mut splat);
+                    }
+                }
+                splats
+            })
+            .reduce(|| vec![Vec3::zero(); width * height], |mut a, b| {
+                for (a, b) in a.iter_mut().zip(b) {
+                    *a += b;
+                }
+                a
+            });
+        let scale = self.camera.exposure() / self.samples as f64;
+        for (pixel, splat) in pixels.iter_mut().zip(splats) {
+            *pixel = (*pixel + splat) * scale;
+        }
     }
 
     pub fn color_at(&self, u: f64, v: f64) -> Vec3 {
+        self.color_samples(u, v, self.samples, None) / self.samples as f64 * self.camera.exposure()
+    }
+
+    /// The sum of samples paths traced through the pixel, before exposure.
+    fn color_samples(&self, u: f64, v: f64, samples: u32, guide: Option<&PathGuide>) -> Vec3 {
         let mut col = Vec3::new(0.0, 0.0, 0.0);
-        for _s in 0..self.samples {
+        for _s in 0..samples {
             let u = (u + drand48()) / self.width as f64;
             let v = (v + drand48()) / self.height as f64;
-            let r = self.camera.get_ray(u, v);
-            col += self.color_from_ray(r, 0);
+            if let Some(r) = self.camera.get_ray(u, v) {
+                match &self.spectrum {
+                    Some(spectrum) => {
+                        let mut wavelengths = SampledWavelengths::sample(drand48());
+                        let values = self.color_from_ray(r, 0, None, Some(&mut wavelengths), guide);
+                        col += spectrum.to_rgb(values, &wavelengths);
+                    }
+                    None => col += self.color_from_ray(r, 0, None, None, guide)
+                }
+            }
         }
-        col /= self.samples as f64;
         col
     }
 
-    fn color_from_ray(&self, ray: Ray, depth: u32) -> Vec3 {
+    /// Converts a color to values at the traced wavelengths when rendering spectrally.
+    fn upsample(&self, color: Vec3, wavelengths: Option<&SampledWavelengths>) -> Vec3 {
+        match (&self.spectrum, wavelengths) {
+            (Some(spectrum), Some(wavelengths)) => spectrum.upsample_sampled(color, wavelengths),
+            _ => color
+        }
+    }
+
+    /// Traces a path from the ray, scatter_pdf is the pdf of the material that scattered the ray if it could have
+    /// sampled the lights and background directly, which is used to weight any light the ray reaches.
+    /// When rendering spectrally the result is the radiance at the wavelengths instead of a color.
+    /// Directions are sampled from the guide when given, and the light found along them is recorded in it.
+    fn color_from_ray(&self, ray: Ray, depth: u32, scatter_pdf: Option<f64>, mut wavelengths: Option<&mut SampledWavelengths>, guide: Option<&PathGuide>) -> Vec3 {
         match self.objects.hit(ray, 0.0, std::f64::MAX) {
-            Some(rec) => {
-                let emitted = rec.material.emitted(rec.u, rec.v, rec.p);
-                let material = &rec.material;
+            Some(mut rec) => {
+                rec.wavelength = wavelengths.as_ref().map(|w| w.hero());
+                let mut emitted = self.upsample(rec.material.emitted(&ray, &rec), wavelengths.as_deref());
+                if let Some(pdf) = scatter_pdf {
+                    if !emitted.is_zero_length() {
+                        // weight against the light having been sampled directly at the previous bounce
+                        emitted *= power_heuristic(pdf, self.light_pdf(&ray, &rec));
+                    }
+                }
                 if depth >= 50 {
                     return emitted;
                 }
-                else if let Some((scattered, attenuation)) = material.scatter(&ray, &rec) {
-                    let ray = Ray::new(scattered.origin + super::EPSILON * rec.normal, scattered.direction);
-                    return emitted + attenuation * self.color_from_ray(ray, depth + 1)
-                }
-                else {
-                    emitted
+                let (rec, wo, through) = match scattering_hit(rec, -ray.direction.normalize(), ray.time) {
+                    Some(scattering) => scattering,
+                    None => return emitted
+                };
+                let through = self.upsample(through, wavelengths.as_deref());
+                let material = &rec.material;
+                let u = [drand48(), drand48(), drand48()];
+                let sample = match guide {
+                    Some(guide) => guide.sample(&rec, wo, u),
+                    None => material.sample(&rec, wo, u)
+                };
+                match sample {
+                    Some(sample) => {
+                        let direct = self.sample_lights(&ray, &rec, wo, wavelengths.as_deref(), guide);
+                        if material.is_dispersive() {
+                            if let Some(wavelengths) = wavelengths.as_deref_mut() {
+                                wavelengths.terminate_secondary();
+                            }
+                        }
+                        let weight = self.upsample(sample.weight, wavelengths.as_deref());
+                        let scattered = Ray::new(offset_origin(&rec, sample.wi), sample.wi, ray.time);
+                        let scatter_pdf = if sample.lobe.is_specular() { None } else { Some(sample.pdf) };
+                        let incoming = self.color_from_ray(scattered, depth + 1, scatter_pdf, wavelengths, guide);
+                        if let (Some(guide), Some(pdf)) = (guide, scatter_pdf) {
+                            guide.record(rec.p, sample.wi, luminance(incoming), pdf);
+                        }
+                        emitted + through * (direct + weight * incoming)
+                    }
+                    None => emitted
                 }
             }
             None => {
                 match &self.background {
-                    Some(bg) => bg.get(ray), 
+                    Some(bg) => {
+                        let radiance = self.upsample(bg.get(ray), wavelengths.as_deref());
+                        // weight against the background having been sampled directly at the previous bounce
+                        match scatter_pdf {
+                            Some(pdf) => power_heuristic(pdf, bg.pdf(ray.direction)) * radiance,
+                            None => radiance
+                        }
+                    }
                     None => Vec3::new(0.0, 0.0, 0.0)
                 }
             }
         }
     }
-}
\ No newline at end of file
+
+    /// Traces a path with the random numbers from sampler, returning the image coordinates it went through and its color.
+    fn metropolis_path(&self, sampler: &Rc<RefCell<MltSampler>>) -> (f64, f64, Vec3) {
+        with_random_stream(Box::new(SharedSampler(Rc::clone(sampler))), || {
+            let (u, v) = (drand48(), drand48());
+            let color = self.camera.get_ray(u, v).map_or(Vec3::zero(), |ray| self.color_from_ray(ray, 0, None, None, None));
+            (u, v, color)
+        })
+    }
+
+    fn render_metropolis(&self, settings: &MetropolisSettings, pixels: &mut [Vec3]) {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let new_sampler = |seed: usize| Rc::new(RefCell::new(MltSampler::new(seed as u64, settings.sigma, settings.large_step_probability)));
+        // the average brightness of paths normalizes the image, and chains start from bright paths
+        let brightness: Vec<f64> = (0..settings.bootstrap_samples)
+            .into_par_iter()
+            .map(|seed| luminance(self.metropolis_path(&new_sampler(seed)).2).max(0.0))
+            .collect();
+        let average = brightness.iter().sum::<f64>() / settings.bootstrap_samples as f64;
+        if average <= 0.0 || settings.chains == 0 {
+            return;
+        }
+        let seeds = Distribution1D::new(brightness);
+        let mutations = settings.mutations_per_pixel as u64 * (width * height) as u64;
+        let film = (0..settings.chains)
+            .into_par_iter()
+            .fold(|| vec![Vec3::zero(); width * height], |mut film, chain| {
+                let mut splat = |u: f64, v: f64, color: Vec3| {
+                    let i = ((u * width as f64) as usize).min(width - 1);
+                    let j = ((v * height as f64) as usize).min(height - 1);
+                    film[(height - 1 - j) * width + i] += color;
+                };
+                let (seed, _) = seeds.sample_discrete(drand48());
+                let sampler = new_sampler(seed);
+                let mut current = self.metropolis_path(&sampler);
+                let chain_mutations = mutations / settings.chains as u64 + if (chain as u64) < mutations % settings.chains as u64 { 1 } else { 0 };
+                for _ in 0..chain_mutations {
+                    sampler.borrow_mut().start_iteration();
+                    let proposed = self.metropolis_path(&sampler);
+                    let (current_brightness, proposed_brightness) = (luminance(current.2), luminance(proposed.2));
+                    let accept = if current_brightness > 0.0 { (proposed_brightness / current_brightness).clamp(0.0, 1.0) } else { 1.0 };
+                    // both paths are recorded, weighted by how likely the chain is to be at each
+                    if proposed_brightness > 0.0 {
+                        splat(proposed.0, proposed.1, accept / proposed_brightness * proposed.2);
+                    }
+                    if current_brightness > 0.0 {
+                        splat(current.0, current.1, (1.0 - accept) / current_brightness * current.2);
+                    }
+                    if drand48() < accept {
+                        current = proposed;
+                        sampler.borrow_mut().accept();
+                    }
+                    else {
+                        sampler.borrow_mut().reject();
+                    }
+                }
+                film
+            })
+            .reduce(|| vec![Vec3::zero(); width * height], |mut a, b| {
+                for (a, b) in a.iter_mut().zip(b) {
+                    *a += b;
+                }
+                a
+            });
+        let scale = self.camera.exposure() * average / settings.mutations_per_pixel as f64;
+        for (pixel, color) in pixels.iter_mut().zip(film) {
+            *pixel = scale * color;
+        }
+    }
+
+    /// Follows a camera ray through specular bounces to a diffuse hit, where direct light is sampled, caustics are
+    /// estimated from the caustic photons and the rest of the indirect light is gathered from the global photons.
+    fn photon_mapped_color(&self, maps: &PhotonMaps, settings: &PhotonSettings, ray: Ray, depth: u32) -> Vec3 {
+        let rec = match self.objects.hit(ray, 0.0, f64::MAX) {
+            Some(rec) => rec,
+            None => return self.background.as_ref().map_or(Vec3::zero(), |bg| bg.get(ray))
+        };
+        let emitted = rec.material.emitted(&ray, &rec);
+        if depth >= 50 {
+            return emitted;
+        }
+        let (rec, wo, through) = match scattering_hit(rec, -ray.direction.normalize(), ray.time) {
+            Some(scattering) => scattering,
+            None => return emitted
+        };
+        // light that isn't specular can be estimated whatever the material picks to scatter
+        let direct = self.sample_lights(&ray, &rec, wo, None, None) + maps.caustic.estimate(&rec, wo, settings.neighbours, settings.radius);
+        match rec.material.sample(&rec, wo, [drand48(), drand48(), drand48()]) {
+            Some(sample) => {
+                let scattered = Ray::new(offset_origin(&rec, sample.wi), sample.wi, ray.time);
+                let indirect = if sample.lobe.is_specular() {
+                    self.photon_mapped_color(maps, settings, scattered, depth + 1)
+                }
+                else {
+                    self.gather(maps, settings, scattered, Some(sample.pdf), depth + 1)
+                };
+                emitted + through * (direct + sample.weight * indirect)
+            }
+            None => emitted + through * direct
+        }
+    }
+
+    /// The light reflected back along a final gather ray, estimated from the global photons where it lands.
+    /// scatter_pdf is the pdf of a diffuse hit scattering the ray, so light it reaches directly is weighted against
+    /// sample_lights; it's None once the ray has bounced specularly, when the caustic photons have counted the lights.
+    fn gather(&self, maps: &PhotonMaps, settings: &PhotonSettings, ray: Ray, scatter_pdf: Option<f64>, depth: u32) -> Vec3 {
+        let rec = match self.objects.hit(ray, 0.0, f64::MAX) {
+            Some(rec) => rec,
+            None => {
+                return match &self.background {
+                    Some(bg) => scatter_pdf.map_or(1.0, |pdf| power_heuristic(pdf, bg.pdf(ray.direction))) * bg.get(ray),
+                    None => Vec3::zero()
+                };
+            }
+        };
+        let mut color = rec.material.emitted(&ray, &rec);
+        if !color.is_zero_length() {
+            let light_pdf = self.light_pdf(&ray, &rec);
+            color *= match scatter_pdf {
+                Some(pdf) => power_heuristic(pdf, light_pdf),
+                // emitters that aren't lights don't emit photons
+                None => if light_pdf > 0.0 { 0.0 } else { 1.0 }
+            };
+        }
+        let (rec, wo, through) = match scattering_hit(rec, -ray.direction.normalize(), ray.time) {
+            Some(scattering) => scattering,
+            None => return color
+        };
+        color += through * maps.global.estimate(&rec, wo, settings.neighbours, settings.radius);
+        if depth < 50 {
+            if let Some(sample) = rec.material.sample(&rec, wo, [drand48(), drand48(), drand48()]) {
+                if sample.lobe.is_specular() {
+                    let scattered = Ray::new(offset_origin(&rec, sample.wi), sample.wi, ray.time);
+                    color += through * sample.weight * self.gather(maps, settings, scattered, None, depth + 1);
+                }
+            }
+        }
+        color
+    }
+
+    /// The pdf of sample_lights picking the direction of the ray, given it hit rec.
+    fn light_pdf(&self, ray: &Ray, rec: &HitRecord) -> f64 {
+        let distance = rec.t * ray.direction.length();
+        self.lights.iter()
+            .filter(|light| !light.is_delta())
+            .filter_map(|light| light.eval(ray.origin, ray.direction))
+            // only the light that was actually hit counts
+            .filter(|sample| (sample.distance - distance).abs() <= super::EPSILON.max(1e-6 * distance))
+            .map(|sample| sample.pdf)
+            .sum()
+    }
+
+    /// Samples a direction towards each light and the background from the hit and returns the light arriving
+    /// from them that isn't blocked, weighted for multiple importance sampling with the material, or with the
+    /// guide when one is sampling directions, which also records the light.
+    fn sample_lights(&self, ray: &Ray, rec: &HitRecord, wo: Vec3, wavelengths: Option<&SampledWavelengths>, guide: Option<&PathGuide>) -> Vec3 {
+        // no light reaches a purely specular material from a sampled direction, so don't trace shadow rays for it
+        if rec.material.is_specular() {
+            return Vec3::zero();
+        }
+        let mut samples: Vec<(LightSample, bool)> = self.lights.iter()
+            .filter_map(|light| light.sample(rec.p).map(|sample| (sample, light.is_delta())))
+            .collect();
+        if let Some((direction, radiance, pdf)) = self.background.as_ref().and_then(|bg| bg.sample()) {
+            samples.push((LightSample{direction, radiance, pdf, distance: f64::MAX}, false));
+        }
+
+        let mut color = Vec3::zero();
+        for (sample, is_delta) in samples {
+            if sample.pdf == 0.0 {
+                continue;
+            }
+            let f = rec.material.eval(rec, wo, sample.direction);
+            if f.is_zero_length() {
+                continue;
+            }
+            let shadow_ray = Ray::new(offset_origin(rec, sample.direction), sample.direction, ray.time);
+            let transmittance = self.objects.transmittance(shadow_ray, 0.0, sample.distance - 2.0 * super::EPSILON);
+            if transmittance.is_zero_length() {
+                continue;
+            }
+            let weight = if is_delta { 1.0 } else {
+                let scatter_pdf = guide.map_or_else(|| rec.material.pdf(rec, wo, sample.direction), |guide| guide.pdf(rec, wo, sample.direction));
+                power_heuristic(sample.pdf, scatter_pdf)
+            };
+            match guide {
+                Some(guide) if !is_delta => guide.record(rec.p, sample.direction, weight * luminance(sample.radiance), sample.pdf),
+                _ => ()
+            }
+            color += weight * self.upsample(f, wavelengths) * self.upsample(transmittance * sample.radiance, wavelengths) / sample.pdf;
+        }
+        color
+    }
+}
+
+/// Writes RGBA data to a png file.
+pub fn write_png(path: &Path, width: u32, height: u32, data: &[u8]) -> std::io::Result<()> {
+    let file = File::create(path)?;
+    let w = BufWriter::new(file);
+
+    let mut encoder = png::Encoder::new(w, width, height);
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+
+    writer.write_image_data(data)?;
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::raytrace::util::RandomStream;
+    use crate::raytrace::hittable_list::HittableList;
+    use crate::raytrace::sphere::Sphere;
+    use crate::raytrace::camera::PerspectiveCamera;
+    use crate::raytrace::light::QuadLight;
+    use crate::raytrace::material::Lambertian;
+    use crate::raytrace::texture::ConstantTexture;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    struct Seeded(StdRng);
+
+    impl RandomStream for Seeded {
+        fn next(&mut self) -> f64 {
+            self.0.gen()
+        }
+    }
+
+    /// A small image of a grey sphere on a grey floor under a quad light, plus any extra objects and lights, for
+    /// checking that another integrator converges to the same image as path tracing.
+    pub(crate) fn comparison_renderer(samples: u32, extra_objects: Vec<Box<dyn Hittable>>, extra_lights: Vec<Arc<dyn Light>>) -> Renderer {
+        let quad = || QuadLight::new(Vec3::new(-1.0, 3.0, -1.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 2.0), Vec3::new(4.0, 4.0, 4.0));
+        let grey = Arc::new(Lambertian::new(Arc::new(ConstantTexture::scalar(0.6))));
+        let mut objects: Vec<Box<dyn Hittable>> = vec![
+            Box::new(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, grey.clone())),
+            Box::new(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 1.0, grey)),
+            Box::new(quad())
+        ];
+        objects.extend(extra_objects);
+        let mut lights: Vec<Arc<dyn Light>> = vec![Arc::new(quad())];
+        lights.extend(extra_lights);
+        let camera = PerspectiveCamera::new(Vec3::new(0.0, 2.0, 8.0), Vec3::new(0.0, 1.0, 0.0), Vec3::unit_y(), 0.6, 1.5, 0.0, 8.0);
+        let mut renderer = Renderer::new(24, 16, samples, Box::new(camera), Arc::new(HittableList::from_vec(objects)), None);
+        renderer.set_lights(lights);
+        renderer
+    }
+
+    /// The mean pixel of the image, rendered on a single thread from a seeded random stream so that it comes out
+    /// the same every run.
+    pub(crate) fn seeded_mean(renderer: &Renderer, seed: u64) -> Vec3 {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+        let pixels = pool.install(|| with_random_stream(Box::new(Seeded(StdRng::seed_from_u64(seed))), || renderer.render_linear()));
+        let n = pixels.len() as f64;
+        pixels.into_iter().fold(Vec3::zero(), |a, b| a + b) / n
+    }
+}
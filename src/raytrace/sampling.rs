@@ -0,0 +1,184 @@
+//! Piecewise constant distributions for importance sampling tabulated functions like images, and other sampling helpers.
+
+use super::Vec3;
+use std::f64::consts::PI;
+
+/// A piecewise constant distribution over [0, 1) built from a list of non-negative weights.
+#[derive(Clone, Debug)]
+pub struct Distribution1D {
+    function: Vec<f64>,
+    cdf: Vec<f64>,
+    integral: f64
+}
+
+impl Distribution1D {
+    pub fn new(function: Vec<f64>) -> Self {
+        let n = function.len();
+        assert!(n > 0, "Distribution1D needs at least one value");
+        let mut cdf = vec![0.0; n + 1];
+        for i in 0..n {
+            cdf[i + 1] = cdf[i] + function[i].max(0.0) / n as f64;
+        }
+        let integral = cdf[n];
+        if integral == 0.0 {
+            // nothing to importance sample, fall back to uniform
+            for (i, c) in cdf.iter_mut().enumerate() {
+                *c = i as f64 / n as f64;
+            }
+        }
+        else {
+            for c in cdf.iter_mut() {
+                *c /= integral;
+            }
+        }
+        Distribution1D{function, cdf, integral}
+    }
+
+    pub fn count(&self) -> usize {
+        self.function.len()
+    }
+
+    /// The integral of the function over [0, 1).
+    pub fn integral(&self) -> f64 {
+        self.integral
+    }
+
+    /// Maps a uniform random number to the distribution, returning the sampled value in [0, 1), its pdf and the index of its segment.
+    pub fn sample_continuous(&self, u: f64) -> (f64, f64, usize) {
+        let i = self.find_segment(u);
+        let width = self.cdf[i + 1] - self.cdf[i];
+        let du = if width > 0.0 { (u - self.cdf[i]) / width } else { 0.0 };
+        let x = (i as f64 + du) / self.count() as f64;
+        (x.min(1.0 - f64::EPSILON), self.segment_pdf(i), i)
+    }
+
+    /// Picks one of the segments with probability proportional to its weight, returning it with its probability.
+    pub fn sample_discrete(&self, u: f64) -> (usize, f64) {
+        let i = self.find_segment(u);
+        (i, self.cdf[i + 1] - self.cdf[i])
+    }
+
+    /// The probability of sample_discrete picking segment i.
+    pub fn discrete_pdf(&self, i: usize) -> f64 {
+        self.cdf[i + 1] - self.cdf[i]
+    }
+
+    /// The density of sample_continuous at x.
+    pub fn pdf(&self, x: f64) -> f64 {
+        let i = ((x * self.count() as f64) as usize).min(self.count() - 1);
+        self.segment_pdf(i)
+    }
+
+    fn segment_pdf(&self, i: usize) -> f64 {
+        (self.cdf[i + 1] - self.cdf[i]) * self.count() as f64
+    }
+
+    fn find_segment(&self, u: f64) -> usize {
+        // the last entry of the cdf that is <= u, skipping any empty segments
+        let i = match self.cdf.binary_search_by(|c| c.partial_cmp(&u).unwrap()) {
+            Ok(i) => i,
+            Err(i) => i - 1
+        };
+        let mut i = i.min(self.count() - 1);
+        while i + 1 < self.count() && self.cdf[i + 1] <= u {
+            i += 1;
+        }
+        i
+    }
+}
+
+/// A piecewise constant distribution over [0, 1)^2 built from a grid of weights stored row by row.
+#[derive(Clone, Debug)]
+pub struct Distribution2D {
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D
+}
+
+impl Distribution2D {
+    pub fn new(function: &[f64], width: usize, height: usize) -> Self {
+        assert_eq!(function.len(), width * height);
+        let rows: Vec<Distribution1D> = function.chunks(width).map(|row| Distribution1D::new(row.to_vec())).collect();
+        let marginal = Distribution1D::new(rows.iter().map(|r| r.integral()).collect());
+        Distribution2D{rows, marginal}
+    }
+
+    /// Maps two uniform random numbers to a point (u, v) in the distribution, returning it with its pdf.
+    /// v selects the row, with v = 0 being the first row.
+    pub fn sample(&self, u1: f64, u2: f64) -> ((f64, f64), f64) {
+        let (v, pdf_v, row) = self.marginal.sample_continuous(u2);
+        let (u, pdf_u, _) = self.rows[row].sample_continuous(u1);
+        ((u, v), pdf_u * pdf_v)
+    }
+
+    pub fn pdf(&self, u: f64, v: f64) -> f64 {
+        let row = ((v * self.rows.len() as f64) as usize).min(self.rows.len() - 1);
+        self.marginal.pdf(v) * self.rows[row].pdf(u)
+    }
+}
+
+/// Samples a direction about +z with a pdf of cos(theta) / pi.
+pub fn cosine_hemisphere(u1: f64, u2: f64) -> Vec3 {
+    let r = u1.sqrt();
+    let phi = 2.0 * PI * u2;
+    Vec3::new(r * phi.cos(), r * phi.sin(), (1.0 - u1).max(0.0).sqrt())
+}
+
+/// Uniformly samples a direction on the unit sphere.
+pub fn uniform_sphere(u1: f64, u2: f64) -> Vec3 {
+    let z = 1.0 - 2.0 * u1;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u2;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// Uniformly samples a direction in the cone around +z whose half angle has cosine cos_max.
+pub fn uniform_cone(u1: f64, u2: f64, cos_max: f64) -> Vec3 {
+    let cos_theta = 1.0 - u1 * (1.0 - cos_max);
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * u2;
+    Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+/// The solid angle pdf of uniform_cone.
+pub fn uniform_cone_pdf(cos_max: f64) -> f64 {
+    1.0 / (2.0 * PI * (1.0 - cos_max))
+}
+
+/// Weights a sample from one of two strategies, given the pdfs of both for the sample, using the power heuristic.
+#[inline]
+pub fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b == 0.0 { 0.0 } else { a / (a + b) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distribution_1d() {
+        let distribution = Distribution1D::new(vec![1.0, 0.0, 3.0]);
+        assert!((distribution.integral() - 4.0 / 3.0).abs() < 1e-12);
+        let (x, pdf, i) = distribution.sample_continuous(0.1);
+        assert_eq!(i, 0);
+        assert!((x - 0.4 / 3.0).abs() < 1e-12);
+        assert!((pdf - 0.75).abs() < 1e-12);
+        // the empty segment is never picked
+        let (x, pdf, i) = distribution.sample_continuous(0.25);
+        assert_eq!(i, 2);
+        assert!((x - 2.0 / 3.0).abs() < 1e-12);
+        assert!((pdf - 2.25).abs() < 1e-12);
+        assert_eq!(distribution.sample_discrete(0.5), (2, 0.75));
+        assert_eq!(distribution.pdf(0.5), 0.0);
+    }
+
+    #[test]
+    fn distribution_2d() {
+        let distribution = Distribution2D::new(&[0.0, 0.0, 1.0, 3.0], 2, 2);
+        let ((u, v), pdf) = distribution.sample(0.5, 0.5);
+        assert!(v >= 0.5 && u >= 0.5);
+        assert!((pdf - 3.0).abs() < 1e-12);
+        assert!((distribution.pdf(u, v) - pdf).abs() < 1e-12);
+        assert_eq!(distribution.pdf(0.2, 0.2), 0.0);
+    }
+}
@@ -0,0 +1,261 @@
+//! An analytic daylight sky following Preetham, Shirley and Smits, "A Practical Analytic Model for Daylight".
+
+use super::Vec3;
+use super::ray::Ray;
+use super::renderer::Background;
+use super::light::DirectionalLight;
+use super::sampling::{uniform_cone, uniform_cone_pdf};
+use super::util::{drand48, orthonormal_basis, xyz_to_linear_srgb, degrees_to_radians};
+use std::f64::consts::PI;
+
+/// Coefficients of the Perez sky luminance distribution for one of Y, x or y.
+#[derive(Clone, Copy, Debug)]
+struct Perez {
+    a: f64,
+    b: f64,
+    c: f64,
+    d: f64,
+    e: f64
+}
+
+impl Perez {
+    /// theta is the angle of the view direction from the zenith and gamma its angle from the sun.
+    fn eval(&self, cos_theta: f64, gamma: f64) -> f64 {
+        let cos_gamma = gamma.cos();
+        (1.0 + self.a * (self.b / cos_theta).exp()) * (1.0 + self.c * (self.d * gamma).exp() + self.e * cos_gamma * cos_gamma)
+    }
+}
+
+/// A clear sky lit by the sun, with +y up. Directions below the horizon see a diffuse ground lit by the sky and sun.
+/// Radiance is in kcd/m^2 scaled by the intensity, so an intensity of 1000 with a PhysicalCamera's exposure gives physical units.
+/// The sun disk is part of the background and is what sample picks, so it acts as a directional light with a small angular size.
+pub struct PhysicalSky {
+    sun_direction: Vec3,
+    turbidity: f64,
+    ground_albedo: Vec3,
+    intensity: f64,
+    sun_cos_max: f64,
+    perez: [Perez; 3],
+    /// Yxy at the zenith divided by the Perez function there, so any direction's Yxy is its Perez function times this
+    zenith: [f64; 3],
+    sun_radiance: Vec3,
+    ground_radiance: Vec3
+}
+
+impl PhysicalSky {
+    /// Creates a sky with the sun towards sun_direction, turbidity between 2 (very clear) and 10 (hazy)
+    /// and the reflectance of the ground.
+    pub fn new(sun_direction: Vec3, turbidity: f64, ground_albedo: Vec3) -> Self {
+        assert!((1.7..=10.0).contains(&turbidity), "The sky model is only valid for turbidity between 1.7 and 10");
+        let t = turbidity;
+        let perez = [
+            Perez{a: 0.1787 * t - 1.4630, b: -0.3554 * t + 0.4275, c: -0.0227 * t + 5.3251, d: 0.1206 * t - 2.5771, e: -0.0670 * t + 0.3703},
+            Perez{a: -0.0193 * t - 0.2592, b: -0.0665 * t + 0.0008, c: -0.0004 * t + 0.2125, d: -0.0641 * t - 0.8989, e: -0.0033 * t + 0.0452},
+            Perez{a: -0.0167 * t - 0.2608, b: -0.0950 * t + 0.0092, c: -0.0079 * t + 0.2102, d: -0.0441 * t - 1.6537, e: -0.0109 * t + 0.0529}
+        ];
+        let mut sky = PhysicalSky{
+            sun_direction: sun_direction.normalize(),
+            turbidity,
+            ground_albedo,
+            intensity: 1.0,
+            // the sun's angular radius is about 0.27 degrees
+            sun_cos_max: degrees_to_radians(0.267).cos(),
+            perez,
+            zenith: [0.0; 3],
+            sun_radiance: Vec3::zero(),
+            ground_radiance: Vec3::zero()
+        };
+        sky.update();
+        sky
+    }
+
+    pub fn sun_direction(&self) -> Vec3 {
+        self.sun_direction
+    }
+
+    pub fn turbidity(&self) -> f64 {
+        self.turbidity
+    }
+
+    /// Scales the radiance of the sky, sun and ground.
+    pub fn set_intensity(&mut self, intensity: f64) {
+        self.intensity = intensity;
+    }
+
+    /// Sets the angular radius of the sun disk in radians, a larger sun gives softer shadows.
+    pub fn set_sun_size(&mut self, angular_radius: f64) {
+        assert!(angular_radius > 0.0 && angular_radius < PI / 2.0);
+        self.sun_cos_max = angular_radius.cos();
+        self.update();
+    }
+
+    pub fn sun_angular_radius(&self) -> f64 {
+        self.sun_cos_max.acos()
+    }
+
+    /// The radiance of the sun disk, which is zero once the sun has set.
+    pub fn sun_radiance(&self) -> Vec3 {
+        self.intensity * self.sun_radiance
+    }
+
+    /// The sun as a directional light with the same irradiance, for lighting without the sky or when a hard shadow is wanted.
+    pub fn sun_light(&self) -> DirectionalLight {
+        DirectionalLight::new(-self.sun_direction, self.sun_radiance() / uniform_cone_pdf(self.sun_cos_max))
+    }
+
+    /// The radiance of the sky in a direction above the horizon, not including the sun disk.
+    pub fn sky_radiance(&self, direction: Vec3) -> Vec3 {
+        self.intensity * self.unscaled_sky_radiance(direction)
+    }
+
+    fn unscaled_sky_radiance(&self, direction: Vec3) -> Vec3 {
+        let direction = direction.normalize();
+        // the model breaks down at the horizon, so clamp just above it
+        let cos_theta = direction.y().max(0.001);
+        let gamma = Vec3::dot(direction, self.sun_direction).clamp(-1.0, 1.0).acos();
+        let big_y = self.zenith[0] * self.perez[0].eval(cos_theta, gamma);
+        let x = self.zenith[1] * self.perez[1].eval(cos_theta, gamma);
+        let y = self.zenith[2] * self.perez[2].eval(cos_theta, gamma);
+        yxy_to_rgb(big_y, x, y)
+    }
+
+    fn sun_zenith_angle(&self) -> f64 {
+        // the sky model isn't defined for the sun below the horizon, so it's kept at the horizon
+        self.sun_direction.y().clamp(0.0, 1.0).acos().min(PI / 2.0 - 0.001)
+    }
+
+    /// Recomputes the zenith values, sun and ground after the parameters change.
+    fn update(&mut self) {
+        let t = self.turbidity;
+        let theta_s = self.sun_zenith_angle();
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let zenith_chromaticity = |m: [[f64; 4]; 3]| {
+            let thetas = [theta_s.powi(3), theta_s * theta_s, theta_s, 1.0];
+            let ts = [t * t, t, 1.0];
+            let mut value = 0.0;
+            for (row, ti) in m.iter().zip(ts.iter()) {
+                for (coefficient, thetai) in row.iter().zip(thetas.iter()) {
+                    value += ti * coefficient * thetai;
+                }
+            }
+            value
+        };
+        let zenith_x = zenith_chromaticity([[0.00166, -0.00375, 0.00209, 0.0], [-0.02903, 0.06377, -0.03202, 0.00394], [0.11693, -0.21196, 0.06052, 0.25886]]);
+        let zenith_y = zenith_chromaticity([[0.00275, -0.00610, 0.00317, 0.0], [-0.04214, 0.08970, -0.04153, 0.00516], [0.15346, -0.26756, 0.06670, 0.26688]]);
+        let zenith = [zenith_luminance, zenith_x, zenith_y];
+        for ((scaled, value), perez) in self.zenith.iter_mut().zip(zenith.iter()).zip(self.perez.iter()) {
+            *scaled = value / perez.eval(1.0, theta_s);
+        }
+
+        self.sun_radiance = if self.sun_direction.y() > 0.0 { sun_radiance(self.sun_direction.y(), t) } else { Vec3::zero() };
+
+        // the ground is lambertian and lit by the sun and the upper hemisphere of the sky, integrated numerically
+        const STEPS: usize = 32;
+        let mut irradiance = Vec3::zero();
+        for i in 0..STEPS {
+            let theta = 0.5 * PI * (i as f64 + 0.5) / STEPS as f64;
+            for j in 0..4 * STEPS {
+                let phi = 2.0 * PI * (j as f64 + 0.5) / (4 * STEPS) as f64;
+                let direction = Vec3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin());
+                let solid_angle = theta.sin() * (0.5 * PI / STEPS as f64) * (2.0 * PI / (4 * STEPS) as f64);
+                irradiance += self.unscaled_sky_radiance(direction) * theta.cos() * solid_angle;
+            }
+        }
+        irradiance += self.sun_radiance * self.sun_direction.y().max(0.0) / uniform_cone_pdf(self.sun_cos_max);
+        self.ground_radiance = self.ground_albedo * irradiance / PI;
+    }
+}
+
+/// Converts luminance and chromaticity to linear sRGB.
+fn yxy_to_rgb(big_y: f64, x: f64, y: f64) -> Vec3 {
+    if y <= 0.0 {
+        return Vec3::zero();
+    }
+    xyz_to_linear_srgb(Vec3::new(x / y * big_y, big_y, (1.0 - x - y) / y * big_y))
+}
+
+/// The radiance of the sun disk in kcd/m^2 after passing through the atmosphere, given the cosine of its zenith angle.
+fn sun_radiance(cos_theta: f64, turbidity: f64) -> Vec3 {
+    // relative optical air mass, Kasten and Young
+    let zenith_degrees = cos_theta.acos().to_degrees();
+    let air_mass = 1.0 / (cos_theta + 0.50572 * (96.07995 - zenith_degrees).powf(-1.6364));
+    // rayleigh and aerosol (Angstrom, with beta from turbidity as in Preetham et al) optical depths at red, green and blue wavelengths in micrometers
+    let beta = 0.04608 * turbidity - 0.04586;
+    let transmittance = |wavelength: f64| {
+        let rayleigh = 0.008735 * wavelength.powf(-4.08);
+        let aerosol = beta * wavelength.powf(-1.3);
+        (-air_mass * (rayleigh + aerosol)).exp()
+    };
+    // the sun's luminance outside the atmosphere is about 1.6e9 cd/m^2
+    1.6e6 * Vec3::new(transmittance(0.68), transmittance(0.55), transmittance(0.44))
+}
+
+impl Background for PhysicalSky {
+    fn get(&self, ray: Ray) -> Vec3 {
+        let direction = ray.direction.normalize();
+        if direction.y() < 0.0 {
+            return self.intensity * self.ground_radiance;
+        }
+        let mut radiance = self.sky_radiance(direction);
+        if Vec3::dot(direction, self.sun_direction) >= self.sun_cos_max {
+            radiance += self.sun_radiance();
+        }
+        radiance
+    }
+
+    fn sample(&self) -> Option<(Vec3, Vec3, f64)> {
+        if self.sun_direction.y() <= 0.0 {
+            return None;
+        }
+        let local = uniform_cone(drand48(), drand48(), self.sun_cos_max);
+        let (s, t) = orthonormal_basis(self.sun_direction);
+        let direction = local.x() * s + local.y() * t + local.z() * self.sun_direction;
+        let radiance = self.get(Ray::new(Vec3::zero(), direction, 0.0));
+        Some((direction, radiance, uniform_cone_pdf(self.sun_cos_max)))
+    }
+
+    fn pdf(&self, direction: Vec3) -> f64 {
+        if self.sun_direction.y() > 0.0 && Vec3::dot(direction.normalize(), self.sun_direction) >= self.sun_cos_max {
+            uniform_cone_pdf(self.sun_cos_max)
+        }
+        else {
+            0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytrace::util::luminance;
+
+    #[test]
+    fn zenith_luminance() {
+        // turbidity 2 with the sun at 45 degrees gives a zenith luminance of about 4 kcd/m^2
+        let sky = PhysicalSky::new(Vec3::new(1.0, 1.0, 0.0), 2.0, Vec3::new(0.3, 0.3, 0.3));
+        let zenith = luminance(sky.sky_radiance(Vec3::unit_y()));
+        assert!(zenith > 3.0 && zenith < 5.0, "zenith luminance {}", zenith);
+        // the sky is brighter around the sun than opposite it
+        let towards = sky.sky_radiance(Vec3::new(1.0, 0.8, 0.0));
+        let away = sky.sky_radiance(Vec3::new(-1.0, 0.8, 0.0));
+        assert!(luminance(towards) > luminance(away));
+        // and bluer at the zenith than the horizon
+        assert!(sky.sky_radiance(Vec3::unit_y()).z() > sky.sky_radiance(Vec3::unit_y()).x());
+    }
+
+    #[test]
+    fn sample_sun() {
+        let sky = PhysicalSky::new(Vec3::new(0.0, 1.0, -1.0), 3.0, Vec3::new(0.3, 0.3, 0.3));
+        for _ in 0..100 {
+            let (direction, radiance, pdf) = sky.sample().unwrap();
+            assert!(Vec3::dot(direction, sky.sun_direction()) >= sky.sun_cos_max - 1e-12);
+            assert_eq!(pdf, sky.pdf(direction));
+            assert!(luminance(radiance) > luminance(sky.sun_radiance()));
+        }
+        assert_eq!(sky.pdf(Vec3::unit_y()), 0.0);
+        // a set sun can't be sampled
+        let night = PhysicalSky::new(Vec3::new(0.0, -1.0, -1.0), 3.0, Vec3::new(0.3, 0.3, 0.3));
+        assert!(night.sample().is_none());
+    }
+}
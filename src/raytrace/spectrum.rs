@@ -0,0 +1,264 @@
+//! Spectral quantities and their conversion to color.
+
+use super::Vec3;
+use super::util::xyz_to_linear_srgb;
+
+/// Piecewise gaussian used by the color matching function fit, with different widths either side of the mean.
+fn gaussian(x: f64, mean: f64, sigma_low: f64, sigma_high: f64) -> f64 {
+    let t = (x - mean) / if x < mean { sigma_low } else { sigma_high };
+    (-0.5 * t * t).exp()
+}
+
+/// The CIE 1931 2 degree color matching functions at a wavelength in nanometers, using the multi-lobe fit from
+/// Wyman, Sloan and Shirley, "Simple Analytic Approximations to the CIE XYZ Color Matching Functions".
+pub fn cie_xyz(wavelength: f64) -> Vec3 {
+    Vec3::new(
+        1.056 * gaussian(wavelength, 599.8, 37.9, 31.0) + 0.362 * gaussian(wavelength, 442.0, 16.0, 26.7) - 0.065 * gaussian(wavelength, 501.1, 20.4, 26.2),
+        0.821 * gaussian(wavelength, 568.8, 46.9, 40.5) + 0.286 * gaussian(wavelength, 530.9, 16.3, 31.1),
+        1.217 * gaussian(wavelength, 437.0, 11.8, 36.0) + 0.681 * gaussian(wavelength, 459.0, 26.0, 13.8)
+    )
+}
+
+/// The visible range of wavelengths in nanometers.
+pub const WAVELENGTH_MIN: f64 = 360.0;
+pub const WAVELENGTH_MAX: f64 = 830.0;
+
+/// Spectral radiance of a blackbody at a wavelength in nanometers and a temperature in kelvin, from Planck's law, in W/(m^2 sr m).
+pub fn blackbody(wavelength: f64, temperature: f64) -> f64 {
+    const C: f64 = 299_792_458.0;
+    const H: f64 = 6.626_070_15e-34;
+    const KB: f64 = 1.380_649e-23;
+    let l = wavelength * 1e-9;
+    2.0 * H * C * C / (l.powi(5) * ((H * C / (l * KB * temperature)).exp() - 1.0))
+}
+
+/// The linear sRGB color of a blackbody at a temperature in kelvin, normalized to a luminance of 1.
+/// Colors outside of the sRGB gamut, as for very low temperatures, are clipped.
+pub fn blackbody_color(temperature: f64) -> Vec3 {
+    assert!(temperature > 0.0, "Blackbody temperature must be positive");
+    let mut xyz = Vec3::zero();
+    let mut wavelength = WAVELENGTH_MIN;
+    while wavelength <= WAVELENGTH_MAX {
+        xyz += blackbody(wavelength, temperature) * cie_xyz(wavelength);
+        wavelength += 1.0;
+    }
+    let rgb = xyz_to_linear_srgb(xyz / xyz.y());
+    Vec3::new(rgb.x().max(0.0), rgb.y().max(0.0), rgb.z().max(0.0))
+}
+
+/// The number of wavelengths traced along each path.
+pub const WAVELENGTH_SAMPLES: usize = 3;
+
+/// The wavelength in nanometers that indices of refraction are usually quoted at, the sodium D line.
+pub const D_LINE: f64 = 589.3;
+
+/// Wavelengths traced together along a path, with values for them carried in the x, y and z of a Vec3.
+/// The first is the hero wavelength, which picks directions when scattering depends on wavelength.
+/// See Wilkie et al, "Hero Wavelength Spectral Sampling".
+#[derive(Clone, Copy, Debug)]
+pub struct SampledWavelengths {
+    pub lambda: [f64; WAVELENGTH_SAMPLES],
+    pub pdf: [f64; WAVELENGTH_SAMPLES]
+}
+
+impl SampledWavelengths {
+    /// Picks a hero wavelength with a uniform random number and spaces the others evenly across the visible range from it.
+    pub fn sample(u: f64) -> Self {
+        let range = WAVELENGTH_MAX - WAVELENGTH_MIN;
+        let mut lambda = [0.0; WAVELENGTH_SAMPLES];
+        for (i, l) in lambda.iter_mut().enumerate() {
+            *l = WAVELENGTH_MIN + range * (u + i as f64 / WAVELENGTH_SAMPLES as f64).fract();
+        }
+        SampledWavelengths{lambda, pdf: [1.0 / range; WAVELENGTH_SAMPLES]}
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    /// Drops all but the hero wavelength, for when a path takes a direction only the hero wavelength could have.
+    pub fn terminate_secondary(&mut self) {
+        if self.pdf[1] == 0.0 {
+            return;
+        }
+        for pdf in self.pdf.iter_mut().skip(1) {
+            *pdf = 0.0;
+        }
+        self.pdf[0] /= WAVELENGTH_SAMPLES as f64;
+    }
+}
+
+/// Converts between RGB colors and spectra, both turning the colors of textures, materials and lights into spectra and
+/// turning the spectral radiance arriving at the film back into RGB.
+///
+/// Colors are upsampled linearly, to blends of three smooth spectra for red, green and blue that add up to 1. This keeps
+/// white flat and round trips every color, though very saturated ones are clipped where their spectrum would go negative.
+/// The film is white balanced so a flat spectrum is RGB white, matching renders without spectral mode.
+pub struct RgbSpectrum {
+    /// the inverse of the matrix taking the weights of the basis spectra to RGB
+    to_basis: [Vec3; 3],
+    white_balance: Vec3,
+    /// the integral of the y color matching function, which normalizes luminance
+    y_integral: f64
+}
+
+impl RgbSpectrum {
+    pub fn new() -> Self {
+        let mut xyz = Vec3::zero();
+        let mut columns = [Vec3::zero(); 3];
+        let mut wavelength = WAVELENGTH_MIN;
+        while wavelength <= WAVELENGTH_MAX {
+            let cmf = cie_xyz(wavelength);
+            let basis = Self::basis(wavelength);
+            xyz += cmf;
+            for (column, weight) in columns.iter_mut().zip(basis.elements().iter()) {
+                *column += *weight * cmf;
+            }
+            wavelength += 1.0;
+        }
+        let y_integral = xyz.y();
+        let white_balance = xyz_to_linear_srgb(xyz / y_integral);
+        let columns = [0, 1, 2].map(|i| xyz_to_linear_srgb(columns[i] / y_integral) / white_balance);
+        RgbSpectrum{to_basis: invert([columns[0], columns[1], columns[2]]), white_balance, y_integral}
+    }
+
+    /// The red, green and blue basis spectra at a wavelength.
+    fn basis(wavelength: f64) -> Vec3 {
+        let smoothstep = |edge0: f64, edge1: f64| {
+            let t = ((wavelength - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+            t * t * (3.0 - 2.0 * t)
+        };
+        let red = smoothstep(570.0, 610.0);
+        let blue = 1.0 - smoothstep(470.0, 510.0);
+        Vec3::new(red, 1.0 - red - blue, blue)
+    }
+
+    /// The value at a wavelength of the spectrum for an RGB color.
+    pub fn upsample(&self, rgb: Vec3, wavelength: f64) -> f64 {
+        let weights = self.to_basis[0] * rgb.x() + self.to_basis[1] * rgb.y() + self.to_basis[2] * rgb.z();
+        Vec3::dot(weights, Self::basis(wavelength)).max(0.0)
+    }
+
+    /// The values of the spectrum for an RGB color at each of the sampled wavelengths.
+    pub fn upsample_sampled(&self, rgb: Vec3, wavelengths: &SampledWavelengths) -> Vec3 {
+        let [l0, l1, l2] = wavelengths.lambda;
+        Vec3::new(self.upsample(rgb, l0), self.upsample(rgb, l1), self.upsample(rgb, l2))
+    }
+
+    /// Estimates the RGB color of spectral radiance from its values at the sampled wavelengths.
+    pub fn to_rgb(&self, values: Vec3, wavelengths: &SampledWavelengths) -> Vec3 {
+        let mut xyz = Vec3::zero();
+        for ((value, lambda), pdf) in values.elements().iter().zip(wavelengths.lambda.iter()).zip(wavelengths.pdf.iter()) {
+            if *pdf > 0.0 {
+                xyz += *value * cie_xyz(*lambda) / *pdf;
+            }
+        }
+        xyz_to_linear_srgb(xyz / (WAVELENGTH_SAMPLES as f64 * self.y_integral)) / self.white_balance
+    }
+}
+
+impl Default for RgbSpectrum {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Inverts a 3x3 matrix given as its columns, returning the columns of the inverse.
+fn invert(m: [Vec3; 3]) -> [Vec3; 3] {
+    // the rows of the inverse are the cross products of pairs of columns over the determinant
+    let determinant = Vec3::dot(m[0], Vec3::cross(m[1], m[2]));
+    let rows = [Vec3::cross(m[1], m[2]), Vec3::cross(m[2], m[0]), Vec3::cross(m[0], m[1])].map(|r| r / determinant);
+    [Vec3::new(rows[0].x(), rows[1].x(), rows[2].x()), Vec3::new(rows[0].y(), rows[1].y(), rows[2].y()), Vec3::new(rows[0].z(), rows[1].z(), rows[2].z())]
+}
+
+/// How the index of refraction of a dielectric changes with wavelength, which spreads white light into colors.
+#[derive(Clone, Copy, Debug)]
+pub enum Dispersion {
+    /// n = a + b / wavelength^2 with the wavelength in micrometers, fine for most glasses across the visible range
+    Cauchy{a: f64, b: f64},
+    /// n^2 = 1 + sum of b wavelength^2 / (wavelength^2 - c) with the wavelength in micrometers, as glass catalogs give
+    Sellmeier{b: [f64; 3], c: [f64; 3]}
+}
+
+impl Dispersion {
+    /// Schott N-BK7, a common crown glass.
+    pub fn bk7() -> Self {
+        Dispersion::Sellmeier{b: [1.039_612_12, 0.231_792_344, 1.010_469_45], c: [0.006_000_698_67, 0.020_017_914_4, 103.560_653]}
+    }
+
+    /// Schott SF11, a dense flint glass with strong dispersion.
+    pub fn sf11() -> Self {
+        Dispersion::Sellmeier{b: [1.737_596_95, 0.313_747_346, 1.898_781_01], c: [0.013_188_707, 0.062_306_814_2, 155.236_29]}
+    }
+
+    /// The index of refraction at a wavelength in nanometers.
+    pub fn refractive_index(&self, wavelength: f64) -> f64 {
+        let l2 = (wavelength * 1e-3) * (wavelength * 1e-3);
+        match self {
+            Dispersion::Cauchy{a, b} => a + b / l2,
+            Dispersion::Sellmeier{b, c} => {
+                (1.0 + b.iter().zip(c.iter()).map(|(b, c)| b * l2 / (l2 - c)).sum::<f64>()).sqrt()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blackbody_colors() {
+        // around 6500K is close to the sRGB white point
+        let white = blackbody_color(6504.0);
+        assert!((white.x() - 1.0).abs() < 0.1 && (white.y() - 1.0).abs() < 0.1 && (white.z() - 1.0).abs() < 0.1, "{:?}", white);
+        let warm = blackbody_color(2700.0);
+        assert!(warm.x() > warm.y() && warm.y() > warm.z());
+        let cool = blackbody_color(12000.0);
+        assert!(cool.z() > cool.y() && cool.y() > cool.x());
+        // Wien's displacement law puts the peak of a 5000K blackbody near 580nm
+        assert!(blackbody(580.0, 5000.0) > blackbody(480.0, 5000.0) && blackbody(580.0, 5000.0) > blackbody(680.0, 5000.0));
+    }
+
+    #[test]
+    fn rgb_round_trip() {
+        let spectrum = RgbSpectrum::new();
+        for &rgb in [Vec3::new(1.0, 1.0, 1.0), Vec3::new(0.5, 0.4, 0.3), Vec3::new(0.2, 0.3, 0.6), Vec3::new(2.0, 1.5, 1.0)].iter() {
+            // integrate the upsampled spectrum, a wavelength at a time
+            let mut xyz = Vec3::zero();
+            let mut wavelength = WAVELENGTH_MIN;
+            while wavelength <= WAVELENGTH_MAX {
+                xyz += spectrum.upsample(rgb, wavelength) * cie_xyz(wavelength);
+                wavelength += 1.0;
+            }
+            let back = xyz_to_linear_srgb(xyz / spectrum.y_integral) / spectrum.white_balance;
+            assert!((back - rgb).length() < 1e-3, "{:?} {:?}", rgb, back);
+        }
+        // white is flat
+        assert!((spectrum.upsample(Vec3::new(1.0, 1.0, 1.0), 400.0) - 1.0).abs() < 1e-9);
+        assert!((spectrum.upsample(Vec3::new(1.0, 1.0, 1.0), 700.0) - 1.0).abs() < 1e-9);
+
+        // the film estimate converges to the color, including after dropping secondary wavelengths
+        let rgb = Vec3::new(0.5, 0.4, 0.3);
+        let n = 20000;
+        let mut total = Vec3::zero();
+        for i in 0..n {
+            let mut wavelengths = SampledWavelengths::sample((i as f64 + 0.5) / n as f64);
+            if i % 2 == 0 {
+                wavelengths.terminate_secondary();
+            }
+            total += spectrum.to_rgb(spectrum.upsample_sampled(rgb, &wavelengths), &wavelengths);
+        }
+        assert!((total / n as f64 - rgb).length() < 0.01, "{:?}", total / n as f64);
+    }
+
+    #[test]
+    fn dispersion() {
+        let bk7 = Dispersion::bk7();
+        assert!((bk7.refractive_index(D_LINE) - 1.5168).abs() < 1e-3);
+        assert!(bk7.refractive_index(486.1) > bk7.refractive_index(656.3));
+        assert!(Dispersion::sf11().refractive_index(D_LINE) > 1.7);
+        let cauchy = Dispersion::Cauchy{a: 1.5046, b: 0.00420};
+        assert!((cauchy.refractive_index(D_LINE) - 1.5167).abs() < 1e-3);
+    }
+}
@@ -4,6 +4,7 @@ use super::ray::Ray;
 use super::hittable::{Hittable, HitRecord};
 use super::material::Material;
 use super::aabb::AABB;
+use super::util::time_fraction;
 
 pub struct Sphere {
     center: Vec3,
@@ -19,43 +20,7 @@ impl Sphere {
 
 impl Hittable for Sphere {
     fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let oc = r.origin - self.center;
-        let a = Vec3::dot(r.direction, r.direction);
-        let b = Vec3::dot(oc, r.direction);
-        let c = Vec3::dot(oc, oc) - self.radius * self.radius;
-        let discriminant = b * b - a * c;
-        // the point is "nudged" along the normal to account for precission error to avoid artifacts in reflection
-        if discriminant > 0.0 {
-            let temp = (-b - f64::sqrt(discriminant)) / a;
-            if temp < t_max && temp > t_min {
-                let p = r.point_at_parameter(temp);
-                let normal = (p - self.center) / self.radius;
-                let (u,v) = get_sphere_uv((p - self.center) / self.radius);
-                return Some(HitRecord{
-                    t: temp,
-                    p: p /* + super::EPSILON * normal */,
-                    normal: normal,
-                    material: Arc::clone(&self.material),
-                    u,
-                    v
-                });
-            }
-            let temp = (-b + f64::sqrt(discriminant)) / a;
-            if temp < t_max && temp > t_min {
-                let p = r.point_at_parameter(temp);
-                let normal = (p - self.center) / self.radius;
-                let (u,v) = get_sphere_uv((p - self.center) / self.radius);
-                return Some(HitRecord{
-                    t: temp,
-                    p: p /* + super::EPSILON * normal */,
-                    normal: normal,
-                    material: Arc::clone(&self.material),
-                    u,
-                    v
-                });
-            }
-        }
-        None
+        hit_sphere(self.center, self.radius, &self.material, r, t_min, t_max)
     }
 
     fn bounding_box(&self) -> Option<AABB> {
@@ -63,10 +28,86 @@ impl Hittable for Sphere {
     }
 }
 
+/// A sphere that moves linearly from center0 at time0 to center1 at time1.
+pub struct MovingSphere {
+    center0: Vec3,
+    center1: Vec3,
+    time0: f64,
+    time1: f64,
+    radius: f64,
+    material: Arc<dyn Material>
+}
+
+impl MovingSphere {
+    pub fn new(center0: Vec3, center1: Vec3, time0: f64, time1: f64, radius: f64, material: Arc<dyn Material>) -> Self {
+        MovingSphere{center0, center1, time0, time1, radius, material}
+    }
+
+    /// The center at time, which stays at the ends of the motion outside time0 to time1.
+    pub fn center(&self, time: f64) -> Vec3 {
+        self.center0 + time_fraction(time, self.time0, self.time1) * (self.center1 - self.center0)
+    }
+}
+
+impl Hittable for MovingSphere {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        hit_sphere(self.center(r.time), self.radius, &self.material, r, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        let box0 = AABB::new(self.center0 - self.radius, self.center0 + self.radius);
+        let box1 = AABB::new(self.center1 - self.radius, self.center1 + self.radius);
+        Some(AABB::merge(&box0, &box1))
+    }
+}
+
+pub(crate) fn hit_sphere(center: Vec3, radius: f64, material: &Arc<dyn Material>, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    let oc = r.origin - center;
+    let a = Vec3::dot(r.direction, r.direction);
+    let b = Vec3::dot(oc, r.direction);
+    let c = Vec3::dot(oc, oc) - radius * radius;
+    let discriminant = b * b - a * c;
+    // the point is "nudged" along the normal to account for precission error to avoid artifacts in reflection
+    if discriminant > 0.0 {
+        let temp = (-b - f64::sqrt(discriminant)) / a;
+        if temp < t_max && temp > t_min {
+            let p = r.point_at_parameter(temp);
+            let normal = (p - center) / radius;
+            let (u,v) = get_sphere_uv((p - center) / radius);
+            return Some(HitRecord::new(temp, p /* + super::EPSILON * normal */, normal, Arc::clone(material), u, v));
+        }
+        let temp = (-b + f64::sqrt(discriminant)) / a;
+        if temp < t_max && temp > t_min {
+            let p = r.point_at_parameter(temp);
+            let normal = (p - center) / radius;
+            let (u,v) = get_sphere_uv((p - center) / radius);
+            return Some(HitRecord::new(temp, p /* + super::EPSILON * normal */, normal, Arc::clone(material), u, v));
+        }
+    }
+    None
+}
+
 fn get_sphere_uv(p: Vec3) -> (f64, f64) {
     let phi = p.z().atan2(p.x());
     let theta = p.y().asin();
     let u = 1.0 - (phi + std::f64::consts::PI) / (2.0 * std::f64::consts::PI);
     let v = (theta + std::f64::consts::FRAC_PI_2) / std::f64::consts::PI;
     (u, v)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytrace::material::NormalMaterial;
+
+    #[test]
+    fn moving_sphere_stays_in_bounding_box() {
+        let sphere = MovingSphere::new(Vec3::zero(), Vec3::new(2.0, 0.0, 0.0), 0.0, 1.0, 1.0, Arc::new(NormalMaterial::new()));
+        assert_eq!(sphere.center(0.5), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(sphere.center(-1.0), Vec3::zero());
+        assert_eq!(sphere.center(3.0), Vec3::new(2.0, 0.0, 0.0));
+        let bbox = sphere.bounding_box().unwrap();
+        let r = Ray::new(Vec3::new(2.0, 0.0, -5.0), Vec3::unit_z(), 2.0);
+        assert!(bbox.hit(r, 0.0, f64::MAX) && sphere.hit(r, 0.0, f64::MAX).is_some());
+    }
+}
@@ -0,0 +1,168 @@
+//! Subsurface scattering by random walks. Light goes in through the surface of a closed object, scatters around
+//! the dense medium inside it and comes back out somewhere else, which gives skin, wax and marble their soft
+//! translucent look.
+//!
+//! The walk happens when light scatters at the surface, and hands back where it came out, so it doesn't use up the
+//! path tracer's bounces and direct light is sampled at the exit. Inside, the object is a constant medium with the
+//! mean free path and albedo found where the light went in. Distances are sampled for one channel picked at random,
+//! and the others are weighted by the balance heuristic over the channels, so mean free paths that differ a lot
+//! between channels stay cheap.
+
+use super::util::drand48;
+use super::aabb::AABB;
+use super::hittable::{HitRecord, Hittable};
+use super::material::{Material, Lambertian, Isotropic, offset_origin};
+use super::constant_medium::{ConstantMedium, Medium};
+use super::microfacet::Frame;
+use super::sampling::cosine_hemisphere;
+use super::texture::{Texture, ConstantTexture};
+use super::ray::Ray;
+use super::Vec3;
+use std::sync::Arc;
+
+/// Walks longer than this are taken to have been absorbed.
+const MAX_SCATTERS: usize = 4096;
+
+/// The material of a subsurface object's surface, which walks light through the inside of the boundary.
+struct SubsurfaceMaterial {
+    boundary: Arc<dyn Hittable>,
+    mean_free_path: Arc<dyn Texture>,
+    albedo: Arc<dyn Texture>,
+    /// the particles inside, which the albedo is left out of since it's weighted per channel
+    phase_function: Arc<dyn Material>,
+    /// where light comes out, which scatters it out diffusely
+    exit: Arc<dyn Material>
+}
+
+impl Material for SubsurfaceMaterial {
+    fn is_subsurface(&self) -> bool {
+        true
+    }
+
+    fn sample_subsurface(&self, hit_record: &HitRecord, wo: Vec3, time: f64) -> Option<(HitRecord, Vec3)> {
+        // rays from inside only get there by rounding, so the surface is black from inside
+        if Vec3::dot(wo, hit_record.normal) <= 0.0 {
+            return None;
+        }
+        let (u, v, p) = (hit_record.u, hit_record.v, hit_record.p);
+        let extinction = self.mean_free_path.value(u, v, p).elements().map(|mean_free_path| 1.0 / mean_free_path.max(1e-6));
+        let albedo = self.albedo.value(u, v, p).elements().map(|albedo| albedo.clamp(0.0, 1.0));
+        let channel = ((drand48() * 3.0) as usize).min(2);
+        let medium = ConstantMedium::new(self.boundary.clone(), extinction[channel], self.phase_function.clone());
+
+        // the fraction of light making it so far, and the chance of the walk so far when tracking each channel,
+        // both kept relative to the chance for the channel being tracked so they don't underflow
+        let mut throughput = [1.0; 3];
+        let mut pdfs = [1.0; 3];
+        // takes a step of the walk into account, returning the weight of the light so far
+        let mut step = |distance: f64, scattered: bool| {
+            let pdf = |c: usize| if scattered { extinction[c] * (-extinction[c] * distance).exp() } else { (-extinction[c] * distance).exp() };
+            let chance = pdf(channel);
+            for c in 0..3 {
+                throughput[c] *= if scattered { albedo[c] * pdf(c) } else { pdf(c) } / chance;
+                pdfs[c] *= pdf(c) / chance;
+            }
+            Vec3::new(throughput[0], throughput[1], throughput[2]) / ((pdfs[0] + pdfs[1] + pdfs[2]) / 3.0)
+        };
+
+        let inwards = Frame::new(-hit_record.normal).to_world(cosine_hemisphere(drand48(), drand48()));
+        let mut r = Ray::new(offset_origin(hit_record, inwards), inwards, time);
+        for _ in 0..MAX_SCATTERS {
+            let surface = self.boundary.hit(r, 0.0, f64::MAX)?;
+            match medium.sample_collision(r, 0.0, surface.t) {
+                Some(collision) => {
+                    if step(collision.t * r.direction.length(), true).is_zero_length() {
+                        return None;
+                    }
+                    let sample = collision.material.sample(&collision, -r.direction, [drand48(), drand48(), drand48()])?;
+                    r = Ray::new(collision.p, sample.wi, time);
+                }
+                None => {
+                    let weight = step(surface.t * r.direction.length(), false);
+                    return Some((HitRecord{material: self.exit.clone(), wavelength: hit_record.wavelength, ..surface}, weight));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// A closed object that scatters light below its surface. The mean free path is how far light of each channel
+/// goes between particles inside, and the albedo how much of it they scatter rather than absorb. Both are looked up
+/// where light goes in, and mean free paths must be more than zero. The object's normals must point outwards.
+pub struct Subsurface {
+    boundary: Arc<dyn Hittable>,
+    material: Arc<dyn Material>
+}
+
+impl Subsurface {
+    pub fn new(boundary: Arc<dyn Hittable>, mean_free_path: Arc<dyn Texture>, albedo: Arc<dyn Texture>) -> Self {
+        let white = Arc::new(ConstantTexture::scalar(1.0));
+        let material = Arc::new(SubsurfaceMaterial{
+            boundary: boundary.clone(),
+            mean_free_path,
+            albedo,
+            phase_function: Arc::new(Isotropic::new(white.clone())),
+            exit: Arc::new(Lambertian::new(white))
+        });
+        Subsurface{boundary, material}
+    }
+}
+
+impl Hittable for Subsurface {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.boundary.hit(r, t_min, t_max).map(|rec| HitRecord{material: self.material.clone(), ..rec})
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.boundary.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytrace::sphere::Sphere;
+
+    /// The average weight light fired into the object comes back out with.
+    fn reflectance(object: &Subsurface, n: usize) -> Vec3 {
+        let r = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::unit_z(), 0.0);
+        let total = (0..n).fold(Vec3::zero(), |total, _| {
+            let rec = object.hit(r, 0.0, f64::MAX).unwrap();
+            assert!((rec.t - 4.0).abs() < 1e-9 && (rec.p - r.point_at_parameter(rec.t)).length() < 1e-9);
+            match rec.material.sample_subsurface(&rec, -r.direction, r.time) {
+                Some((exit, weight)) => {
+                    assert!((exit.p.length() - 1.0).abs() < 1e-6);
+                    let sample = exit.material.sample(&exit, exit.normal, [drand48(), drand48(), drand48()]).unwrap();
+                    assert!(Vec3::dot(sample.wi, exit.normal) >= 0.0);
+                    total + weight * sample.weight
+                }
+                None => total
+            }
+        });
+        total / n as f64
+    }
+
+    #[test]
+    fn energy() {
+        let grey = Arc::new(Lambertian::new(Arc::new(ConstantTexture::scalar(0.5))));
+        let boundary: Arc<dyn Hittable> = Arc::new(Sphere::new(Vec3::zero(), 1.0, grey));
+        let n = 5000;
+
+        // without absorption all the light comes back out
+        let white = Subsurface::new(boundary.clone(), Arc::new(ConstantTexture::scalar(0.05)), Arc::new(ConstantTexture::scalar(1.0)));
+        let total = reflectance(&white, n);
+        assert!((total - Vec3::new(1.0, 1.0, 1.0)).length() < 1e-9, "{:?}", total);
+
+        // each channel comes out as if the whole object had that channel's mean free path
+        let albedo: Arc<dyn Texture> = Arc::new(ConstantTexture::scalar(0.9));
+        let skin = Subsurface::new(boundary.clone(), Arc::new(ConstantTexture::new(Vec3::new(0.3, 0.1, 0.05))), albedo.clone());
+        let total = reflectance(&skin, 4 * n).elements();
+        for (c, &mean_free_path) in [0.3, 0.1, 0.05].iter().enumerate() {
+            let grey = Subsurface::new(boundary.clone(), Arc::new(ConstantTexture::scalar(mean_free_path)), albedo.clone());
+            let expected = reflectance(&grey, n).x();
+            assert!(expected > 0.0 && expected < 1.0);
+            assert!((total[c] - expected).abs() < 0.03, "{:?} {} {}", total, c, expected);
+        }
+    }
+}
@@ -14,6 +14,11 @@ impl ConstantTexture {
     pub fn new(color: Vec3) -> Self {
         ConstantTexture{color}
     }
+
+    /// A gray texture, for material parameters that are a single number.
+    pub fn scalar(value: f64) -> Self {
+        ConstantTexture{color: Vec3::new(value, value, value)}
+    }
 }
 
 impl Texture for ConstantTexture {
@@ -62,6 +67,25 @@ impl Texture for MarbleTexture {
     }
 }
 
+/// Fractal noise between 0 and 1, for densities of clouds and smoke. Features are about 1 / scale across.
+pub struct NoiseTexture {
+    pub scale: f64,
+    fbm: Fbm
+}
+
+impl NoiseTexture {
+    pub fn new(scale: f64) -> Self {
+        NoiseTexture{scale, fbm: Fbm::new()}
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, p: Vec3) -> Vec3 {
+        let value = (0.5 * (1.0 + self.fbm.get((self.scale * p).elements()))).clamp(0.0, 1.0);
+        Vec3::new(value, value, value)
+    }
+}
+
 pub struct ImageTexture {
     data: Vec<u8>,
     width: u32,
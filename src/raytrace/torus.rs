@@ -0,0 +1,102 @@
+use std::sync::Arc;
+use super::Vec3;
+use super::ray::Ray;
+use super::hittable::{Hittable, HitRecord};
+use super::material::Material;
+use super::aabb::AABB;
+use super::disk::azimuth;
+use super::util::polynomial_roots;
+use std::f64::consts::PI;
+
+/// A torus lying in the xz plane around center, whose tube of minor_radius circles the y axis at major_radius.
+pub struct Torus {
+    center: Vec3,
+    major_radius: f64,
+    minor_radius: f64,
+    material: Arc<dyn Material>
+}
+
+impl Torus {
+    pub fn new(center: Vec3, major_radius: f64, minor_radius: f64, material: Arc<dyn Material>) -> Self {
+        Torus{center, major_radius, minor_radius, material}
+    }
+}
+
+impl Hittable for Torus {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (major, minor) = (self.major_radius, self.minor_radius);
+        let (o, d) = (r.origin - self.center, r.direction);
+
+        // only look for hits within the bounding sphere, measuring from where the ray enters it so the
+        // coefficients of the quartic stay small for rays from far away
+        let a = Vec3::dot(d, d);
+        let b = Vec3::dot(o, d);
+        let discriminant = b * b - a * (Vec3::dot(o, o) - (major + minor).powi(2));
+        if discriminant <= 0.0 {
+            return None;
+        }
+        let start = ((-b - discriminant.sqrt()) / a).max(t_min);
+        let end = ((-b + discriminant.sqrt()) / a).min(t_max);
+        if start >= end {
+            return None;
+        }
+        let o = o + start * d;
+
+        // (|p|² - R² - r²)² = 4R²(r² - y²) along the ray
+        let e = Vec3::dot(o, o) - major * major - minor * minor;
+        let f = Vec3::dot(o, d);
+        let four_major2 = 4.0 * major * major;
+        let coefficients = [
+            e * e - four_major2 * (minor * minor - o.y() * o.y()),
+            4.0 * f * e + 2.0 * four_major2 * o.y() * d.y(),
+            2.0 * a * e + 4.0 * f * f + four_major2 * d.y() * d.y(),
+            4.0 * a * f,
+            a * a
+        ];
+        let t = start + polynomial_roots(&coefficients, 0.0, end - start).into_iter().find(|&s| start + s > t_min)?;
+
+        let p = r.point_at_parameter(t);
+        let q = p - self.center;
+        let ring = (q.x() * q.x() + q.z() * q.z()).sqrt();
+        // away from the circle running through the middle of the tube
+        let normal = if ring > 0.0 { q - major / ring * Vec3::new(q.x(), 0.0, q.z()) } else { q };
+        let normal = normal.normalize();
+        let u = azimuth(q.x(), q.z()) / (2.0 * PI);
+        let v = azimuth(ring - major, q.y()) / (2.0 * PI);
+        Some(HitRecord::new(t, p, normal, Arc::clone(&self.material), u, v))
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        let extent = Vec3::new(self.major_radius + self.minor_radius, self.minor_radius, self.major_radius + self.minor_radius);
+        Some(AABB::new(self.center - extent, self.center + extent))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytrace::material::NormalMaterial;
+
+    #[test]
+    fn torus_hit() {
+        let torus = Torus::new(Vec3::zero(), 2.0, 0.5, Arc::new(NormalMaterial::new()));
+        let down = Vec3::new(0.0, -1.0, 0.0);
+        assert!(torus.hit(Ray::new(Vec3::new(0.0, 5.0, 0.0), down, 0.0), 0.0, f64::MAX).is_none());
+
+        let rec = torus.hit(Ray::new(Vec3::new(2.0, 5.0, 0.0), down, 0.0), 0.0, f64::MAX).unwrap();
+        assert!((rec.t - 4.5).abs() < 1e-9 && (rec.v - 0.25).abs() < 1e-9);
+        assert!((rec.normal - Vec3::unit_y()).length() < 1e-9);
+
+        // straight through the middle it crosses the tube twice, and rays from far away are as precise
+        for &distance in &[5.0, 1000.0] {
+            let r = Ray::new(Vec3::new(-distance, 0.0, 0.0), Vec3::unit_x(), 0.0);
+            let rec = torus.hit(r, 0.0, f64::MAX).unwrap();
+            assert!((rec.t - (distance - 2.5)).abs() < 1e-9, "{}", rec.t);
+            assert!((rec.normal - Vec3::new(-1.0, 0.0, 0.0)).length() < 1e-9);
+            let rec = torus.hit(r, rec.t + 0.1, f64::MAX).unwrap();
+            assert!((rec.t - (distance - 1.5)).abs() < 1e-9, "{}", rec.t);
+            assert!((rec.normal - Vec3::unit_x()).length() < 1e-9);
+            assert!(torus.hit(r, distance + 1.6, distance + 2.4).is_none());
+        }
+    }
+}
@@ -1,10 +1,40 @@
 extern crate rand;
 use rand::Rng;
+use std::cell::RefCell;
 
 use super::Vec3;
 
+/// A source of random numbers that drand48 can draw from instead, so an integrator can control the numbers a path uses.
+pub trait RandomStream {
+    /// Returns the next number in [0, 1).
+    fn next(&mut self) -> f64;
+}
+
+thread_local! {
+    static STREAM: RefCell<Option<Box<dyn RandomStream>>> = RefCell::new(None);
+}
+
 pub fn drand48() -> f64 {
-    rand::thread_rng().gen()
+    STREAM.with(|stream| match stream.borrow_mut().as_mut() {
+        Some(stream) => stream.next(),
+        None => rand::thread_rng().gen()
+    })
+}
+
+/// Runs f with drand48 drawing from stream on this thread. The stream must not call drand48 itself.
+pub fn with_random_stream<R>(stream: Box<dyn RandomStream>, f: impl FnOnce() -> R) -> R {
+    /// Puts the previous stream back even if f panics.
+    struct Restore(Option<Box<dyn RandomStream>>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            STREAM.with(|stream| *stream.borrow_mut() = previous);
+        }
+    }
+
+    let _restore = Restore(STREAM.with(|current| current.borrow_mut().replace(stream)));
+    f()
 }
 
 pub fn random_in_unit_sphere() -> Vec3 {
@@ -16,6 +46,14 @@ pub fn random_in_unit_sphere() -> Vec3 {
     p
 }
 
+/// Returns a uniformly distributed direction.
+pub fn random_unit_vector() -> Vec3 {
+    let z = 2.0 * drand48() - 1.0;
+    let phi = 2.0 * std::f64::consts::PI * drand48();
+    let r = (1.0 - z * z).sqrt();
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
 pub fn random_in_unit_disk() -> Vec3 {
     let mut p: Vec3;
     while {
@@ -42,6 +80,77 @@ pub fn approx_equal(a: f64, b: f64) -> bool {
     (a - b).abs() <= std::f64::EPSILON * a.max(b).max(1.0)
 }
 
+/// Returns how far through the interval time0 to time1 the time is, clamped to [0, 1].
+pub fn time_fraction(time: f64, time0: f64, time1: f64) -> f64 {
+    if time1 <= time0 {
+        0.0
+    }
+    else {
+        ((time - time0) / (time1 - time0)).clamp(0.0, 1.0)
+    }
+}
+
+/// The relative luminance of a linear sRGB color.
+#[inline]
+pub fn luminance(color: Vec3) -> f64 {
+    0.2126 * color.x() + 0.7152 * color.y() + 0.0722 * color.z()
+}
+
+/// Converts a CIE XYZ color to linear sRGB.
+pub fn xyz_to_linear_srgb(xyz: Vec3) -> Vec3 {
+    Vec3::new(
+        3.2404542 * xyz.x() - 1.5371385 * xyz.y() - 0.4985314 * xyz.z(),
+        -0.9692660 * xyz.x() + 1.8760108 * xyz.y() + 0.0415560 * xyz.z(),
+        0.0556434 * xyz.x() - 0.2040259 * xyz.y() + 1.0572252 * xyz.z()
+    )
+}
+
+/// Returns two unit vectors that form an orthonormal basis with the unit vector n.
+pub fn orthonormal_basis(n: Vec3) -> (Vec3, Vec3) {
+    // Duff et al, "Building an Orthonormal Basis, Revisited"
+    let sign = 1f64.copysign(n.z());
+    let a = -1.0 / (sign + n.z());
+    let b = n.x() * n.y() * a;
+    (Vec3::new(1.0 + sign * n.x() * n.x() * a, sign * b, -sign * n.x()), Vec3::new(b, sign + n.y() * n.y() * a, -n.y()))
+}
+
+/// The real roots between lo and hi of the polynomial with the given coefficients, lowest power first, in
+/// increasing order. Roots where the polynomial only touches zero without crossing it are missed.
+pub fn polynomial_roots(coefficients: &[f64], lo: f64, hi: f64) -> Vec<f64> {
+    let degree = match coefficients.iter().rposition(|&c| c != 0.0) {
+        Some(degree) => degree,
+        None => return Vec::new()
+    };
+    let evaluate = |x: f64| coefficients[..=degree].iter().rev().fold(0.0, |sum, &c| sum * x + c);
+    if degree == 1 {
+        let root = -coefficients[0] / coefficients[1];
+        return if root >= lo && root <= hi { vec![root] } else { Vec::new() };
+    }
+
+    // the polynomial is monotonic between the roots of its derivative, so each piece crosses zero at most once
+    let derivative: Vec<f64> = coefficients[1..=degree].iter().enumerate().map(|(i, &c)| (i + 1) as f64 * c).collect();
+    let mut ends = vec![lo];
+    ends.extend(polynomial_roots(&derivative, lo, hi));
+    ends.push(hi);
+    let mut roots = Vec::new();
+    for piece in ends.windows(2) {
+        let (mut a, mut b) = (piece[0], piece[1]);
+        let rising = evaluate(a) < 0.0;
+        if rising == (evaluate(b) < 0.0) {
+            continue;
+        }
+        for _ in 0..100 {
+            let middle = 0.5 * (a + b);
+            if middle <= a || middle >= b {
+                break;
+            }
+            if (evaluate(middle) < 0.0) == rising { a = middle } else { b = middle }
+        }
+        roots.push(0.5 * (a + b));
+    }
+    roots
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -50,6 +159,22 @@ mod tests {
         assert!(v > 0.0 && v < 1.0);
     }
 
+    #[test]
+    fn random_stream() {
+        struct Counter(f64);
+
+        impl super::RandomStream for Counter {
+            fn next(&mut self) -> f64 {
+                self.0 += 0.25;
+                self.0
+            }
+        }
+
+        let drawn = super::with_random_stream(Box::new(Counter(0.0)), || (super::drand48(), super::drand48()));
+        assert_eq!(drawn, (0.25, 0.5));
+        assert_ne!(super::drand48(), 0.75);
+    }
+
     #[test]
     fn degrees_to_radians() {
         assert_eq!(super::degrees_to_radians(180.0), 3.141592653589793);
@@ -59,6 +184,29 @@ mod tests {
     fn radians_to_degrees() {
         assert_eq!(super::radians_to_degrees(3.141592653589793), 180.0);
     }
+
+    #[test]
+    fn polynomial_roots() {
+        // (x + 2)(x - 0.5)(x - 1)(x - 3)
+        let coefficients = [-3.0, 8.5, -4.0, -2.5, 1.0];
+        let roots = super::polynomial_roots(&coefficients, -10.0, 10.0);
+        assert_eq!(roots.len(), 4);
+        for (root, expected) in roots.iter().zip([-2.0, 0.5, 1.0, 3.0].iter()) {
+            assert!((root - expected).abs() < 1e-12, "{:?}", roots);
+        }
+        assert_eq!(super::polynomial_roots(&coefficients, 0.0, 2.0).len(), 2);
+        assert!(super::polynomial_roots(&[1.0, 0.0, 1.0], -10.0, 10.0).is_empty());
+    }
+
+    #[test]
+    fn orthonormal_basis() {
+        use super::Vec3;
+        for n in [Vec3::unit_z(), -Vec3::unit_z(), Vec3::new(0.3, -0.4, 0.5).normalize()].iter() {
+            let (s, t) = super::orthonormal_basis(*n);
+            assert!((s.length() - 1.0).abs() < 1e-12 && (t.length() - 1.0).abs() < 1e-12);
+            assert!(Vec3::dot(s, *n).abs() < 1e-12 && Vec3::dot(t, *n).abs() < 1e-12 && Vec3::dot(s, t).abs() < 1e-12);
+        }
+    }
 }
 
 #[repr(align(16))]
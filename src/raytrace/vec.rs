@@ -60,12 +60,12 @@ impl Vec3 {
 
     #[inline]
     pub fn set_y(&mut self, v: f64) {
-        self._x = v;
+        self._y = v;
     }
 
     #[inline]
     pub fn set_z(&mut self, v: f64) {
-        self._x = v;
+        self._z = v;
     }
 
     pub fn length(&self) -> f64 {
@@ -298,6 +298,14 @@ mod tests {
         assert_eq!(v._z, 2.0);
     }
 
+    #[test]
+    fn set() {
+        let mut v = Vec3::new(1.0, 4.0, 2.0);
+        v.set_y(5.0);
+        v.set_z(6.0);
+        assert_eq!(v, Vec3::new(1.0, 5.0, 6.0));
+    }
+
     #[test]
     fn length() {
         let v = Vec3::new(1.0, 4.0, 2.0);
@@ -0,0 +1,368 @@
+//! Grids of voxel values, such as the density of smoke from a simulation, for use as the density of a
+//! heterogeneous medium through a GridTexture.
+//!
+//! Grids are saved and loaded in a simple binary format, all little endian:
+//!
+//! | bytes | contents |
+//! |-------|----------|
+//! | 4 | the magic `VOXL` |
+//! | 12 | the resolution along x, y and z as u32s |
+//! | 4 × x × y × z | the values as f32s, with x changing fastest, then y, then z |
+//!
+//! Headerless 8 bit raw volumes, as many volume datasets come, can be read with DenseGrid::read_raw_u8.
+
+use super::Vec3;
+use super::aabb::AABB;
+use super::texture::Texture;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+const MAGIC: &[u8; 4] = b"VOXL";
+/// The sparse grid stores values in blocks of this many voxels along each side.
+const BLOCK_SIZE: usize = 8;
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// The number of voxels in a grid of the given resolution, which has to have some and not too many to count.
+fn voxel_count(resolution: [usize; 3]) -> io::Result<usize> {
+    if resolution.contains(&0) {
+        return Err(invalid_data("voxel grid is empty"));
+    }
+    resolution[0].checked_mul(resolution[1]).and_then(|n| n.checked_mul(resolution[2])).ok_or_else(|| invalid_data("voxel grid is too large"))
+}
+
+/// Reads exactly length bytes, only allocating as many as the reader actually has.
+fn read_bytes<R: Read>(reader: &mut R, length: usize) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader.take(length as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != length {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "voxel grid data is truncated"));
+    }
+    Ok(bytes)
+}
+
+/// A 3D grid of values, which are taken to be at the centres of the voxels.
+pub trait VoxelGrid: Send + Sync {
+    /// The number of voxels along x, y and z.
+    fn resolution(&self) -> [usize; 3];
+
+    /// The value of the voxel at x, y, z, which must be within the resolution.
+    fn get(&self, x: usize, y: usize, z: usize) -> f64;
+
+    /// The largest value in the grid.
+    fn max_value(&self) -> f64;
+
+    /// Interpolates between the voxels at p, measured in voxels from the grid's corner. Values beyond the outer
+    /// voxel centres are clamped to them, and points outside the grid are 0.
+    fn sample(&self, p: [f64; 3]) -> f64 {
+        let resolution = self.resolution();
+        if (0..3).any(|axis| p[axis] < 0.0 || p[axis] > resolution[axis] as f64) {
+            return 0.0;
+        }
+        let mut index = [[0; 2]; 3];
+        let mut fraction = [0.0; 3];
+        for axis in 0..3 {
+            let x = (p[axis] - 0.5).clamp(0.0, (resolution[axis] - 1) as f64);
+            let i = (x.floor() as usize).min(resolution[axis].saturating_sub(2));
+            index[axis] = [i, (i + 1).min(resolution[axis] - 1)];
+            fraction[axis] = x - i as f64;
+        }
+        let mut value = 0.0;
+        for corner in 0..8 {
+            let (cx, cy, cz) = (corner & 1, (corner >> 1) & 1, corner >> 2);
+            let weight = (if cx == 1 { fraction[0] } else { 1.0 - fraction[0] })
+                * (if cy == 1 { fraction[1] } else { 1.0 - fraction[1] })
+                * (if cz == 1 { fraction[2] } else { 1.0 - fraction[2] });
+            if weight > 0.0 {
+                value += weight * self.get(index[0][cx], index[1][cy], index[2][cz]);
+            }
+        }
+        value
+    }
+}
+
+/// A grid storing every voxel.
+#[derive(Clone, Debug)]
+pub struct DenseGrid {
+    resolution: [usize; 3],
+    data: Vec<f32>
+}
+
+impl DenseGrid {
+    /// Creates a grid from its values, with x changing fastest, then y, then z.
+    pub fn new(resolution: [usize; 3], data: Vec<f32>) -> Self {
+        assert!(resolution.iter().all(|&n| n > 0), "grids need at least one voxel along each axis");
+        assert_eq!(data.len(), resolution[0] * resolution[1] * resolution[2], "grid data does not match its resolution");
+        DenseGrid{resolution, data}
+    }
+
+    /// Creates a grid from a function of the voxel's position, each coordinate going from 0 to 1 across the grid.
+    pub fn from_fn<F: Fn(Vec3) -> f64>(resolution: [usize; 3], f: F) -> Self {
+        let [nx, ny, nz] = resolution;
+        let mut data = Vec::with_capacity(nx * ny * nz);
+        for z in 0..nz {
+            for y in 0..ny {
+                for x in 0..nx {
+                    let p = Vec3::new((x as f64 + 0.5) / nx as f64, (y as f64 + 0.5) / ny as f64, (z as f64 + 0.5) / nz as f64);
+                    data.push(f(p) as f32);
+                }
+            }
+        }
+        DenseGrid::new(resolution, data)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::read(&mut BufReader::new(File::open(path)?))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Reads a grid in the format described at the top of this module.
+    pub fn read<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a voxel grid"));
+        }
+        let mut resolution = [0; 3];
+        for n in resolution.iter_mut() {
+            let mut bytes = [0u8; 4];
+            reader.read_exact(&mut bytes)?;
+            *n = u32::from_le_bytes(bytes) as usize;
+        }
+        let count = voxel_count(resolution)?;
+        let length = count.checked_mul(4).ok_or_else(|| invalid_data("voxel grid is too large"))?;
+        let bytes = read_bytes(reader, length)?;
+        let data = bytes.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect();
+        Ok(DenseGrid::new(resolution, data))
+    }
+
+    /// Writes the grid in the format described at the top of this module.
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        for &n in self.resolution.iter() {
+            writer.write_all(&(n as u32).to_le_bytes())?;
+        }
+        for value in self.data.iter() {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Reads a headerless volume of bytes with x changing fastest, mapping them to values from 0 to 1.
+    pub fn read_raw_u8<R: Read>(reader: &mut R, resolution: [usize; 3]) -> io::Result<Self> {
+        let bytes = read_bytes(reader, voxel_count(resolution)?)?;
+        Ok(DenseGrid::new(resolution, bytes.into_iter().map(|b| b as f32 / 255.0).collect()))
+    }
+
+    /// Writes the values as headerless f32s with z changing fastest, then y, then x. This is the layout of
+    /// OpenVDB's tools::Dense grids, so the values can be copied into one and converted to a VDB with copyFromDense.
+    pub fn write_raw_zyx<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let [nx, ny, nz] = self.resolution;
+        for x in 0..nx {
+            for y in 0..ny {
+                for z in 0..nz {
+                    writer.write_all(&self.data[x + nx * (y + ny * z)].to_le_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl VoxelGrid for DenseGrid {
+    fn resolution(&self) -> [usize; 3] {
+        self.resolution
+    }
+
+    fn get(&self, x: usize, y: usize, z: usize) -> f64 {
+        self.data[x + self.resolution[0] * (y + self.resolution[1] * z)] as f64
+    }
+
+    fn max_value(&self) -> f64 {
+        self.data.iter().fold(0.0f32, |a, &b| a.max(b)) as f64
+    }
+}
+
+/// A grid storing only the blocks of voxels that have values in them, which takes far less memory for smoke and
+/// clouds that fill a small part of their bounds. Voxels in blocks that aren't stored are 0.
+pub struct SparseGrid {
+    resolution: [usize; 3],
+    /// the number of blocks along each axis
+    blocks: [usize; 3],
+    /// the index in data of each block, or None for empty blocks
+    block_indices: Vec<Option<u32>>,
+    data: Vec<[f32; BLOCK_SIZE * BLOCK_SIZE * BLOCK_SIZE]>,
+    max_value: f64
+}
+
+impl SparseGrid {
+    /// Copies a grid, leaving out blocks where no voxel is above threshold.
+    pub fn from_grid(grid: &dyn VoxelGrid, threshold: f64) -> Self {
+        let resolution = grid.resolution();
+        let blocks = [0, 1, 2].map(|axis| resolution[axis].div_ceil(BLOCK_SIZE));
+        let mut block_indices = Vec::with_capacity(blocks[0] * blocks[1] * blocks[2]);
+        let mut data = Vec::new();
+        let mut max_value = 0.0f64;
+        for bz in 0..blocks[2] {
+            for by in 0..blocks[1] {
+                for bx in 0..blocks[0] {
+                    let mut block = [0.0f32; BLOCK_SIZE * BLOCK_SIZE * BLOCK_SIZE];
+                    let mut occupied = false;
+                    for (i, value) in block.iter_mut().enumerate() {
+                        let x = bx * BLOCK_SIZE + i % BLOCK_SIZE;
+                        let y = by * BLOCK_SIZE + (i / BLOCK_SIZE) % BLOCK_SIZE;
+                        let z = bz * BLOCK_SIZE + i / (BLOCK_SIZE * BLOCK_SIZE);
+                        if x < resolution[0] && y < resolution[1] && z < resolution[2] {
+                            let v = grid.get(x, y, z);
+                            occupied |= v > threshold;
+                            *value = v as f32;
+                        }
+                    }
+                    if occupied {
+                        max_value = block.iter().fold(max_value, |a, &b| a.max(b as f64));
+                        block_indices.push(Some(data.len() as u32));
+                        data.push(block);
+                    }
+                    else {
+                        block_indices.push(None);
+                    }
+                }
+            }
+        }
+        SparseGrid{resolution, blocks, block_indices, data, max_value}
+    }
+
+    /// The number of blocks stored, out of all the blocks covering the grid.
+    pub fn occupancy(&self) -> (usize, usize) {
+        (self.data.len(), self.block_indices.len())
+    }
+
+    /// Expands the grid back into every voxel, for saving.
+    pub fn to_dense(&self) -> DenseGrid {
+        let [nx, ny, nz] = self.resolution;
+        DenseGrid::from_fn(self.resolution, |p| {
+            let (x, y, z) = ((p.x() * nx as f64) as usize, (p.y() * ny as f64) as usize, (p.z() * nz as f64) as usize);
+            self.get(x.min(nx - 1), y.min(ny - 1), z.min(nz - 1))
+        })
+    }
+}
+
+impl VoxelGrid for SparseGrid {
+    fn resolution(&self) -> [usize; 3] {
+        self.resolution
+    }
+
+    fn get(&self, x: usize, y: usize, z: usize) -> f64 {
+        let block = x / BLOCK_SIZE + self.blocks[0] * (y / BLOCK_SIZE + self.blocks[1] * (z / BLOCK_SIZE));
+        match self.block_indices[block] {
+            Some(index) => self.data[index as usize][x % BLOCK_SIZE + BLOCK_SIZE * (y % BLOCK_SIZE + BLOCK_SIZE * (z % BLOCK_SIZE))] as f64,
+            None => 0.0
+        }
+    }
+
+    fn max_value(&self) -> f64 {
+        self.max_value
+    }
+}
+
+/// Stretches a grid over a box in the scene, interpolating between its voxels.
+pub struct GridTexture {
+    grid: Arc<dyn VoxelGrid>,
+    bounds: AABB
+}
+
+impl GridTexture {
+    pub fn new(grid: Arc<dyn VoxelGrid>, bounds: AABB) -> Self {
+        GridTexture{grid, bounds}
+    }
+
+    /// The largest value the texture takes, to use as the maximum density of a medium.
+    pub fn max_value(&self) -> f64 {
+        self.grid.max_value()
+    }
+}
+
+impl Texture for GridTexture {
+    fn value(&self, _u: f64, _v: f64, p: Vec3) -> Vec3 {
+        let resolution = self.grid.resolution();
+        let (p, min, max) = (p.elements(), self.bounds.min.elements(), self.bounds.max.elements());
+        let position = [0, 1, 2].map(|axis| (p[axis] - min[axis]) / (max[axis] - min[axis]) * resolution[axis] as f64);
+        let value = self.grid.sample(position);
+        Vec3::new(value, value, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn interpolation() {
+        let grid = DenseGrid::new([2, 1, 1], vec![0.0, 1.0]);
+        assert_eq!(grid.sample([0.5, 0.5, 0.5]), 0.0);
+        assert!((grid.sample([1.0, 0.5, 0.5]) - 0.5).abs() < 1e-12);
+        assert!((grid.sample([1.25, 0.2, 0.9]) - 0.75).abs() < 1e-12);
+        // clamped to the outer centres and 0 outside
+        assert_eq!(grid.sample([1.9, 0.5, 0.5]), 1.0);
+        assert_eq!(grid.sample([2.1, 0.5, 0.5]), 0.0);
+
+        let texture = GridTexture::new(Arc::new(grid), AABB::new(Vec3::new(-1.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0)));
+        assert!((texture.value(0.0, 0.0, Vec3::new(0.0, 0.5, 0.5)).x() - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn sparse_matches_dense() {
+        let dense = DenseGrid::from_fn([20, 13, 9], |p| (0.3 - (p - Vec3::new(0.3, 0.5, 0.5)).length()).max(0.0));
+        let sparse = SparseGrid::from_grid(&dense, 0.0);
+        let (stored, total) = sparse.occupancy();
+        assert!(stored < total);
+        assert_eq!(sparse.max_value(), dense.max_value());
+        for z in 0..9 {
+            for y in 0..13 {
+                for x in 0..20 {
+                    assert_eq!(sparse.get(x, y, z), dense.get(x, y, z));
+                }
+            }
+        }
+        assert_eq!(sparse.to_dense().data, dense.data);
+    }
+
+    #[test]
+    fn read_and_write() {
+        let grid = DenseGrid::from_fn([3, 4, 5], |p| p.x() + 2.0 * p.y() + 4.0 * p.z());
+        let mut bytes = Vec::new();
+        grid.write(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), 16 + 4 * 60);
+        let read = DenseGrid::read(&mut Cursor::new(&bytes)).unwrap();
+        assert_eq!(read.resolution, grid.resolution);
+        assert_eq!(read.data, grid.data);
+        assert!(DenseGrid::read(&mut Cursor::new(&bytes[..100])).is_err());
+        assert!(DenseGrid::read(&mut Cursor::new(b"VOXX")).is_err());
+
+        let mut zyx = Vec::new();
+        grid.write_raw_zyx(&mut zyx).unwrap();
+        let value = |i: usize| f32::from_le_bytes([zyx[4 * i], zyx[4 * i + 1], zyx[4 * i + 2], zyx[4 * i + 3]]) as f64;
+        // x = 1, y = 2, z = 3
+        assert_eq!(value(3 + 5 * (2 + 4)), grid.get(1, 2, 3));
+
+        let raw = DenseGrid::read_raw_u8(&mut Cursor::new(vec![0u8, 255, 51, 0]), [2, 2, 1]).unwrap();
+        assert_eq!(raw.get(1, 0, 0), 1.0);
+        assert!((raw.get(0, 1, 0) - 0.2).abs() < 1e-6);
+        // resolutions that can't hold any voxels, or can't be counted, are rejected instead of panicking
+        for &resolution in &[[0, 2, 2], [usize::MAX, 2, 1]] {
+            let error = DenseGrid::read_raw_u8(&mut Cursor::new(vec![0u8; 4]), resolution).err().unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+        assert!(DenseGrid::read_raw_u8(&mut Cursor::new(vec![0u8; 3]), [2, 2, 1]).is_err());
+    }
+}
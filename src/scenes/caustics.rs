@@ -0,0 +1,49 @@
+use std::sync::Arc;
+use crate::raytrace::hittable::Hittable;
+use crate::raytrace::Vec3;
+use crate::raytrace::sphere::Sphere;
+use crate::raytrace::material::{Lambertian, Dielectric, Metal};
+use crate::raytrace::texture::ConstantTexture;
+use crate::raytrace::camera::PerspectiveCamera;
+use crate::raytrace::light::{Light, SphereLight};
+use crate::raytrace::photon_map::PhotonSettings;
+use crate::raytrace::util::degrees_to_radians;
+
+pub fn camera(aspect: f64) -> PerspectiveCamera {
+    PerspectiveCamera::new(
+        Vec3::new(0.0, 4.0, 10.0),
+        Vec3::new(0.0, 0.8, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        degrees_to_radians(30.0),
+        aspect,
+        0.0,
+        10.0
+    )
+}
+
+fn light() -> SphereLight {
+    SphereLight::new(Vec3::new(-2.0, 6.0, -2.0), 0.2, Vec3::new(400.0, 380.0, 340.0))
+}
+
+/// The lights that are sampled directly and emit photons, the light is also part of the objects.
+pub fn lights() -> Vec<Arc<dyn Light>> {
+    vec![Arc::new(light())]
+}
+
+/// Photons only need gathering from close by, the caustics are small and sharp.
+pub fn photon_settings() -> PhotonSettings {
+    PhotonSettings{photons: 1_000_000, neighbours: 50, radius: 0.2}
+}
+
+/// Glass spheres focusing a small light into bright caustics on the floor, and a mirror sphere reflecting it.
+pub fn generate() -> Vec<Box<dyn Hittable>> {
+    let floor = Arc::new(Lambertian::new(Arc::new(ConstantTexture::new(Vec3::new(0.7, 0.7, 0.7)))));
+    let glass = Arc::new(Dielectric::new(1.5));
+    vec![
+        Box::new(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, floor)),
+        Box::new(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 1.0, glass.clone())),
+        Box::new(Sphere::new(Vec3::new(-2.2, 0.6, 1.0), 0.6, glass)),
+        Box::new(Sphere::new(Vec3::new(2.2, 0.8, -0.5), 0.8, Arc::new(Metal::new(Arc::new(ConstantTexture::new(Vec3::new(0.9, 0.9, 0.9))), 0.0)))),
+        Box::new(light())
+    ]
+}
@@ -0,0 +1,53 @@
+use std::sync::Arc;
+use crate::raytrace::hittable::Hittable;
+use crate::raytrace::Vec3;
+use crate::raytrace::aabb::AABB;
+use crate::raytrace::sphere::Sphere;
+use crate::raytrace::cuboid::Cuboid;
+use crate::raytrace::material::{Lambertian, Isotropic};
+use crate::raytrace::phase::{PhaseMaterial, HenyeyGreenstein};
+use crate::raytrace::texture::{Texture, ConstantTexture, NoiseTexture};
+use crate::raytrace::heterogeneous_medium::HeterogeneousMedium;
+use crate::raytrace::voxel_grid::{DenseGrid, SparseGrid, GridTexture};
+use crate::raytrace::camera::PerspectiveCamera;
+use crate::raytrace::util::degrees_to_radians;
+
+pub fn camera(aspect: f64) -> PerspectiveCamera {
+    PerspectiveCamera::new(
+        Vec3::new(0.0, 2.5, 12.0),
+        Vec3::new(0.0, 1.5, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        degrees_to_radians(30.0),
+        aspect,
+        0.0,
+        10.0
+    )
+}
+
+/// Puffs of cloud over the ground, for lighting with the sky. The one on the left is a sparse voxel grid,
+/// as smoke from a simulation would be loaded, and the one on the right is procedural noise.
+pub fn generate() -> Vec<Box<dyn Hittable>> {
+    let white = Arc::new(ConstantTexture::new(Vec3::new(0.9, 0.9, 0.9)));
+    // water droplets scatter mostly forwards, which brightens the edges of clouds against the sun
+    let droplets = Arc::new(PhaseMaterial::new(Arc::new(HenyeyGreenstein::new(0.8)), white.clone()));
+    let noise = NoiseTexture::new(4.0);
+
+    let boundary = Arc::new(Sphere::new(Vec3::new(1.7, 1.5, 0.0), 1.5, Arc::new(Isotropic::new(white.clone()))));
+    let noise_cloud = HeterogeneousMedium::new(boundary, Arc::new(NoiseTexture::new(4.0)), 2.0, 2.0, droplets.clone());
+
+    // noise thinning out towards the edge of a ball, baked into voxels
+    let bounds = AABB::new(Vec3::new(-3.2, 0.0, -1.5), Vec3::new(-0.2, 3.0, 1.5));
+    let dense = DenseGrid::from_fn([48, 48, 48], |p| {
+        let falloff = (1.0 - 2.0 * (p - Vec3::new(0.5, 0.5, 0.5)).length()).max(0.0);
+        4.0 * falloff * noise.value(0.0, 0.0, 3.0 * p).x()
+    });
+    let density = GridTexture::new(Arc::new(SparseGrid::from_grid(&dense, 0.0)), bounds);
+    let boundary = Arc::new(Cuboid::new(bounds.min, bounds.max, Arc::new(Isotropic::new(white.clone()))));
+    let grid_cloud = HeterogeneousMedium::from_grid(boundary, density, 1.0, droplets);
+
+    vec![
+        Box::new(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, Arc::new(Lambertian::new(Arc::new(ConstantTexture::new(Vec3::new(0.5, 0.5, 0.5))))))),
+        Box::new(noise_cloud),
+        Box::new(grid_cloud)
+    ]
+}
\ No newline at end of file
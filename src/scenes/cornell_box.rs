@@ -2,14 +2,15 @@ use std::sync::Arc;
 use crate::raytrace::hittable::Hittable;
 use crate::raytrace::Vec3;
 use crate::raytrace::cuboid::Cuboid;
-use crate::raytrace::material::{Lambertian, DiffuseLight};
+use crate::raytrace::material::Lambertian;
 use crate::raytrace::texture::ConstantTexture;
-use crate::raytrace::camera::Camera;
+use crate::raytrace::camera::PerspectiveCamera;
 use crate::raytrace::util::degrees_to_radians;
 use crate::raytrace::modify::{Translate, RotateY};
+use crate::raytrace::light::{Light, QuadLight};
 
-pub fn camera(aspect: f64) -> Camera {
-    Camera::new(
+pub fn camera(aspect: f64) -> PerspectiveCamera {
+    PerspectiveCamera::new(
         Vec3::new(278.0, 278.0, -800.0),
         Vec3::new(278.0, 278.0, 0.0),
         Vec3::new(0.0, 1.0, 0.0),
@@ -20,6 +21,15 @@ pub fn camera(aspect: f64) -> Camera {
     )
 }
 
+fn light() -> QuadLight {
+    QuadLight::new(Vec3::new(213.0, 554.8, 227.0), Vec3::new(130.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 105.0), Vec3::new(15.0, 15.0, 15.0))
+}
+
+/// The lights that are sampled directly, the ceiling light is also part of the objects.
+pub fn lights() -> Vec<Arc<dyn Light>> {
+    vec![Arc::new(light())]
+}
+
 pub fn generate() -> Vec<Box<dyn Hittable>> {
     let mut objects: Vec<Box<dyn Hittable>> = Vec::new();
 
@@ -30,7 +40,7 @@ pub fn generate() -> Vec<Box<dyn Hittable>> {
     objects.push(Box::new(Cuboid::new(Vec3::new(0.0, 0.0, 555.0), Vec3::new(555.0, 555.0, 555.1), white.clone())));
     objects.push(Box::new(Cuboid::new(Vec3::new(0.0, 555.0, 0.0), Vec3::new(555.0, 555.1, 555.0), white.clone())));
     objects.push(Box::new(Cuboid::new(Vec3::new(0.0, -0.1, 0.0), Vec3::new(555.0, 0.0, 555.0), white.clone())));
-    objects.push(Box::new(Cuboid::new(Vec3::new(213.0, 554.8, 227.0), Vec3::new(343.0, 554.9, 332.0), Arc::new(DiffuseLight::new(Arc::new(ConstantTexture::new(Vec3::new(15.0, 15.0, 15.0))))))));
+    objects.push(Box::new(light()));
 
     let cuboid = Arc::new(Cuboid::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(165.0, 165.0, 165.0), white.clone()));
     objects.push(Box::new(Translate::new(Arc::new(RotateY::new(cuboid, degrees_to_radians(-18.0))), Vec3::new(130.0, 0.0, 65.0))));
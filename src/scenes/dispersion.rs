@@ -0,0 +1,42 @@
+use std::sync::Arc;
+use crate::raytrace::hittable::Hittable;
+use crate::raytrace::Vec3;
+use crate::raytrace::sphere::Sphere;
+use crate::raytrace::cuboid::Cuboid;
+use crate::raytrace::material::{Lambertian, Dielectric, DiffuseLight};
+use crate::raytrace::modify::RotateY;
+use crate::raytrace::spectrum::Dispersion;
+use crate::raytrace::texture::ConstantTexture;
+use crate::raytrace::camera::PerspectiveCamera;
+use crate::raytrace::util::degrees_to_radians;
+
+pub fn camera(aspect: f64) -> PerspectiveCamera {
+    PerspectiveCamera::new(
+        Vec3::new(0.0, 2.0, 9.0),
+        Vec3::new(0.0, 1.2, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        degrees_to_radians(35.0),
+        aspect,
+        0.0,
+        10.0
+    )
+}
+
+/// Flint glass in front of bright white bars, which split into colored fringes when rendered spectrally.
+pub fn generate() -> Vec<Box<dyn Hittable>> {
+    let flint = Arc::new(Dielectric::dispersive(Dispersion::sf11()));
+    let mut objects: Vec<Box<dyn Hittable>> = vec![Box::new(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, Arc::new(Lambertian::new(Arc::new(ConstantTexture::new(Vec3::new(0.1, 0.1, 0.1)))))))];
+
+    let light = Arc::new(DiffuseLight::new(Arc::new(ConstantTexture::new(Vec3::new(4.0, 4.0, 4.0)))));
+    for i in 0..7 {
+        let x = -4.5 + 1.5 * i as f64;
+        objects.push(Box::new(Cuboid::new(Vec3::new(x, 0.0, -4.0), Vec3::new(x + 0.3, 4.0, -3.9), light.clone())));
+    }
+
+    // a glass block turned to face the camera with an edge, so it acts like a prism
+    let block = Arc::new(Cuboid::new(Vec3::new(-0.8, 0.0, -0.8), Vec3::new(0.8, 2.4, 0.8), flint.clone()));
+    objects.push(Box::new(RotateY::new(block, degrees_to_radians(45.0))));
+    objects.push(Box::new(Sphere::new(Vec3::new(-2.4, 0.8, 0.5), 0.8, flint.clone())));
+    objects.push(Box::new(Sphere::new(Vec3::new(2.4, 0.8, 0.5), 0.8, flint)));
+    objects
+}
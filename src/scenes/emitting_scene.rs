@@ -4,11 +4,11 @@ use crate::raytrace::Vec3;
 use crate::raytrace::sphere::Sphere;
 use crate::raytrace::material::{Lambertian, DiffuseLight};
 use crate::raytrace::texture::{MarbleTexture, ConstantTexture};
-use crate::raytrace::camera::Camera;
+use crate::raytrace::camera::PerspectiveCamera;
 use crate::raytrace::util::degrees_to_radians;
 
-pub fn camera(width: u32, height: u32) -> Camera {
-    Camera::new(
+pub fn camera(width: u32, height: u32) -> PerspectiveCamera {
+    PerspectiveCamera::new(
         Vec3::new(10.0, 2.0, 2.0),
         Vec3::new(0.0, 0.0, 0.0),
         Vec3::new(0.0, 1.0, 0.0),
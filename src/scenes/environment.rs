@@ -0,0 +1,23 @@
+use std::sync::Arc;
+use std::path::Path;
+use crate::raytrace::hittable::Hittable;
+use crate::raytrace::Vec3;
+use crate::raytrace::sphere::Sphere;
+use crate::raytrace::material::{Metal, Lambertian, Dielectric};
+use crate::raytrace::texture::ConstantTexture;
+use crate::raytrace::environment_map::EnvironmentMap;
+use crate::raytrace::quaternion::Quaternion;
+
+/// Lights the scene with the image in environment.hdr, which can also be a png or exr file.
+pub fn background() -> EnvironmentMap {
+    EnvironmentMap::load(Path::new("environment.hdr"), Quaternion::identity(), 1.0).expect("Failed to load environment.hdr")
+}
+
+pub fn generate() -> Vec<Box<dyn Hittable>> {
+    vec![
+        Box::new(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, Arc::new(Lambertian::new(Arc::new(ConstantTexture::new(Vec3::new(0.5, 0.5, 0.5))))))),
+        Box::new(Sphere::new(Vec3::new(-4.0, 1.0, 0.0), 1.0, Arc::new(Lambertian::new(Arc::new(ConstantTexture::new(Vec3::new(0.8, 0.8, 0.8))))))),
+        Box::new(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 1.0, Arc::new(Dielectric::new(1.5)))),
+        Box::new(Sphere::new(Vec3::new(4.0, 1.0, 0.0), 1.0, Arc::new(Metal::new(Arc::new(ConstantTexture::new(Vec3::new(0.9, 0.9, 0.9))), 0.0))))
+    ]
+}
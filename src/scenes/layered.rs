@@ -0,0 +1,41 @@
+use std::sync::Arc;
+use crate::raytrace::hittable::Hittable;
+use crate::raytrace::Vec3;
+use crate::raytrace::sphere::Sphere;
+use crate::raytrace::material::{Material, Lambertian, Metal, RoughConductor};
+use crate::raytrace::layered::{MixMaterial, Coated};
+use crate::raytrace::texture::{Texture, ConstantTexture, CheckerTexture, MarbleTexture};
+
+fn color(r: f64, g: f64, b: f64) -> Arc<dyn Texture> {
+    Arc::new(ConstantTexture::new(Vec3::new(r, g, b)))
+}
+
+/// Spheres made by blending and coating the basic materials, use with the microfacet camera.
+pub fn generate() -> Vec<Box<dyn Hittable>> {
+    let checker = Arc::new(CheckerTexture::new(color(0.2, 0.2, 0.2), color(0.8, 0.8, 0.8)));
+    let mut objects: Vec<Box<dyn Hittable>> = vec![Box::new(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, Arc::new(Lambertian::new(checker))))];
+
+    // wood grain from two browns blended by a marble pattern, under amber varnish
+    let light_wood: Arc<dyn Material> = Arc::new(Lambertian::new(color(0.6, 0.4, 0.2)));
+    let dark_wood: Arc<dyn Material> = Arc::new(Lambertian::new(color(0.3, 0.15, 0.05)));
+    let wood: Arc<dyn Material> = Arc::new(MixMaterial::new(light_wood, dark_wood, Arc::new(MarbleTexture::new(12.0))));
+    let mut varnished_wood = Coated::new(wood.clone(), 1.5, 0.0);
+    varnished_wood.set_color(Vec3::new(0.95, 0.85, 0.6));
+
+    // patches of dirt over brushed steel
+    let steel: Arc<dyn Material> = Arc::new(RoughConductor::aluminum(0.2));
+    let dirt: Arc<dyn Material> = Arc::new(Lambertian::new(color(0.25, 0.2, 0.15)));
+    let dirty_metal = MixMaterial::new(steel, dirt, Arc::new(MarbleTexture::new(3.0)));
+
+    let red: Arc<dyn Material> = Arc::new(Lambertian::new(color(0.8, 0.1, 0.1)));
+    let glossy_paint = Coated::new(red.clone(), 1.5, 0.15);
+    let half_mirror = MixMaterial::with_amount(red, Arc::new(Metal::new(color(0.9, 0.9, 0.9), 0.0)), 0.5);
+
+    let materials: Vec<Arc<dyn Material>> = vec![wood, Arc::new(varnished_wood), Arc::new(dirty_metal), Arc::new(glossy_paint), Arc::new(half_mirror)];
+    let count = materials.len();
+    for (i, material) in materials.into_iter().enumerate() {
+        let x = 1.8 * (i as f64 - 0.5 * (count - 1) as f64);
+        objects.push(Box::new(Sphere::new(Vec3::new(x, 0.8, 0.0), 0.8, material)));
+    }
+    objects
+}
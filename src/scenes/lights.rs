@@ -0,0 +1,72 @@
+use std::sync::Arc;
+use crate::raytrace::hittable::Hittable;
+use crate::raytrace::Vec3;
+use crate::raytrace::sphere::Sphere;
+use crate::raytrace::material::{Metal, Lambertian, Isotropic};
+use crate::raytrace::constant_medium::GlobalMedium;
+use crate::raytrace::texture::{ConstantTexture, CheckerTexture};
+use crate::raytrace::camera::PerspectiveCamera;
+use crate::raytrace::light::{Light, PointLight, SpotLight, DirectionalLight, SphereLight, QuadLight, MeshLight};
+use crate::raytrace::util::degrees_to_radians;
+
+pub fn camera(aspect: f64) -> PerspectiveCamera {
+    PerspectiveCamera::new(
+        Vec3::new(0.0, 4.0, 12.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        degrees_to_radians(40.0),
+        aspect,
+        0.0,
+        10.0
+    )
+}
+
+fn sphere_light() -> SphereLight {
+    SphereLight::new(Vec3::new(-3.0, 3.0, -1.0), 0.3, Vec3::new(40.0, 20.0, 10.0))
+}
+
+fn quad_light() -> QuadLight {
+    QuadLight::new(Vec3::new(-1.0, 5.0, -1.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 2.0), Vec3::new(4.0, 4.0, 4.0))
+}
+
+fn mesh_light() -> MeshLight {
+    // a small pyramid
+    let vertices = [Vec3::new(2.5, 0.0, 1.5), Vec3::new(3.5, 0.0, 1.5), Vec3::new(3.5, 0.0, 2.5), Vec3::new(2.5, 0.0, 2.5), Vec3::new(3.0, 0.8, 2.0)];
+    MeshLight::from_indices(&vertices, &[[0, 1, 4], [1, 2, 4], [2, 3, 4], [3, 0, 4]], Vec3::new(2.0, 8.0, 4.0))
+}
+
+/// The lights that are sampled directly, the area lights are also part of the objects.
+pub fn lights() -> Vec<Arc<dyn Light>> {
+    let mut lights = foggy_lights();
+    lights.push(Arc::new(DirectionalLight::new(Vec3::new(1.0, -2.0, -1.0), Vec3::new(0.1, 0.1, 0.12))));
+    lights
+}
+
+/// The lights for the scene in fog, which leaves out the directional light since its light would have to come
+/// through infinite fog.
+pub fn foggy_lights() -> Vec<Arc<dyn Light>> {
+    vec![
+        Arc::new(PointLight::new(Vec3::new(3.0, 4.0, 3.0), Vec3::new(10.0, 10.0, 10.0))),
+        Arc::new(SpotLight::new(Vec3::new(-3.0, 6.0, 4.0), Vec3::new(0.5, -1.0, -0.5), Vec3::new(40.0, 40.0, 60.0), degrees_to_radians(20.0), degrees_to_radians(15.0))),
+        Arc::new(sphere_light()),
+        Arc::new(quad_light()),
+        Arc::new(mesh_light())
+    ]
+}
+
+/// Thin fog filling the scene around the objects, which shows the beams of the lights.
+pub fn fog(objects: Arc<dyn Hittable>) -> GlobalMedium {
+    GlobalMedium::new(objects, 0.03, Arc::new(Isotropic::new(Arc::new(ConstantTexture::scalar(1.0)))))
+}
+
+pub fn generate() -> Vec<Box<dyn Hittable>> {
+    let checker = Arc::new(CheckerTexture::new(Arc::new(ConstantTexture::new(Vec3::new(0.2, 0.2, 0.2))), Arc::new(ConstantTexture::new(Vec3::new(0.8, 0.8, 0.8)))));
+    vec![
+        Box::new(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, Arc::new(Lambertian::new(checker)))),
+        Box::new(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 1.0, Arc::new(Lambertian::new(Arc::new(ConstantTexture::new(Vec3::new(0.7, 0.7, 0.7))))))),
+        Box::new(Sphere::new(Vec3::new(-2.5, 0.75, 1.5), 0.75, Arc::new(Metal::new(Arc::new(ConstantTexture::new(Vec3::new(0.9, 0.8, 0.6))), 0.2)))),
+        Box::new(sphere_light()),
+        Box::new(quad_light()),
+        Box::new(mesh_light())
+    ]
+}
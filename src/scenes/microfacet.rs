@@ -0,0 +1,40 @@
+use std::sync::Arc;
+use crate::raytrace::hittable::Hittable;
+use crate::raytrace::Vec3;
+use crate::raytrace::sphere::Sphere;
+use crate::raytrace::material::{Lambertian, RoughConductor, RoughDielectric};
+use crate::raytrace::texture::{ConstantTexture, CheckerTexture};
+use crate::raytrace::camera::PerspectiveCamera;
+use crate::raytrace::util::degrees_to_radians;
+
+pub fn camera(aspect: f64) -> PerspectiveCamera {
+    PerspectiveCamera::new(
+        Vec3::new(0.0, 7.0, 12.0),
+        Vec3::new(0.0, 0.0, -1.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        degrees_to_radians(35.0),
+        aspect,
+        0.0,
+        10.0
+    )
+}
+
+/// Rows of gold, copper, silver and aluminum spheres getting rougher from left to right, in front of rough glass.
+pub fn generate() -> Vec<Box<dyn Hittable>> {
+    let checker = Arc::new(CheckerTexture::new(Arc::new(ConstantTexture::new(Vec3::new(0.2, 0.2, 0.2))), Arc::new(ConstantTexture::new(Vec3::new(0.8, 0.8, 0.8)))));
+    let mut objects: Vec<Box<dyn Hittable>> = vec![Box::new(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, Arc::new(Lambertian::new(checker))))];
+
+    let metals: [fn(f64) -> RoughConductor; 4] = [RoughConductor::gold, RoughConductor::copper, RoughConductor::silver, RoughConductor::aluminum];
+    for (row, metal) in metals.iter().enumerate() {
+        for column in 0..4 {
+            let roughness = column as f64 / 3.0;
+            let center = Vec3::new(-3.0 + 2.0 * column as f64, 0.8, -2.0 * row as f64);
+            objects.push(Box::new(Sphere::new(center, 0.8, Arc::new(metal(roughness)))));
+        }
+    }
+    for column in 0..4 {
+        let roughness = column as f64 / 6.0;
+        objects.push(Box::new(Sphere::new(Vec3::new(-3.0 + 2.0 * column as f64, 0.8, 2.0), 0.8, Arc::new(RoughDielectric::new(1.5, roughness)))));
+    }
+    objects
+}
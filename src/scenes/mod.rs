@@ -5,4 +5,17 @@ pub mod image_texture;
 pub mod box_scene;
 pub mod emitting_scene;
 pub mod cornell_box;
-pub mod cornell_smoke;
\ No newline at end of file
+pub mod cornell_smoke;
+pub mod motion_blur;
+pub mod turntable;
+pub mod environment;
+pub mod sky;
+pub mod lights;
+pub mod microfacet;
+pub mod principled;
+pub mod layered;
+pub mod dispersion;
+pub mod caustics;
+pub mod clouds;
+pub mod subsurface;
+pub mod shapes;
\ No newline at end of file
@@ -0,0 +1,56 @@
+use std::sync::Arc;
+use crate::raytrace::hittable::Hittable;
+use crate::raytrace::Vec3;
+use crate::raytrace::sphere::Sphere;
+use crate::raytrace::material::Lambertian;
+use crate::raytrace::principled::Principled;
+use crate::raytrace::texture::{Texture, ConstantTexture, CheckerTexture, MarbleTexture};
+
+fn color(r: f64, g: f64, b: f64) -> Arc<dyn Texture> {
+    Arc::new(ConstantTexture::new(Vec3::new(r, g, b)))
+}
+
+fn scalar(value: f64) -> Arc<dyn Texture> {
+    Arc::new(ConstantTexture::scalar(value))
+}
+
+/// A row of spheres showing off each feature of the principled material, use with the microfacet camera.
+pub fn generate() -> Vec<Box<dyn Hittable>> {
+    let checker = Arc::new(CheckerTexture::new(color(0.2, 0.2, 0.2), color(0.8, 0.8, 0.8)));
+    let mut objects: Vec<Box<dyn Hittable>> = vec![Box::new(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, Arc::new(Lambertian::new(checker))))];
+
+    let plastic = Principled::new(color(0.8, 0.1, 0.1));
+
+    let mut metal = Principled::new(color(0.95, 0.64, 0.54));
+    metal.set_metallic(scalar(1.0));
+    metal.set_roughness(scalar(0.3));
+
+    // the roughness follows a marble pattern
+    let mut marbled = Principled::new(color(0.9, 0.9, 0.9));
+    marbled.set_metallic(scalar(1.0));
+    marbled.set_roughness(Arc::new(MarbleTexture::new(4.0)));
+
+    let mut velvet = Principled::new(color(0.2, 0.05, 0.3));
+    velvet.set_roughness(scalar(1.0));
+    velvet.set_specular(scalar(0.0));
+    velvet.set_sheen(scalar(1.0), scalar(0.5));
+
+    let mut car_paint = Principled::new(color(0.05, 0.15, 0.6));
+    car_paint.set_roughness(scalar(0.6));
+    car_paint.set_clearcoat(scalar(1.0), scalar(0.02));
+
+    let mut glass = Principled::new(color(0.8, 1.0, 0.9));
+    glass.set_roughness(scalar(0.05));
+    glass.set_transmission(scalar(1.0), 1.5);
+
+    let mut glowing = Principled::new(color(0.1, 0.1, 0.1));
+    glowing.set_emission(color(4.0, 2.0, 0.5));
+
+    let materials = vec![plastic, metal, marbled, velvet, car_paint, glass, glowing];
+    let count = materials.len();
+    for (i, material) in materials.into_iter().enumerate() {
+        let x = 1.8 * (i as f64 - 0.5 * (count - 1) as f64);
+        objects.push(Box::new(Sphere::new(Vec3::new(x, 0.8, 0.0), 0.8, Arc::new(material))));
+    }
+    objects
+}
@@ -0,0 +1,30 @@
+use std::sync::Arc;
+use crate::raytrace::hittable::Hittable;
+use crate::raytrace::Vec3;
+use crate::raytrace::sphere::Sphere;
+use crate::raytrace::cylinder::Cylinder;
+use crate::raytrace::cone::Cone;
+use crate::raytrace::disk::{Disk, Annulus};
+use crate::raytrace::torus::Torus;
+use crate::raytrace::modify::{Translate, RotateX, RotateZ};
+use crate::raytrace::material::{Lambertian, Metal, Dielectric};
+use crate::raytrace::texture::{ConstantTexture, CheckerTexture};
+use crate::raytrace::util::degrees_to_radians;
+
+/// The analytic shapes on a checkered floor, some of them tipped over, for the microfacet scene's camera.
+pub fn generate() -> Vec<Box<dyn Hittable>> {
+    let checker = Arc::new(CheckerTexture::new(Arc::new(ConstantTexture::new(Vec3::new(0.2, 0.2, 0.2))), Arc::new(ConstantTexture::new(Vec3::new(0.8, 0.8, 0.8)))));
+    let mut objects: Vec<Box<dyn Hittable>> = vec![Box::new(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, Arc::new(Lambertian::new(checker))))];
+    let color = |r: f64, g: f64, b: f64| Arc::new(Lambertian::new(Arc::new(ConstantTexture::new(Vec3::new(r, g, b)))));
+
+    objects.push(Box::new(Cylinder::new(Vec3::new(-3.0, 0.0, -2.0), 0.7, 1.6, color(0.8, 0.3, 0.2))));
+    let mut tube = Cylinder::new(Vec3::zero(), 0.6, 2.0, Arc::new(Metal::new(Arc::new(ConstantTexture::new(Vec3::new(0.9, 0.8, 0.6))), 0.1)));
+    tube.set_capped(false);
+    objects.push(Box::new(Translate::new(Arc::new(RotateZ::new(Arc::new(tube), degrees_to_radians(90.0))), Vec3::new(1.0, 0.6, -2.0))));
+    objects.push(Box::new(Cone::new(Vec3::new(3.0, 0.0, -2.0), 0.8, 1.8, color(0.2, 0.5, 0.8))));
+
+    objects.push(Box::new(Translate::new(Arc::new(RotateX::new(Arc::new(Disk::new(Vec3::zero(), 0.8, color(0.9, 0.8, 0.2))), degrees_to_radians(60.0))), Vec3::new(-3.0, 0.9, 1.5))));
+    objects.push(Box::new(Annulus::new(Vec3::new(0.0, 0.05, 1.5), 0.4, 0.9, color(0.3, 0.7, 0.3))));
+    objects.push(Box::new(Translate::new(Arc::new(RotateX::new(Arc::new(Torus::new(Vec3::zero(), 0.7, 0.25, Arc::new(Dielectric::new(1.5)))), degrees_to_radians(70.0))), Vec3::new(3.0, 1.0, 1.5))));
+    objects
+}
\ No newline at end of file
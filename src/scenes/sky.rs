@@ -0,0 +1,9 @@
+use crate::raytrace::Vec3;
+use crate::raytrace::sky::PhysicalSky;
+
+/// A clear afternoon sky, scaled down to suit a camera without exposure settings.
+pub fn background() -> PhysicalSky {
+    let mut sky = PhysicalSky::new(Vec3::new(-0.6, 0.5, 0.4), 2.5, Vec3::new(0.3, 0.3, 0.3));
+    sky.set_intensity(0.02);
+    sky
+}
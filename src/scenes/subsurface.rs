@@ -0,0 +1,23 @@
+use std::sync::Arc;
+use crate::raytrace::hittable::Hittable;
+use crate::raytrace::Vec3;
+use crate::raytrace::sphere::Sphere;
+use crate::raytrace::material::Lambertian;
+use crate::raytrace::subsurface::Subsurface;
+use crate::raytrace::texture::{ConstantTexture, CheckerTexture, MarbleTexture};
+
+/// Skin, wax and veined marble spheres in the sun, for the microfacet scene's camera.
+pub fn generate() -> Vec<Box<dyn Hittable>> {
+    let checker = Arc::new(CheckerTexture::new(Arc::new(ConstantTexture::new(Vec3::new(0.2, 0.2, 0.2))), Arc::new(ConstantTexture::new(Vec3::new(0.8, 0.8, 0.8)))));
+    let grey = Arc::new(Lambertian::new(Arc::new(ConstantTexture::scalar(0.5))));
+    let mut objects: Vec<Box<dyn Hittable>> = vec![Box::new(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, Arc::new(Lambertian::new(checker))))];
+
+    let sphere = |x: f64| -> Arc<dyn Hittable> { Arc::new(Sphere::new(Vec3::new(x, 1.2, 0.0), 1.2, grey.clone())) };
+    let skin = Subsurface::new(sphere(-3.0), Arc::new(ConstantTexture::new(Vec3::new(0.12, 0.05, 0.03))), Arc::new(ConstantTexture::new(Vec3::new(0.98, 0.9, 0.8))));
+    let wax = Subsurface::new(sphere(0.0), Arc::new(ConstantTexture::new(Vec3::new(0.3, 0.2, 0.1))), Arc::new(ConstantTexture::new(Vec3::new(0.99, 0.95, 0.8))));
+    let marble = Subsurface::new(sphere(3.0), Arc::new(ConstantTexture::scalar(0.05)), Arc::new(MarbleTexture::new(3.0)));
+    objects.push(Box::new(skin));
+    objects.push(Box::new(wax));
+    objects.push(Box::new(marble));
+    objects
+}
\ No newline at end of file
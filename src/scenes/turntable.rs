@@ -0,0 +1,41 @@
+use std::sync::Arc;
+use crate::raytrace::hittable::Hittable;
+use crate::raytrace::Vec3;
+use crate::raytrace::sphere::Sphere;
+use crate::raytrace::cuboid::Cuboid;
+use crate::raytrace::material::{Lambertian, Metal};
+use crate::raytrace::texture::{MarbleTexture, ConstantTexture, CheckerTexture};
+use crate::raytrace::quaternion::Quaternion;
+use crate::raytrace::animation::{AnimatedCamera, AnimatedTransform, Track};
+use crate::raytrace::util::degrees_to_radians;
+
+/// A camera that orbits the origin once over duration seconds.
+pub fn animation(aspect: f64, duration: f64) -> AnimatedCamera {
+    const KEYS: u32 = 24;
+    let mut look_from = Track::new();
+    for i in 0..=KEYS {
+        let angle = 2.0 * std::f64::consts::PI * i as f64 / KEYS as f64;
+        look_from.add(duration * i as f64 / KEYS as f64, Vec3::new(13.0 * angle.cos(), 2.5, 13.0 * angle.sin()));
+    }
+    AnimatedCamera::new(look_from, Track::constant(Vec3::new(0.0, 1.0, 0.0)), Track::constant(degrees_to_radians(25.0)), aspect)
+}
+
+pub fn generate() -> Vec<Box<dyn Hittable>> {
+    let mut objects: Vec<Box<dyn Hittable>> = Vec::new();
+    objects.push(Box::new(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, Arc::new(Lambertian::new(Arc::new(CheckerTexture::new(Arc::new(ConstantTexture::new(Vec3::new(0.2, 0.3, 0.1))), Arc::new(ConstantTexture::new(Vec3::new(0.9, 0.9, 0.9))))))))));
+    objects.push(Box::new(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 1.0, Arc::new(Lambertian::new(Arc::new(MarbleTexture::new(3.0)))))));
+
+    // a cube that spins and bobs up and down beside the sphere
+    let cuboid = Arc::new(Cuboid::new(Vec3::new(-0.5, -0.5, -0.5), Vec3::new(0.5, 0.5, 0.5), Arc::new(Metal::new(Arc::new(ConstantTexture::new(Vec3::new(0.8, 0.6, 0.3))), 0.1))));
+    let translation = Track::new()
+        .key(0.0, Vec3::new(3.0, 0.5, 0.0))
+        .key(1.0, Vec3::new(3.0, 1.5, 0.0))
+        .key(2.0, Vec3::new(3.0, 0.5, 0.0));
+    let mut rotation = Track::new();
+    for i in 0..=4 {
+        rotation.add(i as f64 * 0.5, Quaternion::from_rotation_axis(Vec3::new(1.0, 1.0, 0.0), i as f64 * std::f64::consts::FRAC_PI_2));
+    }
+    objects.push(Box::new(AnimatedTransform::rigid(cuboid, translation, rotation)));
+
+    objects
+}